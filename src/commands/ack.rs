@@ -1,41 +1,39 @@
 use crate::data_types::VALQ_TYPE;
-use crate::structs::valq_msg::ValqMsg;
+use crate::structs::q_event::QEvent;
 use crate::structs::valq_type::ValqType;
-use crate::utils::replicate_cmd_check;
-use std::collections::VecDeque;
+use crate::utils::replication::{self, Effect};
+use crate::utils::{claim_check, notify, replica_cmd_check};
 use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
 
 pub(crate) fn ack(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
-    replicate_cmd_check(ctx)?;
+    replica_cmd_check(ctx)?;
     if args.len() != 2 {
         return Err(ValkeyError::Str("specify q name and message ID"));
     }
     let mut args = args.into_iter();
     let key_arg = args.next_arg()?;
     let msg_id_arg = args.next_u64()?;
-    let value = ctx
-        .open_key_writable(&key_arg)
-        .get_value::<ValqType>(&VALQ_TYPE)?;
-    handler(msg_id_arg, value)
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    let result = handler(msg_id_arg, value);
+    notify::notify_events(ctx, &key_arg, &key);
+    replication::replicate_effects(ctx, &key_arg, &key);
+    claim_check::apply_body_ops(ctx, &key)?;
+    result
 }
 
 fn handler(msg_id_arg: u64, value: Option<&mut ValqType>) -> ValkeyResult {
     match value {
         Some(tmp) => {
-            let msgs: &mut VecDeque<ValqMsg> = tmp.msgs_mut();
-            // iterate through messages looking for the message with the given ID
-            for (index, _msg) in msgs
-                .iter_mut()
-                .enumerate()
-                .filter(|(_index, msg)| *msg.id() == msg_id_arg)
-            {
-                msgs.remove(index);
-                return Ok(format!("ack {}", msg_id_arg).into());
-            }
-            Err(ValkeyError::String(format!(
-                "message not found with id {}",
-                msg_id_arg
-            )))
+            // remove the message with the given ID
+            let msg = tmp.take_msg("msgs", msg_id_arg)?;
+            tmp.release_body(&msg);
+            tmp.effects_mut().push(Effect::Remove {
+                list: "msgs".to_string(),
+                id: msg_id_arg,
+            });
+            tmp.add_event(QEvent::Ack);
+            Ok(format!("ack {}", msg_id_arg).into())
         }
         None => Err(ValkeyError::Str("invalid queue")),
     }
@@ -44,6 +42,7 @@ fn handler(msg_id_arg: u64, value: Option<&mut ValqType>) -> ValkeyResult {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structs::valq_msg::ValqMsg;
     use valkey_module::ValkeyValue;
 
     #[test]
@@ -70,11 +69,20 @@ mod tests {
         let test = handler(1, Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("ack 1".to_string()));
         assert_eq!(valq.msgs_mut().len(), 1);
+        assert_eq!(*valq.events(), [QEvent::Ack]);
+        assert_eq!(
+            *valq.effects(),
+            [Effect::Remove {
+                list: "msgs".to_string(),
+                id: 1,
+            }]
+        );
         assert_eq!(valq.dlq_msgs_mut().len(), 0);
 
         // invalid message ID
         let test = handler(3, Some(&mut valq));
         assert!(test.is_err());
+        assert_eq!(valq.effects().len(), 1);
     }
 
     #[test]
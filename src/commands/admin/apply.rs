@@ -0,0 +1,155 @@
+use crate::commands::admin::restore::parse_msg;
+use crate::data_types::VALQ_TYPE;
+use crate::structs::valq_type::ValqType;
+use crate::utils::replication::Effect;
+use crate::utils::{internal_cmd_check, next_u64_option, notify, replicate_cmd_check};
+use valkey_module::logging::log_warning;
+use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
+
+/// Applies a change sent by the primary in place of pop, push, ack, extend, claim,
+/// the retention period GC and release on disconnect, see `replication::Effect`.
+/// Effects are taken as is, so only the primary and the AOF may send it.
+pub(crate) fn apply(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    internal_cmd_check(ctx)?;
+    replicate_cmd_check(ctx)?;
+    if args.len() < 2 {
+        return Err(ValkeyError::Str("specify q name and effect"));
+    }
+    let mut args = args.into_iter();
+    let key_arg = args.next_arg()?;
+    let args: Vec<Vec<u8>> = args.map(|arg| arg.as_slice().to_vec()).collect();
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    let q_name = value.as_deref().map(|tmp| tmp.name().clone());
+    let effect = parse_effect(q_name.as_deref().unwrap_or_default(), args)?;
+    // keyspace events are raised on the primary, replicas only signal the change
+    // a missing queue or message means the replica or the AOF diverged from the primary
+    let result = handler(effect, value)
+        .inspect_err(|err| log_warning(format!("valq apply {}: {}", key_arg, err)))?;
+    notify::signal_modified(ctx, &key_arg);
+    Ok(result)
+}
+
+/// Parses the arguments produced by `Effect::args` for the queue named `q_name`.
+fn parse_effect(q_name: &str, mut args: Vec<Vec<u8>>) -> Result<Effect, ValkeyError> {
+    if args.is_empty() {
+        return Err(ValkeyError::Str("specify effect"));
+    }
+    let kind = String::from_utf8_lossy(&args.remove(0)).to_lowercase();
+    if kind == "push" {
+        let (list, score, msg) = parse_msg(q_name, args)?;
+        return Ok(Effect::Push { list, score, msg });
+    }
+    let mut args = args
+        .into_iter()
+        .map(|arg| String::from_utf8_lossy(&arg).to_string());
+    let effect = match kind.as_str() {
+        "lease" => Effect::Lease {
+            id: next_u64_option(&mut args, "id")?,
+            timeout_at: next_u64_option(&mut args, "timeout")?,
+            delivery_attempts: next_u64_option(&mut args, "delivery attempts")?,
+            consumer: args.next().ok_or(ValkeyError::Str("specify consumer"))?,
+            leased_at: next_u64_option(&mut args, "leased at")?,
+        },
+        "promote" => Effect::Promote(next_u64_option(&mut args, "id")?),
+        "dlq" => Effect::Dlq {
+            list: args.next().unwrap_or_default().to_lowercase(),
+            id: next_u64_option(&mut args, "id")?,
+            timeout_at: next_u64_option(&mut args, "timeout")?,
+        },
+        "remove" => Effect::Remove {
+            list: args.next().unwrap_or_default().to_lowercase(),
+            id: next_u64_option(&mut args, "id")?,
+        },
+        "counters" => Effect::Counters {
+            id_sequence: next_u64_option(&mut args, "id sequence")?,
+            overflow_count: next_u64_option(&mut args, "overflow count")?,
+        },
+        _ => {
+            return Err(ValkeyError::Str(
+                "specify PUSH, LEASE, PROMOTE, DLQ, REMOVE or COUNTERS",
+            ));
+        }
+    };
+    if args.next().is_some() {
+        return Err(ValkeyError::Str("too many arguments"));
+    }
+    Ok(effect)
+}
+
+fn handler(effect: Effect, value: Option<&mut ValqType>) -> ValkeyResult {
+    match value {
+        Some(tmp) => {
+            effect.apply(tmp)?;
+            Ok("OK".into())
+        }
+        None => Err(ValkeyError::Str("q not found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::valq_msg::ValqMsg;
+
+    #[test]
+    fn test_parse_effect() {
+        let mut msg = ValqMsg::new(7, "msg1".to_string(), Some(100), 2);
+        msg.set_consumer(Some("worker1".to_string()));
+        msg.set_leased_at(Some(90));
+        let effects = [
+            Effect::Push {
+                list: "delayed".to_string(),
+                score: 50,
+                msg: msg.clone(),
+            },
+            Effect::lease(&msg),
+            Effect::Promote(7),
+            Effect::Dlq {
+                list: "msgs".to_string(),
+                id: 7,
+                timeout_at: 100,
+            },
+            Effect::Remove {
+                list: "dlq".to_string(),
+                id: 7,
+            },
+            Effect::Counters {
+                id_sequence: 7,
+                overflow_count: 1,
+            },
+        ];
+        for effect in effects {
+            assert_eq!(parse_effect("q", effect.args()).unwrap(), effect);
+        }
+        // pushed messages can only reference their own companion key
+        let mut msg = ValqMsg::new(8, String::new(), None, 0);
+        msg.set_body_key(Some("user:1".to_string()));
+        let effect = Effect::Push {
+            list: "msgs".to_string(),
+            score: 0,
+            msg,
+        };
+        assert!(parse_effect("q", effect.args()).is_err());
+        assert!(parse_effect("q", vec![]).is_err());
+        assert!(parse_effect("q", vec![b"invalid".to_vec()]).is_err());
+        assert!(parse_effect("q", vec![b"PROMOTE".to_vec()]).is_err());
+        assert!(
+            parse_effect("q", vec![b"PROMOTE".to_vec(), b"1".to_vec(), b"2".to_vec()]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_handler() {
+        assert!(handler(Effect::Promote(1), None).is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(handler(Effect::Promote(1), Some(&mut valq)).is_err());
+        let effect = Effect::Push {
+            list: "msgs".to_string(),
+            score: 0,
+            msg: ValqMsg::new(1, "msg1".to_string(), None, 0),
+        };
+        assert!(handler(effect, Some(&mut valq)).is_ok());
+        assert_eq!(valq.msgs().len(), 1);
+    }
+}
@@ -1,9 +1,8 @@
+use crate::commands::admin::q_options::QOptions;
 use crate::data_types::VALQ_TYPE;
 use crate::structs::valq_type::ValqType;
-use crate::utils::replicate_cmd_check;
-use crate::{
-    DELIVERY_ATTEMPTS_DEFAULT, GLOBAL_Q_LIST, RETENTION_PERIOD_DEFAULT, VISIBILITY_TIMEOUT_DEFAULT,
-};
+use crate::utils::{notify, q_registry, replicate_cmd_check};
+use crate::{DELIVERY_ATTEMPTS_DEFAULT, RETENTION_PERIOD_DEFAULT, VISIBILITY_TIMEOUT_DEFAULT};
 use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
 
 pub(crate) fn create(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
@@ -15,9 +14,14 @@ pub(crate) fn create(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
     }
     let mut args = args.into_iter();
     let key_arg = args.next_arg()?;
-    let visibility_timeout_arg = args.next_u64().unwrap_or(VISIBILITY_TIMEOUT_DEFAULT);
-    let max_delivery_attempts_arg = args.next_u64().unwrap_or(DELIVERY_ATTEMPTS_DEFAULT);
-    let retention_period_arg = args.next_u64().unwrap_or(RETENTION_PERIOD_DEFAULT);
+    let options = QOptions::parse(args.map(|arg| arg.to_string()).collect())?;
+    let visibility_timeout_arg = options
+        .visibility_timeout
+        .unwrap_or(VISIBILITY_TIMEOUT_DEFAULT);
+    let max_delivery_attempts_arg = options
+        .max_delivery_attempts
+        .unwrap_or(DELIVERY_ATTEMPTS_DEFAULT);
+    let retention_period_arg = options.retention_period.unwrap_or(RETENTION_PERIOD_DEFAULT);
     let key = ctx.open_key_writable(&key_arg);
     let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
     match value {
@@ -27,15 +31,16 @@ pub(crate) fn create(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
         }
         None => {
             // create a new queue
-            let valq = ValqType::new(
+            let mut valq = ValqType::new(
                 key_arg.to_string().as_str(),
                 Some(visibility_timeout_arg),
                 Some(max_delivery_attempts_arg),
                 Some(retention_period_arg),
             )?;
+            options.apply(&mut valq)?;
             key.set_value(&VALQ_TYPE, valq)?;
-            let mut q_list = GLOBAL_Q_LIST.write()?;
-            q_list.insert(key_arg.to_string());
+            q_registry::register(ctx, key_arg.to_string().as_str())?;
+            notify::signal_modified(ctx, &key_arg);
             Ok(format!("created {}", key_arg).into())
         }
     }
@@ -1,6 +1,7 @@
-use crate::GLOBAL_Q_LIST;
-use crate::utils::replicate_cmd_check;
-use valkey_module::{Context, ValkeyError, ValkeyResult, ValkeyString};
+use crate::data_types::VALQ_TYPE;
+use crate::structs::valq_type::ValqType;
+use crate::utils::{claim_check, notify, replicate_cmd_check};
+use valkey_module::{Context, NotifyEvent, ValkeyError, ValkeyResult, ValkeyString};
 
 pub(crate) fn delete(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
     replicate_cmd_check(ctx)?;
@@ -9,10 +10,17 @@ pub(crate) fn delete(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
     }
     let key_arg = &args[0];
     let key = ctx.open_key_writable(key_arg);
+    // delete companion keys of claim-checked messages
+    if let Ok(Some(valq)) = key.get_value::<ValqType>(&VALQ_TYPE) {
+        valq.release_all_bodies();
+        claim_check::apply_body_ops(ctx, &key)?;
+    }
     match key.delete() {
+        // the queue is unregistered by the `unlink2` callback, the event lets `claim_check` forget
+        // the companion keys deleted above
         Ok(_) => {
-            let mut q_list = GLOBAL_Q_LIST.write()?;
-            q_list.remove(&key_arg.to_string());
+            notify::signal_modified(ctx, key_arg);
+            ctx.notify_keyspace_event(NotifyEvent::GENERIC, "del", key_arg);
             Ok(format!("deleted {}", key_arg).into())
         }
         Err(err) => Err(ValkeyError::String(format!(
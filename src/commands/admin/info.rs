@@ -26,11 +26,68 @@ fn handler(value: Option<&ValqType>) -> ValkeyResult {
                     "max_delivery_attempts".into(),
                     tmp.max_delivery_attempts().to_string().into(),
                 ),
+                ("max_inflight".into(), tmp.max_inflight().to_string().into()),
                 (
                     "retention_period".into(),
                     tmp.retention_period().to_string().into(),
                 ),
+                (
+                    "retry_backoff_base".into(),
+                    tmp.retry_policy().base().to_string().into(),
+                ),
+                (
+                    "retry_backoff_max".into(),
+                    tmp.retry_policy().max().to_string().into(),
+                ),
+                (
+                    "retry_backoff_jitter".into(),
+                    tmp.retry_policy().jitter().to_string().into(),
+                ),
+                ("paused".into(), tmp.paused().to_string().into()),
+                (
+                    "rate_limit".into(),
+                    tmp.rate_limiter().rate().to_string().into(),
+                ),
+                (
+                    "rate_per".into(),
+                    tmp.rate_limiter().per().to_string().into(),
+                ),
+                (
+                    "release_on_disconnect".into(),
+                    tmp.release_on_disconnect().to_string().into(),
+                ),
+                ("max_length".into(), tmp.max_length().to_string().into()),
+                ("max_bytes".into(), tmp.max_bytes().to_string().into()),
+                ("max_msg_size".into(), tmp.max_msg_size().to_string().into()),
+                (
+                    "overflow_policy".into(),
+                    tmp.overflow_policy().as_str().into(),
+                ),
+                (
+                    "overflow_count".into(),
+                    tmp.overflow_count().to_string().into(),
+                ),
+                (
+                    "group_max_inflight".into(),
+                    tmp.group_max_inflight().to_string().into(),
+                ),
                 ("id_sequence".into(), tmp.id_sequence().to_string().into()),
+                (
+                    "claim_check_threshold".into(),
+                    tmp.claim_check_threshold().to_string().into(),
+                ),
+                (
+                    "compress_threshold".into(),
+                    tmp.compress_threshold().to_string().into(),
+                ),
+                // message bodies including companion keys
+                ("bytes".into(), tmp.bytes().to_string().into()),
+                ("msgs_bytes".into(), tmp.msgs_bytes().to_string().into()),
+                ("dlq_bytes".into(), tmp.dlq_bytes().to_string().into()),
+                (
+                    "delayed_bytes".into(),
+                    tmp.delayed_bytes().to_string().into(),
+                ),
                 ("dlq_msgs".into(), tmp.dlq_msgs().len().to_string().into()),
                 // TODO - exclude messages with timeout_at and max_delivery_attempts
                 ("msgs".into(), tmp.msgs().len().to_string().into()),
@@ -64,12 +121,32 @@ mod tests {
         assert_eq!(
             test.unwrap(),
             ValkeyValue::OrderedMap(BTreeMap::from([
+                ("bytes".into(), "0".into()),
+                ("claim_check_threshold".into(), "0".into()),
+                ("compress_threshold".into(), "0".into()),
+                ("delayed_bytes".into(), "0".into()),
                 ("delayed_msgs".into(), "0".into()),
+                ("dlq_bytes".into(), "0".into()),
                 ("dlq_msgs".into(), "0".into()),
+                ("group_max_inflight".into(), "0".into()),
                 ("id_sequence".into(), "0".into()),
+                ("max_bytes".into(), "0".into()),
                 ("max_delivery_attempts".into(), "5".into()),
+                ("max_inflight".into(), "0".into()),
+                ("max_length".into(), "0".into()),
+                ("max_msg_size".into(), "0".into()),
                 ("msgs".into(), "0".into()),
+                ("msgs_bytes".into(), "0".into()),
+                ("overflow_count".into(), "0".into()),
+                ("overflow_policy".into(), "reject".into()),
+                ("paused".into(), "false".into()),
+                ("rate_limit".into(), "0".into()),
+                ("rate_per".into(), "0".into()),
+                ("release_on_disconnect".into(), "false".into()),
                 ("retention_period".into(), "86400".into()),
+                ("retry_backoff_base".into(), "0".into()),
+                ("retry_backoff_jitter".into(), "0".into()),
+                ("retry_backoff_max".into(), "0".into()),
                 ("visibility_timeout".into(), "30".into()),
             ]))
         );
@@ -89,12 +166,32 @@ mod tests {
         assert_eq!(
             test.unwrap(),
             ValkeyValue::OrderedMap(BTreeMap::from([
+                ("bytes".into(), "16".into()),
+                ("claim_check_threshold".into(), "0".into()),
+                ("compress_threshold".into(), "0".into()),
+                ("delayed_bytes".into(), "0".into()),
                 ("delayed_msgs".into(), "0".into()),
+                ("dlq_bytes".into(), "8".into()),
                 ("dlq_msgs".into(), "1".into()),
+                ("group_max_inflight".into(), "0".into()),
                 ("id_sequence".into(), "0".into()),
+                ("max_bytes".into(), "0".into()),
                 ("max_delivery_attempts".into(), "5".into()),
+                ("max_inflight".into(), "0".into()),
+                ("max_length".into(), "0".into()),
+                ("max_msg_size".into(), "0".into()),
                 ("msgs".into(), "2".into()),
+                ("msgs_bytes".into(), "8".into()),
+                ("overflow_count".into(), "0".into()),
+                ("overflow_policy".into(), "reject".into()),
+                ("paused".into(), "false".into()),
+                ("rate_limit".into(), "0".into()),
+                ("rate_per".into(), "0".into()),
+                ("release_on_disconnect".into(), "false".into()),
                 ("retention_period".into(), "86400".into()),
+                ("retry_backoff_base".into(), "0".into()),
+                ("retry_backoff_jitter".into(), "0".into()),
+                ("retry_backoff_max".into(), "0".into()),
                 ("visibility_timeout".into(), "30".into())
             ]))
         );
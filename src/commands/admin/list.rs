@@ -1,9 +1,19 @@
-use crate::GLOBAL_Q_LIST;
-use valkey_module::{ValkeyResult, ValkeyValue};
+use crate::GLOBAL_Q_REGISTRY;
+use crate::utils::q_registry;
+use valkey_module::{Context, ValkeyResult, ValkeyValue};
 
-pub(crate) fn list() -> ValkeyResult {
-    let q_list = GLOBAL_Q_LIST.read()?;
-    let output: Vec<ValkeyValue> = q_list.iter().map(|s| s.into()).collect();
+/// Lists the queues of the selected db, ordered by name.
+pub(crate) fn list(ctx: &Context) -> ValkeyResult {
+    handler(q_registry::selected_db(ctx))
+}
+
+fn handler(db: i32) -> ValkeyResult {
+    let registry = GLOBAL_Q_REGISTRY.read()?;
+    let output: Vec<ValkeyValue> = registry
+        .queues_in_db(db)
+        .into_iter()
+        .map(|s| s.into())
+        .collect();
     Ok(output.into())
 }
 
@@ -13,7 +23,7 @@ mod tests {
 
     #[test]
     fn test_list() {
-        let test = list();
+        let test = handler(-2);
         assert!(test.is_ok());
         match test.unwrap() {
             ValkeyValue::Array(tmp) => {
@@ -1,6 +1,10 @@
+pub(crate) mod apply;
 pub(crate) mod create;
 pub(crate) mod delete;
 pub(crate) mod info;
 pub(crate) mod list;
+pub(crate) mod pause;
 pub(crate) mod purge;
+pub(crate) mod q_options;
+pub(crate) mod restore;
 pub(crate) mod update;
@@ -0,0 +1,59 @@
+use crate::data_types::VALQ_TYPE;
+use crate::structs::valq_type::ValqType;
+use crate::utils::{notify, replicate_cmd_check};
+use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
+
+pub(crate) fn pause(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    set_paused(ctx, args, true)
+}
+
+pub(crate) fn resume(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    set_paused(ctx, args, false)
+}
+
+fn set_paused(ctx: &Context, args: Vec<ValkeyString>, paused: bool) -> ValkeyResult {
+    replicate_cmd_check(ctx)?;
+    if args.len() != 1 {
+        return Err(ValkeyError::Str("specify q name"));
+    }
+    let mut args = args.into_iter();
+    let key_arg = args.next_arg()?;
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    let result = handler(paused, value)?;
+    notify::signal_modified(ctx, &key_arg);
+    Ok(result)
+}
+
+fn handler(paused: bool, value: Option<&mut ValqType>) -> ValkeyResult {
+    match value {
+        Some(tmp) => {
+            tmp.set_paused(paused);
+            Ok("OK".into())
+        }
+        None => Err(ValkeyError::Str("q not found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_nonexistent_queue() {
+        let test = handler(true, None);
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(!*valq.paused());
+        let test = handler(true, Some(&mut valq));
+        assert!(test.is_ok());
+        assert!(*valq.paused());
+        let test = handler(false, Some(&mut valq));
+        assert!(test.is_ok());
+        assert!(!*valq.paused());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::data_types::VALQ_TYPE;
 use crate::structs::q_type::QType;
 use crate::structs::valq_type::ValqType;
-use crate::utils::replicate_cmd_check;
+use crate::utils::{claim_check, notify, replicate_cmd_check};
 use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
 
 pub(crate) fn purge(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
@@ -16,26 +16,32 @@ pub(crate) fn purge(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
     let q_type = QType::from_str(args.next_str().unwrap_or("main"));
     let key = ctx.open_key_writable(&key_arg);
     let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
-    handler(q_type, value)
+    let result = handler(q_type, value)?;
+    notify::signal_modified(ctx, &key_arg);
+    claim_check::apply_body_ops(ctx, &key)?;
+    Ok(result)
 }
 
 fn handler(q_type: QType, value: Option<&mut ValqType>) -> ValkeyResult {
     match value {
         Some(tmp) => match q_type {
             QType::Main => {
-                let msgs_count = tmp.msgs().len();
-                tmp.msgs_mut().clear();
-                Ok(msgs_count.into())
+                let msgs: Vec<_> = tmp.msgs_mut().drain(..).collect();
+                msgs.iter().for_each(|msg| tmp.release_body(msg));
+                tmp.count_queued_bytes();
+                Ok(msgs.len().into())
             }
             QType::Dlq => {
-                let msgs_count = tmp.dlq_msgs().len();
-                tmp.dlq_msgs_mut().clear();
-                Ok(msgs_count.into())
+                let msgs: Vec<_> = tmp.dlq_msgs_mut().drain(..).collect();
+                msgs.iter().for_each(|msg| tmp.release_body(msg));
+                Ok(msgs.len().into())
             }
             QType::Delayed => {
-                let msgs_count = tmp.delayed_msgs().len() as usize;
+                let msgs: Vec<_> = tmp.delayed_msgs().members().keys().cloned().collect();
                 tmp.delayed_msgs_mut().clear();
-                Ok(msgs_count.into())
+                msgs.iter().for_each(|msg| tmp.release_body(msg));
+                tmp.count_queued_bytes();
+                Ok(msgs.len().into())
             }
         },
         None => Err(ValkeyError::Str("q not found")),
@@ -0,0 +1,351 @@
+use crate::structs::overflow_policy::OverflowPolicy;
+use crate::structs::retry_policy::RetryPolicy;
+use crate::structs::valq_type::ValqType;
+use crate::utils::next_u64_option;
+use valkey_module::ValkeyError;
+
+/// Queue settings passed to `valq create` and `valq update`.
+/// Positional visibility timeout, max delivery attempts and retention period
+/// can be followed by keyword settings such as `BACKOFF base max jitter`, `RELEASEONDISCONNECT yes|no`
+/// `MAXINFLIGHT n`, `GROUPMAXINFLIGHT n`, `RATE n PER s`, `MAXLENGTH n`, `MAXBYTES n`
+/// `OVERFLOW reject|dropoldest|dlq`, `MAXMSGSIZE n`, `CLAIMCHECK n` and `COMPRESS n`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct QOptions {
+    pub(crate) visibility_timeout: Option<u64>,
+    pub(crate) max_delivery_attempts: Option<u64>,
+    pub(crate) retention_period: Option<u64>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) release_on_disconnect: Option<bool>,
+    pub(crate) max_inflight: Option<u64>,
+    pub(crate) group_max_inflight: Option<u64>,
+    pub(crate) rate_limit: Option<(u64, u64)>,
+    pub(crate) max_length: Option<u64>,
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) overflow_policy: Option<OverflowPolicy>,
+    pub(crate) max_msg_size: Option<u64>,
+    pub(crate) claim_check_threshold: Option<u64>,
+    pub(crate) compress_threshold: Option<u64>,
+}
+
+impl QOptions {
+    /// Parses the arguments that follow the queue name.
+    ///
+    /// # Errors
+    /// Returns an error if there are more than 3 positional arguments,
+    /// an unknown keyword or a keyword with missing or invalid values.
+    pub(crate) fn parse(args: Vec<String>) -> Result<Self, ValkeyError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter().peekable();
+        // leading numeric arguments are positional
+        let mut positional = vec![];
+        while let Some(value) = args.peek().and_then(|arg| arg.parse::<u64>().ok()) {
+            positional.push(value);
+            args.next();
+        }
+        if positional.len() > 3 {
+            return Err(ValkeyError::Str(
+                "specify visibility timeout, max delivery attempts and retention period only once",
+            ));
+        }
+        options.visibility_timeout = positional.first().copied();
+        options.max_delivery_attempts = positional.get(1).copied();
+        options.retention_period = positional.get(2).copied();
+        // the rest are keyword settings
+        while let Some(keyword) = args.next() {
+            match keyword.to_lowercase().as_str() {
+                "backoff" => {
+                    let base = next_u64_option(&mut args, "backoff base")?;
+                    let max = next_u64_option(&mut args, "backoff max")?;
+                    let jitter = next_u64_option(&mut args, "backoff jitter")?;
+                    options.retry_policy = Some(RetryPolicy::new(base, max, jitter));
+                }
+                "releaseondisconnect" => {
+                    options.release_on_disconnect = Some(next_yes_no(&mut args)?);
+                }
+                "maxinflight" => {
+                    options.max_inflight = Some(next_u64_option(&mut args, "max inflight")?);
+                }
+                "groupmaxinflight" => {
+                    options.group_max_inflight =
+                        Some(next_u64_option(&mut args, "group max inflight")?);
+                }
+                "rate" => {
+                    let rate = next_u64_option(&mut args, "rate")?;
+                    if !args
+                        .next()
+                        .is_some_and(|arg| arg.eq_ignore_ascii_case("per"))
+                    {
+                        return Err(ValkeyError::Str("specify RATE n PER seconds"));
+                    }
+                    let per = next_u64_option(&mut args, "rate period")?;
+                    options.rate_limit = Some((rate, per));
+                }
+                "maxlength" => {
+                    options.max_length = Some(next_u64_option(&mut args, "max length")?);
+                }
+                "maxbytes" => {
+                    options.max_bytes = Some(next_u64_option(&mut args, "max bytes")?);
+                }
+                "overflow" => {
+                    let overflow_policy = args
+                        .next()
+                        .and_then(|arg| OverflowPolicy::parse(&arg))
+                        .ok_or(ValkeyError::Str(
+                        "specify overflow reject, dropoldest or dlq",
+                    ))?;
+                    options.overflow_policy = Some(overflow_policy);
+                }
+                "maxmsgsize" => {
+                    options.max_msg_size = Some(next_u64_option(&mut args, "max message size")?);
+                }
+                "claimcheck" => {
+                    options.claim_check_threshold =
+                        Some(next_u64_option(&mut args, "claim check threshold")?);
+                }
+                "compress" => {
+                    options.compress_threshold =
+                        Some(next_u64_option(&mut args, "compress threshold")?);
+                }
+                _ => {
+                    return Err(ValkeyError::String(format!("unknown option {}", keyword)));
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Applies the positional and keyword settings that are set to the queue.
+    ///
+    /// # Errors
+    /// Returns an error if a setting is out of range, the queue is left unchanged.
+    pub(crate) fn apply(&self, valq: &mut ValqType) -> Result<(), ValkeyError> {
+        // validate all settings on an empty queue first so a failing setting doesn't leave
+        // the ones before it applied
+        let mut staged = ValqType::new(valq.name(), None, None, None)?;
+        self.set_all(&mut staged)?;
+        self.set_all(valq)
+    }
+
+    fn set_all(&self, valq: &mut ValqType) -> Result<(), ValkeyError> {
+        if let Some(visibility_timeout) = self.visibility_timeout {
+            valq.set_visibility_timeout(visibility_timeout)?;
+        }
+        if let Some(max_delivery_attempts) = self.max_delivery_attempts {
+            valq.set_max_delivery_attempts(max_delivery_attempts)?;
+        }
+        if let Some(retention_period) = self.retention_period {
+            valq.set_retention_period(retention_period)?;
+        }
+        if let Some(retry_policy) = &self.retry_policy {
+            valq.set_retry_policy(retry_policy.clone())?;
+        }
+        if let Some(release_on_disconnect) = self.release_on_disconnect {
+            valq.set_release_on_disconnect(release_on_disconnect);
+        }
+        if let Some(max_inflight) = self.max_inflight {
+            valq.set_max_inflight(max_inflight)?;
+        }
+        if let Some(group_max_inflight) = self.group_max_inflight {
+            valq.set_group_max_inflight(group_max_inflight)?;
+        }
+        if let Some((rate, per)) = self.rate_limit {
+            valq.set_rate_limit(rate, per)?;
+        }
+        if let Some(max_length) = self.max_length {
+            valq.set_max_length(max_length);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            valq.set_max_bytes(max_bytes);
+        }
+        if let Some(overflow_policy) = self.overflow_policy {
+            valq.set_overflow_policy(overflow_policy);
+        }
+        if let Some(max_msg_size) = self.max_msg_size {
+            valq.set_max_msg_size(max_msg_size)?;
+        }
+        if let Some(claim_check_threshold) = self.claim_check_threshold {
+            valq.set_claim_check_threshold(claim_check_threshold)?;
+        }
+        if let Some(compress_threshold) = self.compress_threshold {
+            valq.set_compress_threshold(compress_threshold)?;
+        }
+        Ok(())
+    }
+}
+
+fn next_yes_no(args: &mut impl Iterator<Item = String>) -> Result<bool, ValkeyError> {
+    match args.next().map(|arg| arg.to_lowercase()).as_deref() {
+        Some("yes") => Ok(true),
+        Some("no") => Ok(false),
+        _ => Err(ValkeyError::Str("specify yes or no")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_empty() {
+        let test = QOptions::parse(vec![]).unwrap();
+        assert_eq!(test, QOptions::default());
+    }
+
+    #[test]
+    fn parse_positional() {
+        let test = QOptions::parse(to_args(&["1", "2", "300"])).unwrap();
+        assert_eq!(test.visibility_timeout, Some(1));
+        assert_eq!(test.max_delivery_attempts, Some(2));
+        assert_eq!(test.retention_period, Some(300));
+        let test = QOptions::parse(to_args(&["1", "2", "300", "4"]));
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn parse_backoff() {
+        let test = QOptions::parse(to_args(&["10", "BACKOFF", "1", "60", "10"])).unwrap();
+        assert_eq!(test.visibility_timeout, Some(10));
+        assert_eq!(test.max_delivery_attempts, None);
+        assert_eq!(test.retry_policy, Some(RetryPolicy::new(1, 60, 10)));
+        let test = QOptions::parse(to_args(&["backoff", "1", "60"]));
+        assert!(test.is_err());
+        let test = QOptions::parse(to_args(&["backoff", "1", "60", "invalid"]));
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn parse_release_on_disconnect() {
+        let test = QOptions::parse(to_args(&["RELEASEONDISCONNECT", "yes"])).unwrap();
+        assert_eq!(test.release_on_disconnect, Some(true));
+        let test = QOptions::parse(to_args(&["releaseondisconnect", "NO"])).unwrap();
+        assert_eq!(test.release_on_disconnect, Some(false));
+        let test = QOptions::parse(to_args(&["releaseondisconnect", "1"]));
+        assert!(test.is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let options = QOptions::parse(to_args(&["releaseondisconnect", "yes"])).unwrap();
+        assert!(options.apply(&mut valq).is_ok());
+        assert!(*valq.release_on_disconnect());
+    }
+
+    #[test]
+    fn parse_max_inflight() {
+        let test = QOptions::parse(to_args(&["MAXINFLIGHT", "5"])).unwrap();
+        assert_eq!(test.max_inflight, Some(5));
+        let test = QOptions::parse(to_args(&["maxinflight"]));
+        assert!(test.is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(test_apply(&mut valq, &["maxinflight", "5"]).is_ok());
+        assert_eq!(*valq.max_inflight(), 5);
+        assert!(test_apply(&mut valq, &["maxinflight", "100001"]).is_err());
+        assert_eq!(*valq.max_inflight(), 5);
+        assert!(test_apply(&mut valq, &["GROUPMAXINFLIGHT", "2"]).is_ok());
+        assert_eq!(*valq.group_max_inflight(), 2);
+        assert!(test_apply(&mut valq, &["groupmaxinflight", "x"]).is_err());
+    }
+
+    #[test]
+    fn parse_rate_limit() {
+        let test = QOptions::parse(to_args(&["RATE", "10", "PER", "1"])).unwrap();
+        assert_eq!(test.rate_limit, Some((10, 1)));
+        assert!(QOptions::parse(to_args(&["rate", "10", "1"])).is_err());
+        assert!(QOptions::parse(to_args(&["rate", "10", "per"])).is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(test_apply(&mut valq, &["rate", "5", "per", "60"]).is_ok());
+        assert_eq!(*valq.rate_limiter().rate(), 5);
+        assert!(test_apply(&mut valq, &["rate", "5", "per", "0"]).is_err());
+    }
+
+    #[test]
+    fn parse_overflow() {
+        let test = QOptions::parse(to_args(&[
+            "MAXLENGTH",
+            "100",
+            "MAXBYTES",
+            "1024",
+            "OVERFLOW",
+            "dropoldest",
+        ]))
+        .unwrap();
+        assert_eq!(test.max_length, Some(100));
+        assert_eq!(test.max_bytes, Some(1024));
+        assert_eq!(test.overflow_policy, Some(OverflowPolicy::DropOldest));
+        assert!(QOptions::parse(to_args(&["overflow", "invalid"])).is_err());
+        assert!(QOptions::parse(to_args(&["maxlength"])).is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(test_apply(&mut valq, &["maxlength", "10", "overflow", "dlq"]).is_ok());
+        assert_eq!(*valq.max_length(), 10);
+        assert_eq!(*valq.overflow_policy(), OverflowPolicy::Dlq);
+    }
+
+    #[test]
+    fn parse_max_msg_size() {
+        let test = QOptions::parse(to_args(&["MAXMSGSIZE", "1024"])).unwrap();
+        assert_eq!(test.max_msg_size, Some(1024));
+        assert!(QOptions::parse(to_args(&["maxmsgsize", "-1"])).is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(test_apply(&mut valq, &["maxmsgsize", "1024"]).is_ok());
+        assert_eq!(*valq.max_msg_size(), 1024);
+        assert!(test_apply(&mut valq, &["CLAIMCHECK", "4096"]).is_ok());
+        assert_eq!(*valq.claim_check_threshold(), 4096);
+    }
+
+    #[test]
+    fn parse_compress() {
+        let test = QOptions::parse(to_args(&["COMPRESS", "256"])).unwrap();
+        assert_eq!(test.compress_threshold, Some(256));
+        assert!(QOptions::parse(to_args(&["compress"])).is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(test_apply(&mut valq, &["compress", "256"]).is_ok());
+        assert_eq!(*valq.compress_threshold(), 256);
+        assert!(test_apply(&mut valq, &["compress", "536870913"]).is_err());
+    }
+
+    fn test_apply(valq: &mut ValqType, args: &[&str]) -> Result<(), ValkeyError> {
+        QOptions::parse(to_args(args))?.apply(valq)
+    }
+
+    #[test]
+    fn parse_unknown_option() {
+        let test = QOptions::parse(to_args(&["10", "invalid"]));
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn apply_all_or_nothing() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let test = test_apply(
+            &mut valq,
+            &[
+                "10",
+                "2",
+                "300",
+                "maxinflight",
+                "5",
+                "compress",
+                "536870913",
+            ],
+        );
+        assert!(test.is_err());
+        assert_eq!(*valq.visibility_timeout(), 30);
+        assert_eq!(*valq.max_inflight(), 0);
+        assert!(test_apply(&mut valq, &["10", "2", "300", "maxinflight", "5"]).is_ok());
+        assert_eq!(*valq.visibility_timeout(), 10);
+        assert_eq!(*valq.max_delivery_attempts(), 2);
+        assert_eq!(*valq.retention_period(), 300);
+        assert_eq!(*valq.max_inflight(), 5);
+    }
+
+    #[test]
+    fn apply_backoff() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let options = QOptions::parse(to_args(&["backoff", "1", "60", "10"])).unwrap();
+        assert!(options.apply(&mut valq).is_ok());
+        assert_eq!(*valq.retry_policy(), RetryPolicy::new(1, 60, 10));
+        let options = QOptions::parse(to_args(&["backoff", "100", "60", "10"])).unwrap();
+        assert!(options.apply(&mut valq).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use crate::commands::admin::q_options::QOptions;
+use crate::data_types::VALQ_TYPE;
+use crate::structs::body_encoding::BodyEncoding;
+use crate::structs::valq_msg::ValqMsg;
+use crate::structs::valq_type::ValqType;
+use crate::utils::{
+    claim_check, internal_cmd_check, next_u64_option, notify, q_registry, replicate_cmd_check,
+};
+use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
+
+/// Number of arguments that follow `valq restore <key> MSG`.
+const MSG_ARGS_LEN: usize = 14;
+
+/// Rebuilds a queue from the commands emitted by `aof_rewrite`.
+/// `valq restore <key> QUEUE ...` creates the queue and `valq restore <key> MSG ...` adds one message to it.
+/// Messages are taken as is, so only the AOF and the primary may send it.
+pub(crate) fn restore(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    internal_cmd_check(ctx)?;
+    replicate_cmd_check(ctx)?;
+    if args.len() < 2 {
+        return Err(ValkeyError::Str("specify q name and QUEUE or MSG"));
+    }
+    let mut args = args.into_iter();
+    let key_arg = args.next_arg()?;
+    let kind = args.next_string()?;
+    let args: Vec<Vec<u8>> = args.map(|arg| arg.as_slice().to_vec()).collect();
+    let key = ctx.open_key_writable(&key_arg);
+    match kind.to_lowercase().as_str() {
+        "queue" => {
+            if key.get_value::<ValqType>(&VALQ_TYPE)?.is_some() {
+                return Err(ValkeyError::String(format!("{} exists", key_arg)));
+            }
+            let args = args
+                .iter()
+                .map(|arg| String::from_utf8_lossy(arg).to_string())
+                .collect();
+            let valq = restore_q(key_arg.to_string().as_str(), args)?;
+            key.set_value(&VALQ_TYPE, valq)?;
+            q_registry::register(ctx, key_arg.to_string().as_str())?;
+            notify::signal_modified(ctx, &key_arg);
+            Ok("OK".into())
+        }
+        "msg" => {
+            let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+            let q_name = value.as_deref().map(|tmp| tmp.name().clone());
+            let (list, score, msg) = parse_msg(q_name.as_deref().unwrap_or_default(), args)?;
+            let result = handler(&list, score, msg, value)?;
+            notify::signal_modified(ctx, &key_arg);
+            Ok(result)
+        }
+        _ => Err(ValkeyError::Str("specify QUEUE or MSG")),
+    }
+}
+
+/// Creates the queue from visibility timeout, max delivery attempts, retention period,
+/// id sequence, paused and overflow count followed by an optional `NAME` and the keyword settings
+/// of `valq create`. The name defaults to `key_name`, it differs after a `RENAME`.
+fn restore_q(key_name: &str, args: Vec<String>) -> Result<ValqType, ValkeyError> {
+    let mut args = args.into_iter().peekable();
+    let visibility_timeout = next_u64_option(&mut args, "visibility timeout")?;
+    let max_delivery_attempts = next_u64_option(&mut args, "max delivery attempts")?;
+    let retention_period = next_u64_option(&mut args, "retention period")?;
+    let id_sequence = next_u64_option(&mut args, "id sequence")?;
+    let paused = next_u64_option(&mut args, "paused")?;
+    let overflow_count = next_u64_option(&mut args, "overflow count")?;
+    let name = match args.next_if(|arg| arg.eq_ignore_ascii_case("name")) {
+        Some(_) => args.next().ok_or(ValkeyError::Str("specify name"))?,
+        None => key_name.to_string(),
+    };
+    let options = QOptions::parse(args.collect())?;
+    let mut valq = ValqType::new(
+        &name,
+        Some(visibility_timeout),
+        Some(max_delivery_attempts),
+        Some(retention_period),
+    )?;
+    valq.set_id_sequence(id_sequence);
+    valq.set_paused(paused == 1);
+    valq.set_overflow_count(overflow_count);
+    options.apply(&mut valq)?;
+    Ok(valq)
+}
+
+/// Parses the list, delayed score and message fields in the order of `restore_msg_args`.
+/// The body key must be the companion key of the message in the queue named `q_name`,
+/// it's deleted when the message is removed.
+pub(crate) fn parse_msg(
+    q_name: &str,
+    mut args: Vec<Vec<u8>>,
+) -> Result<(String, u64, ValqMsg), ValkeyError> {
+    if args.len() != MSG_ARGS_LEN {
+        return Err(ValkeyError::Str(
+            "specify list, score, id, encoding, body, timeout, attempts, max attempts, visibility timeout, consumer, leased at, group, body key and body key length",
+        ));
+    }
+    // the body is binary safe, the rest are text
+    let body = std::mem::take(&mut args[4]);
+    let mut args = args
+        .into_iter()
+        .map(|arg| String::from_utf8_lossy(&arg).to_string());
+    let list = args.next().unwrap_or_default().to_lowercase();
+    let score = next_u64_option(&mut args, "score")?;
+    let id = next_u64_option(&mut args, "id")?;
+    let encoding = BodyEncoding::from_u64(next_u64_option(&mut args, "encoding")?)
+        .ok_or(ValkeyError::Str("invalid body encoding"))?;
+    args.next();
+    let timeout_at = Some(next_u64_option(&mut args, "timeout")?).filter(|&tmp| tmp > 0);
+    let delivery_attempts = next_u64_option(&mut args, "delivery attempts")?;
+    let mut msg = ValqMsg::new(id, String::new(), timeout_at, delivery_attempts);
+    match encoding {
+        BodyEncoding::Raw => msg.set_body(String::from_utf8_lossy(&body).to_string()),
+        BodyEncoding::Deflate => msg.set_compressed_body(body),
+    };
+    msg.set_encoding(encoding);
+    // optional values are passed as 0 or an empty string when not set
+    msg.set_max_delivery_attempts(
+        Some(next_u64_option(&mut args, "max delivery attempts")?).filter(|&tmp| tmp > 0),
+    );
+    msg.set_visibility_timeout(
+        Some(next_u64_option(&mut args, "visibility timeout")?).filter(|&tmp| tmp > 0),
+    );
+    msg.set_consumer(args.next().filter(|tmp| !tmp.is_empty()));
+    msg.set_leased_at(Some(next_u64_option(&mut args, "leased at")?).filter(|&tmp| tmp > 0));
+    msg.set_group(args.next().filter(|tmp| !tmp.is_empty()));
+    let body_key = args.next().filter(|tmp| !tmp.is_empty());
+    if body_key
+        .as_ref()
+        .is_some_and(|tmp| *tmp != claim_check::body_key(q_name, id))
+    {
+        return Err(ValkeyError::Str("invalid body key"));
+    }
+    msg.set_body_key(body_key);
+    msg.set_body_key_len(next_u64_option(&mut args, "body key length")?);
+    Ok((list, score, msg))
+}
+
+fn handler(list: &str, score: u64, msg: ValqMsg, value: Option<&mut ValqType>) -> ValkeyResult {
+    match value {
+        Some(tmp) => {
+            tmp.add_msg(list, score, msg)?;
+            Ok("OK".into())
+        }
+        None => Err(ValkeyError::Str("q not found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::aof_rewrite::{restore_msg_args, restore_q_args};
+    use crate::structs::overflow_policy::OverflowPolicy;
+    use crate::structs::retry_policy::RetryPolicy;
+
+    fn to_args(args: Vec<Vec<u8>>) -> Vec<String> {
+        // skip QUEUE
+        args.iter()
+            .skip(1)
+            .map(|arg| String::from_utf8_lossy(arg).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_restore_q() {
+        let mut valq = ValqType::new("q", Some(10), Some(3), Some(600)).unwrap();
+        valq.set_id_sequence(42);
+        valq.set_paused(true);
+        valq.set_overflow_count(2);
+        valq.set_overflow_policy(OverflowPolicy::Dlq);
+        let _ = valq.set_retry_policy(RetryPolicy::new(1, 60, 10));
+        let _ = valq.set_rate_limit(5, 60);
+        let _ = valq.set_compress_threshold(256);
+        let test = restore_q("q", to_args(restore_q_args(&valq))).unwrap();
+        assert_eq!(test.name(), "q");
+        assert_eq!(*test.visibility_timeout(), 10);
+        assert_eq!(*test.max_delivery_attempts(), 3);
+        assert_eq!(*test.retention_period(), 600);
+        assert_eq!(*test.id_sequence(), 42);
+        assert!(*test.paused());
+        assert_eq!(*test.overflow_count(), 2);
+        assert_eq!(*test.overflow_policy(), OverflowPolicy::Dlq);
+        assert_eq!(*test.retry_policy(), RetryPolicy::new(1, 60, 10));
+        assert_eq!(*test.rate_limiter().rate(), 5);
+        assert_eq!(*test.compress_threshold(), 256);
+        // default settings round trip too
+        let valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(restore_q("q", to_args(restore_q_args(&valq))).is_ok());
+        assert!(restore_q("q", vec!["10".to_string()]).is_err());
+        // renamed queues keep their name
+        let test = restore_q("q-renamed", to_args(restore_q_args(&valq))).unwrap();
+        assert_eq!(test.name(), "q");
+    }
+
+    #[test]
+    fn test_parse_msg() {
+        let mut msg = ValqMsg::new(7, "{\"status\":\"ok\"}".repeat(10), Some(100), 2);
+        msg.set_consumer(Some("worker1".to_string()));
+        msg.set_leased_at(Some(90));
+        msg.set_group(Some("tenant1".to_string()));
+        msg.compress();
+        let mut args = restore_msg_args("delayed", 50, &msg);
+        args.remove(0);
+        let (list, score, test) = parse_msg("q", args).unwrap();
+        assert_eq!(list, "delayed");
+        assert_eq!(score, 50);
+        assert_eq!(test, msg);
+        assert!(parse_msg("q", vec![b"msgs".to_vec()]).is_err());
+
+        // body keys must be the companion key of the message
+        let mut msg = ValqMsg::new(8, String::new(), None, 0);
+        msg.set_body_key(Some(claim_check::body_key("q", 8)));
+        msg.set_body_key_len(10);
+        let mut args = restore_msg_args("msgs", 0, &msg);
+        args.remove(0);
+        assert_eq!(parse_msg("q", args.clone()).unwrap().2, msg);
+        assert!(parse_msg("other-q", args).is_err());
+        msg.set_body_key(Some("user:1".to_string()));
+        let mut args = restore_msg_args("msgs", 0, &msg);
+        args.remove(0);
+        assert!(parse_msg("q", args).is_err());
+    }
+
+    #[test]
+    fn test_handler() {
+        let msg = ValqMsg::new(1, "msg1".to_string(), None, 0);
+        assert!(handler("msgs", 0, msg.clone(), None).is_err());
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(handler("msgs", 0, msg.clone(), Some(&mut valq)).is_ok());
+        assert!(handler("dlq", 0, msg.clone(), Some(&mut valq)).is_ok());
+        assert!(handler("delayed", 10, msg.clone(), Some(&mut valq)).is_ok());
+        assert!(handler("invalid", 0, msg, Some(&mut valq)).is_err());
+        assert_eq!(valq.msgs().len(), 1);
+        assert_eq!(valq.dlq_msgs().len(), 1);
+        assert_eq!(valq.delayed_msgs().len(), 1);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::data_types::VALQ_TYPE;
+use crate::structs::q_event::QEvent;
+use crate::structs::valq_msg::ValqMsg;
+use crate::structs::valq_type::ValqType;
+use crate::utils;
+use crate::utils::replication::{self, Effect};
+use crate::utils::{
+    claim_check, next_u64_option, notify, release_on_disconnect, replica_cmd_check,
+};
+use std::collections::BTreeMap;
+use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString, ValkeyValue};
+
+static CLAIM_COUNT_DEFAULT: u64 = 100;
+
+pub(crate) fn claim(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    replica_cmd_check(ctx)?;
+    if args.is_empty() {
+        return Err(ValkeyError::Str(
+            "specify q name, CONSUMER name, MINIDLE seconds and optional COUNT",
+        ));
+    }
+    let mut args = args.into_iter();
+    let key_arg = args.next_arg()?;
+    let mut options = ClaimOptions::parse(args.map(|arg| arg.to_string()).collect())?;
+    options.client_id = Some(ctx.get_client_id());
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    let result = handler(options, value);
+    if let Ok(Some(valq)) = key.get_value::<ValqType>(&VALQ_TYPE) {
+        if !valq.effects().is_empty() {
+            release_on_disconnect::track(valq, [ctx.get_client_id()]);
+        }
+    }
+    notify::notify_events(ctx, &key_arg, &key);
+    replication::replicate_effects(ctx, &key_arg, &key);
+    let result = result?;
+    match key.get_value::<ValqType>(&VALQ_TYPE)? {
+        Some(valq) => claim_check::load_bodies(ctx, valq, result),
+        None => Ok(result),
+    }
+}
+
+/// Arguments of `valq claim`.
+#[derive(Debug, PartialEq)]
+struct ClaimOptions {
+    /// Name of the consumer taking over the messages.
+    consumer: String,
+    /// Only claim messages idle for at least this many seconds.
+    min_idle: u64,
+    /// Maximum number of messages to claim.
+    count: u64,
+    /// ID of the client connection taking over the messages.
+    client_id: Option<u64>,
+}
+
+impl ClaimOptions {
+    fn parse(args: Vec<String>) -> Result<Self, ValkeyError> {
+        let mut consumer = None;
+        let mut min_idle = None;
+        let mut count = CLAIM_COUNT_DEFAULT;
+        let mut args = args.into_iter();
+        while let Some(keyword) = args.next() {
+            match keyword.to_lowercase().as_str() {
+                "consumer" => {
+                    consumer = Some(
+                        args.next()
+                            .ok_or(ValkeyError::Str("specify consumer name"))?,
+                    );
+                }
+                "minidle" => {
+                    min_idle = Some(next_u64_option(&mut args, "min idle")?);
+                }
+                "count" => {
+                    count = next_u64_option(&mut args, "count")?;
+                }
+                _ => {
+                    return Err(ValkeyError::String(format!("unknown option {}", keyword)));
+                }
+            }
+        }
+        match (consumer, min_idle) {
+            (Some(consumer), Some(min_idle)) => Ok(Self {
+                consumer,
+                min_idle,
+                count,
+                client_id: None,
+            }),
+            _ => Err(ValkeyError::Str(
+                "specify CONSUMER name and MINIDLE seconds",
+            )),
+        }
+    }
+}
+
+fn handler(options: ClaimOptions, value: Option<&mut ValqType>) -> ValkeyResult {
+    match value {
+        // paused queues don't hand out messages, same as pop
+        Some(tmp) if *tmp.paused() => Ok("".into()),
+        Some(tmp) => {
+            let visibility_timeout = *tmp.visibility_timeout();
+            let max_delivery_attempts = *tmp.max_delivery_attempts();
+            // claimed messages are already leased, so a claim keeps the in-flight counts and is
+            // only refused while the queue or a group is above its limit
+            if tmp.check_above_max_inflight() {
+                return Ok(ValkeyValue::Array(vec![]));
+            }
+            let groups_above_max_inflight = tmp.groups_above_max_inflight();
+            let now = utils::now_as_millis();
+            let mut rate_limiter = tmp.rate_limiter().clone();
+            let mut retry_after = None;
+            let mut claimed: Vec<ValqMsg> = Vec::new();
+            let mut effects = Vec::new();
+            // take over in-flight messages of other consumers that have been idle long enough
+            for msg in tmp
+                .msgs_mut()
+                .iter_mut()
+                .filter(|msg| msg.is_in_flight())
+                .filter(|msg| msg.consumer().as_ref() != Some(&options.consumer))
+                .filter(|msg| msg.idle() >= options.min_idle)
+                // messages out of delivery attempts go to the dlq when their lease expires
+                .filter(|msg| msg.check_max_delivery_attempts(max_delivery_attempts))
+                .filter(|msg| {
+                    msg.group()
+                        .as_ref()
+                        .is_none_or(|group| !groups_above_max_inflight.contains(group))
+                })
+                .take(options.count as usize)
+            {
+                // every claimed message takes a token, same as pop
+                retry_after = rate_limiter.retry_after(now);
+                if retry_after.is_some() {
+                    break;
+                }
+                rate_limiter.acquire(now);
+                let lease_timeout = msg.visibility_timeout().unwrap_or(visibility_timeout);
+                msg.lease(
+                    Some(options.consumer.clone()),
+                    options.client_id,
+                    utils::now_as_seconds().saturating_add(lease_timeout),
+                );
+                msg.set_delivery_attempts(msg.delivery_attempts() + 1);
+                effects.push(Effect::lease(msg));
+                claimed.push(msg.clone());
+            }
+            *tmp.rate_limiter_mut() = rate_limiter;
+            // out of tokens before the first message, tell the consumer when to come back
+            if let (Some(retry_after), true) = (retry_after, claimed.is_empty()) {
+                return Ok(ValkeyValue::OrderedMap(BTreeMap::from([(
+                    "retry_after".into(),
+                    retry_after.to_string().into(),
+                )])));
+            }
+            if !effects.is_empty() {
+                tmp.add_event(QEvent::Claim);
+            }
+            tmp.effects_mut().extend(effects);
+            // leases are kept when a body can't be decoded, the messages go to the dlq eventually
+            claimed
+                .into_iter()
+                .map(ValkeyValue::try_from)
+                .collect::<Result<Vec<_>, _>>()
+                .map(ValkeyValue::from)
+        }
+        None => Err(ValkeyError::Str("invalid queue")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::valq_type::tests::valq_with_msgs;
+
+    fn claim_options(consumer: &str, min_idle: u64, count: u64) -> ClaimOptions {
+        ClaimOptions {
+            consumer: consumer.to_string(),
+            min_idle,
+            count,
+            client_id: None,
+        }
+    }
+
+    fn valq_with_leased_msgs() -> ValqType {
+        valq_with_msgs(&[
+            Some((Some("c1"), None, 60)),
+            Some((Some("c1"), None, 5)),
+            Some((Some("c2"), None, 60)),
+            Some((Some("c1"), None, 60)),
+        ])
+    }
+
+    #[test]
+    fn test_with_nonexistent_queue() {
+        let test = handler(claim_options("c2", 10, 10), None);
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn test_with_empty_queue() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let test = handler(claim_options("c2", 10, 10), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::Array(vec![]));
+    }
+
+    #[test]
+    fn test_claims_idle_msgs_of_other_consumers() {
+        let mut valq = valq_with_leased_msgs();
+        let test = handler(claim_options("c2", 30, 10), Some(&mut valq));
+        match test.unwrap() {
+            ValkeyValue::Array(msgs) => assert_eq!(msgs.len(), 2),
+            _ => panic!("Expected ValkeyValue::Array"),
+        }
+        for id in [1, 4] {
+            let msg = valq.msgs().iter().find(|msg| *msg.id() == id).unwrap();
+            assert_eq!(msg.consumer().as_deref(), Some("c2"));
+            assert_eq!(*msg.delivery_attempts(), 2);
+            assert!(msg.idle() < 30);
+        }
+        // not idle long enough
+        let msg = valq.msgs().iter().find(|msg| *msg.id() == 2).unwrap();
+        assert_eq!(msg.consumer().as_deref(), Some("c1"));
+        assert_eq!(*msg.delivery_attempts(), 1);
+        let test: Vec<Effect> = [0, 3]
+            .iter()
+            .map(|&index| Effect::lease(&valq.msgs()[index]))
+            .collect();
+        assert_eq!(*valq.effects(), test);
+        assert_eq!(*valq.events(), [QEvent::Claim]);
+    }
+
+    #[test]
+    fn test_claims_up_to_count() {
+        let mut valq = valq_with_leased_msgs();
+        let test = handler(claim_options("c3", 30, 1), Some(&mut valq));
+        match test.unwrap() {
+            ValkeyValue::Array(msgs) => assert_eq!(msgs.len(), 1),
+            _ => panic!("Expected ValkeyValue::Array"),
+        }
+        assert_eq!(valq.msgs()[0].consumer().as_deref(), Some("c3"));
+        assert_eq!(valq.msgs()[2].consumer().as_deref(), Some("c2"));
+    }
+
+    #[test]
+    fn test_with_paused_queue() {
+        let mut valq = valq_with_leased_msgs();
+        valq.set_paused(true);
+        let test = handler(claim_options("c2", 30, 10), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        assert_eq!(valq.msgs()[0].consumer().as_deref(), Some("c1"));
+        assert!(valq.effects().is_empty());
+        valq.set_paused(false);
+        let test = handler(claim_options("c2", 30, 10), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::Array(vec![]));
+    }
+
+    #[test]
+    fn test_skips_msgs_out_of_delivery_attempts() {
+        let mut valq = valq_with_leased_msgs();
+        let _ = valq.set_max_delivery_attempts(1);
+        let test = handler(claim_options("c2", 30, 10), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::Array(vec![]));
+    }
+
+    #[test]
+    fn test_with_rate_limit() {
+        let mut valq = valq_with_leased_msgs();
+        let _ = valq.set_rate_limit(1, 60);
+        let test = handler(claim_options("c2", 30, 10), Some(&mut valq));
+        match test.unwrap() {
+            ValkeyValue::Array(msgs) => assert_eq!(msgs.len(), 1),
+            _ => panic!("Expected ValkeyValue::Array"),
+        }
+        let test = handler(claim_options("c3", 30, 10), Some(&mut valq));
+        assert_eq!(
+            test.unwrap(),
+            ValkeyValue::OrderedMap(BTreeMap::from([(
+                "retry_after".into(),
+                "60".to_string().into(),
+            )]))
+        );
+        assert_eq!(valq.effects().len(), 1);
+    }
+
+    #[test]
+    fn test_above_max_inflight() {
+        let mut valq = valq_with_leased_msgs();
+        let _ = valq.set_max_inflight(4);
+        let test = handler(claim_options("c2", 30, 10), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::Array(vec![]));
+        let _ = valq.set_max_inflight(3);
+        let test = handler(claim_options("c3", 30, 10), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::Array(vec![]));
+    }
+
+    #[test]
+    fn test_above_group_max_inflight() {
+        let mut valq = valq_with_leased_msgs();
+        for index in [0, 2, 3] {
+            valq.msgs_mut()[index].set_group(Some("g1".to_string()));
+        }
+        let _ = valq.set_group_max_inflight(2);
+        let test = handler(claim_options("c3", 30, 10), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::Array(vec![]));
+        let _ = valq.set_group_max_inflight(3);
+        let test = handler(claim_options("c3", 30, 10), Some(&mut valq));
+        match test.unwrap() {
+            ValkeyValue::Array(msgs) => assert_eq!(msgs.len(), 3),
+            _ => panic!("Expected ValkeyValue::Array"),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+        let test = ClaimOptions::parse(args(&["CONSUMER", "c1", "MINIDLE", "10"])).unwrap();
+        assert_eq!(test, claim_options("c1", 10, CLAIM_COUNT_DEFAULT));
+        let test = ClaimOptions::parse(args(&["consumer", "c1", "minidle", "10", "count", "5"]));
+        assert_eq!(test.unwrap(), claim_options("c1", 10, 5));
+        assert!(ClaimOptions::parse(args(&["CONSUMER", "c1"])).is_err());
+        assert!(ClaimOptions::parse(args(&["MINIDLE", "10"])).is_err());
+        assert!(ClaimOptions::parse(args(&["CONSUMER", "c1", "MINIDLE", "x"])).is_err());
+        assert!(ClaimOptions::parse(args(&["invalid"])).is_err());
+    }
+}
@@ -1,13 +1,15 @@
 use crate::data_types::VALQ_TYPE;
+use crate::structs::q_event::QEvent;
 use crate::structs::valq_msg::ValqMsg;
 use crate::structs::valq_type::ValqType;
 use crate::utils;
-use crate::utils::replicate_cmd_check;
+use crate::utils::replication::{self, Effect};
+use crate::utils::{notify, replica_cmd_check};
 use std::collections::VecDeque;
 use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
 
 pub(crate) fn extend(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
-    replicate_cmd_check(ctx)?;
+    replica_cmd_check(ctx)?;
     if args.len() != 3 {
         return Err(ValkeyError::Str("specify q name, message ID and seconds"));
     }
@@ -20,10 +22,12 @@ pub(crate) fn extend(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
             "extend timeout must be less than or equal to 43_200 seconds (12 hours)",
         ));
     }
-    let value = ctx
-        .open_key_writable(&key_arg)
-        .get_value::<ValqType>(&VALQ_TYPE)?;
-    handler(msg_id_arg, extend_seconds_arg, value)
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    let result = handler(msg_id_arg, extend_seconds_arg, value);
+    notify::notify_events(ctx, &key_arg, &key);
+    replication::replicate_effects(ctx, &key_arg, &key);
+    result
 }
 
 fn handler(msg_id_arg: u64, extend_seconds_arg: u64, value: Option<&mut ValqType>) -> ValkeyResult {
@@ -31,11 +35,15 @@ fn handler(msg_id_arg: u64, extend_seconds_arg: u64, value: Option<&mut ValqType
         Some(tmp) => {
             let msgs: &mut VecDeque<ValqMsg> = tmp.msgs_mut();
             // iterate through messages looking for the message with the given ID
-            for msg in msgs.iter_mut().filter(|msg| *msg.id() == msg_id_arg) {
-                // update timeout_at
+            if let Some(msg) = msgs.iter_mut().find(|msg| *msg.id() == msg_id_arg) {
+                // update timeout_at and leased_at, the consumer is still working on the message
                 msg.set_timeout_at(Some(
                     utils::now_as_seconds().saturating_add(extend_seconds_arg),
                 ));
+                msg.set_leased_at(Some(utils::now_as_seconds()));
+                let effect = Effect::lease(msg);
+                tmp.effects_mut().push(effect);
+                tmp.add_event(QEvent::Extend);
                 return Ok("extend".into());
             }
             Err(ValkeyError::String(format!(
@@ -79,6 +87,8 @@ mod tests {
         // check if the timeout_at is updated
         let msg = valq.msgs_mut().get(0).unwrap();
         assert!(msg.timeout_at().unwrap() > utils::now_as_seconds());
+        assert_eq!(valq.effects()[0], Effect::lease(&valq.msgs()[0]));
+        assert_eq!(*valq.events(), [QEvent::Extend]);
 
         // invalid message ID
         let test = handler(3, 10, Some(&mut valq));
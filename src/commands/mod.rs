@@ -1,47 +1,138 @@
 mod ack;
 mod admin;
+mod claim;
 mod extend;
+mod pending;
 mod pop;
 mod push;
 
 use admin::info;
 use valkey_module::{Context, NextArg, ValkeyResult, ValkeyString, ValkeyValue};
 
+/// `valq <subcommand>`, kept as an alias of the `valq.<subcommand>` commands.
 pub(crate) fn valq_cmd(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
     if args.len() == 1 {
         return help();
     }
     let mut args = args.into_iter().skip(1);
     let subcmd = args.next_string()?.to_lowercase();
-    let args: Vec<ValkeyString> = args.collect();
-    match subcmd.to_lowercase().as_str() {
+    dispatch(ctx, &subcmd, args.collect())
+}
+
+/// Runs `valq.<subcommand>`, the arguments after the command name are the same as for the alias.
+fn subcommand(ctx: &Context, subcmd: &str, args: Vec<ValkeyString>) -> ValkeyResult {
+    dispatch(ctx, subcmd, args.into_iter().skip(1).collect())
+}
+
+fn dispatch(ctx: &Context, subcmd: &str, args: Vec<ValkeyString>) -> ValkeyResult {
+    match subcmd {
         "create" => admin::create::create(ctx, args),
         "delete" => admin::delete::delete(ctx, args),
         "update" => admin::update::update(ctx, args),
-        "list" => admin::list::list(),
+        "list" => admin::list::list(ctx),
         "info" => info::info(ctx, args),
         "purge" => admin::purge::purge(ctx, args),
         "push" => push::push(ctx, args),
         "pop" => pop::pop(ctx, args),
         "ack" => ack::ack(ctx, args),
         "extend" => extend::extend(ctx, args),
+        "pending" => pending::pending(ctx, args),
+        "claim" => claim::claim(ctx, args),
+        "pause" => admin::pause::pause(ctx, args),
+        "resume" => admin::pause::resume(ctx, args),
+        "restore" => admin::restore::restore(ctx, args),
+        "apply" => admin::apply::apply(ctx, args),
         _ => help(),
     }
 }
 
+pub(crate) fn valq_create(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "create", args)
+}
+
+pub(crate) fn valq_delete(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "delete", args)
+}
+
+pub(crate) fn valq_update(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "update", args)
+}
+
+pub(crate) fn valq_list(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "list", args)
+}
+
+pub(crate) fn valq_info(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "info", args)
+}
+
+pub(crate) fn valq_purge(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "purge", args)
+}
+
+pub(crate) fn valq_pause(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "pause", args)
+}
+
+pub(crate) fn valq_resume(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "resume", args)
+}
+
+pub(crate) fn valq_push(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "push", args)
+}
+
+pub(crate) fn valq_pop(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "pop", args)
+}
+
+pub(crate) fn valq_ack(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "ack", args)
+}
+
+pub(crate) fn valq_extend(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "extend", args)
+}
+
+pub(crate) fn valq_pending(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "pending", args)
+}
+
+pub(crate) fn valq_claim(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "claim", args)
+}
+
+pub(crate) fn valq_restore(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "restore", args)
+}
+
+pub(crate) fn valq_apply(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "apply", args)
+}
+
+pub(crate) fn valq_help(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    subcommand(ctx, "help", args)
+}
+
 fn help() -> ValkeyResult {
     let output: Vec<ValkeyValue> = vec![
-        "valq - top level command".into(),
+        "valq - top level command, each subcommand is also registered as valq.<subcommand>".into(),
         "valq create - create new q".into(),
         "valq delete - delete q".into(),
         "valq update - update q".into(),
-        "valq list - list all queues".into(),
+        "valq list - list all queues in the selected db".into(),
         "valq info - info about q".into(),
         "valq purge - purge messages in q, dlq or delayed q".into(),
-        "valq push - push message to q with optional delay".into(),
-        "valq pop - get message from q".into(),
+        "valq pause - stop handing out messages from q, pushes are still accepted".into(),
+        "valq resume - resume handing out messages from a paused q".into(),
+        "valq push - push message to q with optional delay, max attempts, timeout and group".into(),
+        "valq pop - get message from q with optional timeout and consumer name".into(),
         "valq ack - ack message completion".into(),
         "valq extend - extend message to have more time to complete it".into(),
+        "valq pending - list in-flight messages with optional consumer and idle filters".into(),
+        "valq claim - take over idle in-flight messages from other consumers".into(),
+        "valq restore - rebuild q from AOF, emitted by AOF rewrite, rejected from clients".into(),
+        "valq apply - apply a change to q, replicated by the primary, rejected from clients".into(),
         "valq help - display this message".into(),
     ];
     Ok(output.into())
@@ -0,0 +1,178 @@
+use crate::data_types::VALQ_TYPE;
+use crate::structs::valq_msg::ValqMsg;
+use crate::structs::valq_type::ValqType;
+use crate::utils::next_u64_option;
+use std::collections::BTreeMap;
+use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString, ValkeyValue};
+
+pub(crate) fn pending(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
+    if args.is_empty() {
+        return Err(ValkeyError::Str(
+            "specify q name and optional CONSUMER name, IDLE seconds",
+        ));
+    }
+    let mut args = args.into_iter();
+    let key_arg = args.next_arg()?;
+    let options = PendingOptions::parse(args.map(|arg| arg.to_string()).collect())?;
+    let key = ctx.open_key(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    handler(options, value)
+}
+
+/// Optional filters of `valq pending`.
+#[derive(Debug, Default, PartialEq)]
+struct PendingOptions {
+    /// Only list messages leased by this consumer.
+    consumer: Option<String>,
+    /// Only list messages idle for at least this many seconds.
+    min_idle: u64,
+}
+
+impl PendingOptions {
+    fn parse(args: Vec<String>) -> Result<Self, ValkeyError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(keyword) = args.next() {
+            match keyword.to_lowercase().as_str() {
+                "consumer" => {
+                    let consumer = args
+                        .next()
+                        .ok_or(ValkeyError::Str("specify consumer name"))?;
+                    options.consumer = Some(consumer);
+                }
+                "idle" => {
+                    options.min_idle = next_u64_option(&mut args, "idle")?;
+                }
+                _ => {
+                    return Err(ValkeyError::String(format!("unknown option {}", keyword)));
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn handler(options: PendingOptions, value: Option<&ValqType>) -> ValkeyResult {
+    match value {
+        Some(tmp) => {
+            let output: Vec<ValkeyValue> = tmp
+                .msgs()
+                .iter()
+                .filter(|msg| msg.is_in_flight())
+                .filter(|msg| match &options.consumer {
+                    Some(consumer) => msg.consumer().as_ref() == Some(consumer),
+                    None => true,
+                })
+                .filter(|msg| msg.idle() >= options.min_idle)
+                .map(pending_msg)
+                .collect();
+            Ok(output.into())
+        }
+        None => Err(ValkeyError::Str("q not found")),
+    }
+}
+
+fn pending_msg(msg: &ValqMsg) -> ValkeyValue {
+    ValkeyValue::OrderedMap(BTreeMap::from([
+        ("id".into(), msg.id().to_string().into()),
+        (
+            "consumer".into(),
+            msg.consumer().clone().unwrap_or_default().into(),
+        ),
+        (
+            "timeout_at".into(),
+            msg.timeout_at().unwrap_or(0).to_string().into(),
+        ),
+        (
+            "delivery_attempts".into(),
+            msg.delivery_attempts().to_string().into(),
+        ),
+        ("idle".into(), msg.idle().to_string().into()),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::valq_type::tests::valq_with_msgs;
+    use crate::utils;
+    use valkey_module::redisvalue::ValkeyValueKey;
+
+    fn valq_with_leased_msgs() -> ValqType {
+        let mut valq = valq_with_msgs(&[
+            Some((Some("c1"), None, 20)),
+            Some((Some("c2"), None, 0)),
+            Some((Some("c1"), None, 0)),
+            None,
+        ]);
+        // expired lease is not in flight
+        valq.msgs_mut()[2].set_timeout_at(Some(utils::now_as_seconds() - 1));
+        valq
+    }
+
+    fn pending_ids(result: ValkeyResult) -> Vec<ValkeyValue> {
+        match result.unwrap() {
+            ValkeyValue::Array(msgs) => msgs
+                .into_iter()
+                .map(|msg| match msg {
+                    ValkeyValue::OrderedMap(map) => map
+                        .get(&ValkeyValueKey::String("id".to_string()))
+                        .unwrap()
+                        .clone(),
+                    _ => panic!("Expected ValkeyValue::OrderedMap"),
+                })
+                .collect(),
+            _ => panic!("Expected ValkeyValue::Array"),
+        }
+    }
+
+    #[test]
+    fn test_with_nonexistent_queue() {
+        let test = handler(PendingOptions::default(), None);
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn test_with_empty_queue() {
+        let valq = ValqType::new("q", None, None, None).unwrap();
+        let test = handler(PendingOptions::default(), Some(&valq));
+        assert_eq!(test.unwrap(), ValkeyValue::Array(vec![]));
+    }
+
+    #[test]
+    fn test_with_leased_msgs() {
+        let valq = valq_with_leased_msgs();
+        let test = handler(PendingOptions::default(), Some(&valq));
+        assert_eq!(
+            pending_ids(test),
+            vec![
+                ValkeyValue::BulkString("1".to_string()),
+                ValkeyValue::BulkString("2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_consumer_and_idle_filters() {
+        let valq = valq_with_leased_msgs();
+        let options = PendingOptions::parse(vec!["CONSUMER".to_string(), "c2".to_string()]);
+        let test = handler(options.unwrap(), Some(&valq));
+        assert_eq!(
+            pending_ids(test),
+            vec![ValkeyValue::BulkString("2".to_string())]
+        );
+        let options = PendingOptions::parse(vec!["IDLE".to_string(), "10".to_string()]);
+        let test = handler(options.unwrap(), Some(&valq));
+        assert_eq!(
+            pending_ids(test),
+            vec![ValkeyValue::BulkString("1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert!(PendingOptions::parse(vec!["CONSUMER".to_string()]).is_err());
+        assert!(PendingOptions::parse(vec!["IDLE".to_string(), "x".to_string()]).is_err());
+        assert!(PendingOptions::parse(vec!["invalid".to_string()]).is_err());
+    }
+}
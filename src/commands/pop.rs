@@ -1,33 +1,102 @@
+use crate::VISIBILITY_TIMEOUT_MAX;
 use crate::data_types::VALQ_TYPE;
+use crate::structs::q_event::QEvent;
 use crate::structs::valq_msg::ValqMsg;
 use crate::structs::valq_type::ValqType;
 use crate::utils;
-use crate::utils::replicate_cmd_check;
-use std::collections::VecDeque;
-use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
+use crate::utils::replication::{self, Effect};
+use crate::utils::{
+    claim_check, next_u64_option, notify, release_on_disconnect, replica_cmd_check,
+};
+use std::collections::{BTreeMap, VecDeque};
+use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString, ValkeyValue};
 
 pub(crate) fn pop(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
-    replicate_cmd_check(ctx)?;
+    replica_cmd_check(ctx)?;
     if args.is_empty() {
         return Err(ValkeyError::Str("specify q name"));
     }
     let mut args = args.into_iter();
     let key_arg = args.next_arg()?;
-    let value = ctx
-        .open_key_writable(&key_arg)
-        .get_value::<ValqType>(&VALQ_TYPE)?;
-    handler(value)
+    let mut options = PopOptions::parse(args.map(|arg| arg.to_string()).collect())?;
+    if options.consumer.is_none() {
+        // default to the client name or id
+        options.consumer = Some(
+            ctx.get_client_name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|_| ctx.get_client_id().to_string()),
+        );
+    }
+    options.client_id = Some(ctx.get_client_id());
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    let result = handler(options, value);
+    if let Ok(Some(valq)) = key.get_value::<ValqType>(&VALQ_TYPE) {
+        if !valq.effects().is_empty() {
+            release_on_disconnect::track(valq, [ctx.get_client_id()]);
+        }
+    }
+    notify::notify_events(ctx, &key_arg, &key);
+    replication::replicate_effects(ctx, &key_arg, &key);
+    let result = result?;
+    match key.get_value::<ValqType>(&VALQ_TYPE)? {
+        Some(valq) => claim_check::load_bodies(ctx, valq, result),
+        None => Ok(result),
+    }
+}
+
+/// Optional keyword arguments of `valq pop`.
+#[derive(Debug, Default, PartialEq)]
+struct PopOptions {
+    /// Overrides the visibility timeout for this lease only.
+    visibility_timeout: Option<u64>,
+    /// Name of the consumer taking the lease.
+    consumer: Option<String>,
+    /// ID of the client connection taking the lease.
+    client_id: Option<u64>,
 }
 
-fn handler(value: Option<&mut ValqType>) -> ValkeyResult {
+impl PopOptions {
+    fn parse(args: Vec<String>) -> Result<Self, ValkeyError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(keyword) = args.next() {
+            match keyword.to_lowercase().as_str() {
+                "timeout" => {
+                    let visibility_timeout = next_u64_option(&mut args, "timeout")?;
+                    if !(1..=VISIBILITY_TIMEOUT_MAX).contains(&visibility_timeout) {
+                        return Err(ValkeyError::Str(
+                            "timeout must be between 1 and 43_200 seconds (12 hours)",
+                        ));
+                    }
+                    options.visibility_timeout = Some(visibility_timeout);
+                }
+                "consumer" => {
+                    let consumer = args
+                        .next()
+                        .ok_or(ValkeyError::Str("specify consumer name"))?;
+                    options.consumer = Some(consumer);
+                }
+                _ => {
+                    return Err(ValkeyError::String(format!("unknown option {}", keyword)));
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn handler(options: PopOptions, value: Option<&mut ValqType>) -> ValkeyResult {
     match value {
+        // paused queues also keep delayed messages and messages out of attempts where they are
+        Some(tmp) if *tmp.paused() => Ok("".into()),
         Some(tmp) => {
             move_delayed_msgs_to_main_q(tmp);
-            let max_delivery_attempts_msgs = match process_main_q(tmp) {
+            let dlq_msgs = match process_main_q(tmp, &options) {
                 Ok(value) => value,
                 Err(value) => return value,
             };
-            move_max_delivery_msgs_to_dlq(tmp, &max_delivery_attempts_msgs);
+            move_msgs_to_dlq(tmp, &dlq_msgs);
             // all messages have timeout_at, return nothing
             Ok("".into())
         }
@@ -47,62 +116,113 @@ fn move_delayed_msgs_to_main_q(valq: &mut ValqType) {
         valq.delayed_msgs_mut().remove(&msg);
         // push to the front of msgs to process delayed messages first
         valq.msgs_mut().push_front(msg.clone());
+        valq.effects_mut().push(Effect::Promote(*msg.id()));
     }
 }
 
-fn process_main_q(tmp: &mut ValqType) -> Result<Vec<(usize, ValqMsg)>, ValkeyResult> {
+fn process_main_q(tmp: &mut ValqType, options: &PopOptions) -> Result<Vec<u64>, ValkeyResult> {
     let visibility_timeout = *tmp.visibility_timeout();
     let max_delivery_attempts = *tmp.max_delivery_attempts();
+    let retry_policy = tmp.retry_policy().clone();
+    let max_inflight_reached = tmp.check_max_inflight();
+    let groups_at_max_inflight = tmp.groups_at_max_inflight();
+    let now = utils::now_as_millis();
+    let retry_after = tmp.rate_limiter().retry_after(now);
     let msgs: &mut VecDeque<ValqMsg> = tmp.msgs_mut();
-    let mut max_delivery_attempts_msgs = Vec::new();
+    let mut dlq_msgs = Vec::new();
+    let mut leased_msg = None;
     // iterate through messages and find the first one that is visible
-    for (index, msg) in msgs
-        .iter_mut()
-        .enumerate()
-        .filter(|(_index, msg)| msg.check_timeout_at())
-    {
+    for msg in msgs.iter_mut().filter(|msg| msg.check_timeout_at()) {
         if !msg.check_max_delivery_attempts(max_delivery_attempts) {
-            max_delivery_attempts_msgs.push((index, msg.clone()));
+            dlq_msgs.push(*msg.id());
             continue; // skip this message
         }
-        // set timeout_at
-        msg.set_timeout_at(Some(
-            utils::now_as_seconds().saturating_add(visibility_timeout),
-        ));
+        // wait out the backoff before redelivering an expired message
+        if !msg.check_redelivery_delay(retry_policy.delay(*msg.delivery_attempts(), *msg.id())) {
+            continue;
+        }
+        // keep scanning for the dlq but do not lease more than max_inflight messages
+        if max_inflight_reached {
+            continue;
+        }
+        // serve the next eligible message when its group is at the per-group limit
+        if msg
+            .group()
+            .as_ref()
+            .is_some_and(|group| groups_at_max_inflight.contains(group))
+        {
+            continue;
+        }
+        // out of tokens, tell the consumer when to come back
+        if let Some(retry_after) = retry_after {
+            return Err(Ok(ValkeyValue::OrderedMap(BTreeMap::from([(
+                "retry_after".into(),
+                retry_after.to_string().into(),
+            )]))));
+        }
+        // a body that can't be decoded goes to the dlq without taking a lease, attempt or token
+        let body = match msg.decoded_body() {
+            Ok(body) => body,
+            Err(_) => {
+                dlq_msgs.push(*msg.id());
+                continue;
+            }
+        };
+        // set timeout_at, pop and message overrides take precedence over the queue setting
+        let lease_timeout = options
+            .visibility_timeout
+            .or(*msg.visibility_timeout())
+            .unwrap_or(visibility_timeout);
+        msg.lease(
+            options.consumer.clone(),
+            options.client_id,
+            utils::now_as_seconds().saturating_add(lease_timeout),
+        );
         // increment delivery_attempts
         msg.set_delivery_attempts(msg.delivery_attempts() + 1);
-        // return the message
-        return Err(Ok(msg.clone().into()));
+        leased_msg = Some((msg.clone(), body));
+        break;
     }
-    Ok(max_delivery_attempts_msgs)
+    // return the message
+    if let Some((msg, body)) = leased_msg {
+        move_msgs_to_dlq(tmp, &dlq_msgs);
+        tmp.rate_limiter_mut().acquire(now);
+        tmp.effects_mut().push(Effect::lease(&msg));
+        tmp.add_event(QEvent::Pop);
+        if *msg.delivery_attempts() > 1 {
+            tmp.add_event(QEvent::Redelivered);
+        }
+        return Err(Ok(ValkeyValue::OrderedMap(BTreeMap::from([
+            ("id".into(), msg.id().to_string().into()),
+            ("body".into(), body.into()),
+        ]))));
+    }
+    Ok(dlq_msgs)
 }
 
-fn move_max_delivery_msgs_to_dlq(
-    valq: &mut ValqType,
-    max_delivery_attempts_msgs: &Vec<(usize, ValqMsg)>,
-) {
-    // remove from msgs and add to dlq_msgs
-    for (index, msg) in max_delivery_attempts_msgs {
-        valq.msgs_mut().remove(*index);
-        valq.dlq_msgs_mut().push_back(msg.clone());
+fn move_msgs_to_dlq(valq: &mut ValqType, dlq_msgs: &Vec<u64>) {
+    // add to dlq_msgs in queue order
+    for id in dlq_msgs {
+        let _ = valq.move_to_dlq("msgs", *id);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use valkey_module::ValkeyValue;
+    use crate::structs::body_encoding::BodyEncoding;
+    use crate::structs::retry_policy::RetryPolicy;
 
     #[test]
     fn test_with_nonexistent_queue() {
-        let test = handler(None);
+        let test = handler(PopOptions::default(), None);
         assert!(test.is_err());
     }
 
     #[test]
     fn test_with_empty_queue_returns_nothing() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
-        let test = handler(Some(&mut valq));
+        let test = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
         assert!(valq.msgs().is_empty());
         assert!(valq.dlq_msgs().is_empty());
@@ -113,7 +233,7 @@ mod tests {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
         let msg = ValqMsg::new(1, "msg".to_string(), Some(utils::now_as_seconds() + 10), 0);
         valq.msgs_mut().push_back(msg);
-        let test = handler(Some(&mut valq));
+        let test = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
     }
 
@@ -122,9 +242,10 @@ mod tests {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
         let msg = ValqMsg::new(1, "msg".to_string(), Some(utils::now_as_seconds()), 5);
         valq.msgs_mut().push_back(msg);
-        let test = handler(Some(&mut valq));
+        let test = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
         assert_eq!(valq.dlq_msgs().len(), 1);
+        assert_eq!(*valq.events(), [QEvent::Dlq]);
     }
 
     #[test]
@@ -132,9 +253,212 @@ mod tests {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
         let msg = ValqMsg::new(1, "msg".to_string(), Some(utils::now_as_seconds()), 0);
         valq.msgs_mut().push_back(msg);
-        let test = handler(Some(&mut valq));
+        let test = handler(PopOptions::default(), Some(&mut valq));
         assert!(test.is_ok());
         assert!(valq.dlq_msgs().is_empty());
+        assert_eq!(*valq.events(), [QEvent::Pop]);
+    }
+
+    #[test]
+    fn test_with_timeout_overrides() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let mut msg1 = ValqMsg::new(1, "msg1".to_string(), None, 0);
+        msg1.set_visibility_timeout(Some(600));
+        valq.msgs_mut().push_back(msg1);
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        // message override
+        let _ = handler(PopOptions::default(), Some(&mut valq));
+        assert!(valq.msgs()[0].timeout_at().unwrap() >= utils::now_as_seconds() + 600);
+        // pop override takes precedence
+        let options = PopOptions {
+            visibility_timeout: Some(3_600),
+            ..Default::default()
+        };
+        let _ = handler(options, Some(&mut valq));
+        assert!(valq.msgs()[1].timeout_at().unwrap() >= utils::now_as_seconds() + 3_600);
+    }
+
+    #[test]
+    fn test_with_consumer() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(1, "msg1".to_string(), None, 0));
+        let options = PopOptions {
+            consumer: Some("c1".to_string()),
+            ..Default::default()
+        };
+        let _ = handler(options, Some(&mut valq));
+        assert_eq!(valq.msgs()[0].consumer().as_deref(), Some("c1"));
+        assert!(valq.msgs()[0].leased_at().is_some());
+        assert!(valq.msgs()[0].is_in_flight());
+    }
+
+    #[test]
+    fn test_with_max_delivery_attempts_override() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let mut msg = ValqMsg::new(1, "msg".to_string(), Some(utils::now_as_seconds()), 1);
+        msg.set_max_delivery_attempts(Some(1));
+        valq.msgs_mut().push_back(msg);
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        assert_eq!(valq.dlq_msgs().len(), 1);
+    }
+
+    #[test]
+    fn test_with_max_inflight() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_max_inflight(1);
+        for id in 1..=2 {
+            valq.msgs_mut()
+                .push_back(ValqMsg::new(id, format!("msg{}", id), None, 0));
+        }
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        // limit reached although msg2 is visible
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        // ack frees up a slot
+        valq.msgs_mut().pop_front();
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        assert_eq!(*valq.msgs()[0].id(), 2);
+    }
+
+    #[test]
+    fn test_with_group_max_inflight() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_group_max_inflight(1);
+        for (id, group) in [(1, Some("t1")), (2, Some("t1")), (3, Some("t2")), (4, None)] {
+            let mut msg = ValqMsg::new(id, format!("msg{}", id), None, 0);
+            msg.set_group(group.map(|group| group.to_string()));
+            valq.msgs_mut().push_back(msg);
+        }
+        // msg2 is skipped while msg1 of the same group is leased
+        let mut popped = vec![];
+        for _ in 0..4 {
+            let _ = handler(PopOptions::default(), Some(&mut valq));
+        }
+        for msg in valq.msgs() {
+            if msg.is_in_flight() {
+                popped.push(*msg.id());
+            }
+        }
+        assert_eq!(popped, vec![1, 3, 4]);
+        // ack frees up a slot for the group
+        valq.msgs_mut().pop_front();
+        let _ = handler(PopOptions::default(), Some(&mut valq));
+        assert!(valq.msgs()[0].is_in_flight());
+        assert_eq!(*valq.msgs()[0].id(), 2);
+    }
+
+    #[test]
+    fn test_with_corrupt_body() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_rate_limit(1, 60);
+        let mut msg = ValqMsg::new(1, String::new(), None, 0);
+        msg.set_encoding(BodyEncoding::Deflate);
+        msg.set_compressed_body(vec![0xff; 8]);
+        valq.msgs_mut().push_back(msg);
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_eq!(
+            test.unwrap(),
+            ValkeyValue::OrderedMap(BTreeMap::from([
+                ("id".into(), "2".into()),
+                ("body".into(), "msg2".into()),
+            ]))
+        );
+        assert_eq!(*valq.dlq_msgs()[0].id(), 1);
+        assert_eq!(*valq.dlq_msgs()[0].delivery_attempts(), 0);
+        assert_eq!(
+            valq.effects()[0],
+            Effect::Dlq {
+                list: "msgs".to_string(),
+                id: 1,
+                timeout_at: valq.dlq_msgs()[0].timeout_at().unwrap(),
+            }
+        );
+        assert_eq!(*valq.events(), [QEvent::Dlq, QEvent::Pop]);
+    }
+
+    #[test]
+    fn test_with_rate_limit() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_rate_limit(1, 60);
+        for id in 1..=2 {
+            valq.msgs_mut()
+                .push_back(ValqMsg::new(id, format!("msg{}", id), None, 0));
+        }
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        // bucket is empty, next token in 60 seconds
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_eq!(
+            test.unwrap(),
+            ValkeyValue::OrderedMap(BTreeMap::from([("retry_after".into(), "60".into())]))
+        );
+        assert!(!valq.msgs()[1].is_in_flight());
+    }
+
+    #[test]
+    fn test_with_paused_queue() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        valq.set_paused(true);
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(1, "msg1".to_string(), None, 0));
+        valq.msgs_mut().push_back(ValqMsg::new(
+            2,
+            "msg2".to_string(),
+            Some(utils::now_as_seconds()),
+            5,
+        ));
+        valq.delayed_msgs_mut().insert(
+            ValqMsg::new(3, "msg3".to_string(), None, 0),
+            utils::now_as_seconds(),
+        );
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        assert_eq!(valq.msgs().len(), 2);
+        assert!(valq.dlq_msgs().is_empty());
+        assert_eq!(valq.delayed_msgs().len(), 1);
+        valq.set_paused(false);
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let test = PopOptions::parse(vec![]).unwrap();
+        assert_eq!(test, PopOptions::default());
+        let test = PopOptions::parse(vec!["TIMEOUT".to_string(), "60".to_string()]).unwrap();
+        assert_eq!(test.visibility_timeout, Some(60));
+        let test = PopOptions::parse(vec!["TIMEOUT".to_string(), "0".to_string()]);
+        assert!(test.is_err());
+        let test = PopOptions::parse(vec!["CONSUMER".to_string(), "c1".to_string()]).unwrap();
+        assert_eq!(test.consumer.as_deref(), Some("c1"));
+        let test = PopOptions::parse(vec!["CONSUMER".to_string()]);
+        assert!(test.is_err());
+        let test = PopOptions::parse(vec!["invalid".to_string()]);
+        assert!(test.is_err());
+    }
+
+    #[test]
+    fn test_with_retry_backoff() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_retry_policy(RetryPolicy::new(10, 100, 0));
+        // lease expired 5 seconds ago, first backoff is 10 seconds
+        let msg = ValqMsg::new(1, "msg".to_string(), Some(utils::now_as_seconds() - 5), 1);
+        valq.msgs_mut().push_back(msg);
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        // lease expired 10 seconds ago, backoff has passed
+        valq.msgs_mut()[0].set_timeout_at(Some(utils::now_as_seconds() - 10));
+        let test = handler(PopOptions::default(), Some(&mut valq));
+        assert_ne!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
+        assert_eq!(*valq.msgs()[0].delivery_attempts(), 2);
+        assert_eq!(*valq.events(), [QEvent::Pop, QEvent::Redelivered]);
     }
 
     #[test]
@@ -143,13 +467,68 @@ mod tests {
         let msg = ValqMsg::new(1, "msg".to_string(), Some(utils::now_as_seconds()), 5);
         valq.msgs_mut().push_back(msg);
 
-        let test = handler(Some(&mut valq));
+        let test = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("".to_string()));
         assert!(valq.msgs().is_empty());
         assert_eq!(valq.dlq_msgs().len(), 1);
         assert_eq!(valq.dlq_msgs()[0].id(), &1);
     }
 
+    #[test]
+    fn test_move_several_messages_to_dlq() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        for (id, attempts) in [(1, 5), (2, 0), (3, 5), (4, 5)] {
+            let msg = ValqMsg::new(
+                id,
+                format!("msg{}", id),
+                Some(utils::now_as_seconds() + 10),
+                attempts,
+            );
+            valq.msgs_mut().push_back(msg);
+        }
+        valq.msgs_mut()[0].set_timeout_at(Some(utils::now_as_seconds()));
+        valq.msgs_mut()[2].set_timeout_at(Some(utils::now_as_seconds()));
+        valq.msgs_mut()[3].set_timeout_at(Some(utils::now_as_seconds()));
+        let _ = handler(PopOptions::default(), Some(&mut valq));
+        let test: Vec<u64> = valq.dlq_msgs().iter().map(|msg| *msg.id()).collect();
+        assert_eq!(test, vec![1, 3, 4]);
+        assert_eq!(valq.msgs().len(), 1);
+        assert_eq!(*valq.msgs()[0].id(), 2);
+    }
+
+    #[test]
+    fn test_effects_replay_on_replica() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let now = utils::now_as_seconds();
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(1, "msg1".to_string(), Some(now), 5));
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(3, "msg3".to_string(), None, 0), now + 60);
+        let mut replica = valq.clone();
+        // dlq move, then lease
+        let _ = handler(PopOptions::default(), Some(&mut valq));
+        let _ = handler(PopOptions::default(), Some(&mut valq));
+        // promotion once the delay has passed on the primary
+        let msg = valq.delayed_msgs_mut().take(3).unwrap();
+        valq.delayed_msgs_mut().insert(msg.clone(), now);
+        replica.delayed_msgs_mut().take(3);
+        replica.delayed_msgs_mut().insert(msg, now);
+        let _ = handler(PopOptions::default(), Some(&mut valq));
+        for effect in std::mem::take(valq.effects_mut()) {
+            assert!(effect.apply(&mut replica).is_ok());
+        }
+        for msg in valq.msgs_mut().iter_mut() {
+            // client IDs are local to the primary
+            msg.set_client_id(None);
+        }
+        assert_eq!(format!("{:?}", replica), format!("{:?}", valq));
+        assert_eq!(*replica.msgs()[0].id(), 3);
+        assert!(replica.msgs()[0].is_in_flight());
+        assert_eq!(*replica.dlq_msgs()[0].id(), 1);
+    }
+
     #[test]
     fn test_move_delayed_msgs_to_main_q_moves_ready_messages() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
@@ -160,7 +539,7 @@ mod tests {
         valq.delayed_msgs_mut()
             .insert(msg2.clone(), utils::now_as_seconds());
 
-        let _ = handler(Some(&mut valq));
+        let _ = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(valq.delayed_msgs().len(), 0);
         assert_eq!(valq.msgs().len(), 2);
         assert_eq!(*valq.msgs()[0].id(), 2);
@@ -170,7 +549,7 @@ mod tests {
     #[test]
     fn test_move_delayed_msgs_to_main_q_handles_empty_delayed_msgs() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
-        let _ = handler(Some(&mut valq));
+        let _ = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(valq.delayed_msgs().len(), 0);
         assert!(valq.msgs().is_empty());
     }
@@ -182,7 +561,7 @@ mod tests {
         valq.delayed_msgs_mut()
             .insert(msg.clone(), utils::now_as_seconds() + 10);
 
-        let _ = handler(Some(&mut valq));
+        let _ = handler(PopOptions::default(), Some(&mut valq));
         assert_eq!(valq.delayed_msgs().len(), 1);
         assert!(valq.msgs().is_empty());
     }
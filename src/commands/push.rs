@@ -1,73 +1,235 @@
 use crate::data_types::VALQ_TYPE;
+use crate::structs::q_event::QEvent;
 use crate::structs::valq_msg::ValqMsg;
 use crate::structs::valq_type::ValqType;
 use crate::utils;
-use crate::utils::replicate_cmd_check;
+use crate::utils::replication::{self, Effect};
+use crate::utils::{claim_check, next_u64_option, notify, replica_cmd_check};
+use crate::{DELIVERY_ATTEMPTS_MAX, MAX_MSG_SIZE, VISIBILITY_TIMEOUT_MAX};
+use std::sync::atomic::Ordering;
 use valkey_module::{Context, NextArg, ValkeyError, ValkeyResult, ValkeyString};
 
 pub(crate) fn push(ctx: &Context, args: Vec<ValkeyString>) -> ValkeyResult {
-    replicate_cmd_check(ctx)?;
-    if args.len() != 2 && args.len() != 3 {
+    replica_cmd_check(ctx)?;
+    if args.len() < 2 {
         return Err(ValkeyError::Str(
-            "specify q name, message and optional delay",
+            "specify q name, message and optional delay, MAXATTEMPTS n, TIMEOUT s, GROUP key",
         ));
     }
     let mut args = args.into_iter();
     let key_arg = args.next_arg()?;
-    let value_arg = args.next_string()?;
-    let delay_arg = args.next_u64().unwrap_or(0);
-    let value = ctx
-        .open_key_writable(&key_arg)
-        .get_value::<ValqType>(&VALQ_TYPE)?;
-    handler(value_arg, delay_arg, value)
+    let value_arg = args.next_arg()?;
+    let options = PushOptions::parse(args.map(|arg| arg.to_string()).collect())?;
+    let key = ctx.open_key_writable(&key_arg);
+    let value = key.get_value::<ValqType>(&VALQ_TYPE)?;
+    // reject oversized messages before the body is copied out of the argument
+    if let Some(tmp) = value.as_deref() {
+        tmp.check_msg_size(
+            value_arg.len() as u64,
+            MAX_MSG_SIZE.load(Ordering::Relaxed) as u64,
+        )?;
+        claim_check::check_body_key(ctx, tmp, value_arg.len() as u64)?;
+    }
+    let mut result = handler(value_arg.to_string_lossy(), options, value);
+    // the companion key is written before the message is replicated
+    if let Err(err) = claim_check::store_bodies(ctx, &key) {
+        if let Some(valq) = key.get_value::<ValqType>(&VALQ_TYPE)? {
+            cancel(valq);
+        }
+        result = Err(err);
+    }
+    // rejected pushes still count towards overflow_count
+    notify::notify_events(ctx, &key_arg, &key);
+    replication::replicate_effects(ctx, &key_arg, &key);
+    claim_check::apply_body_ops(ctx, &key)?;
+    result
+}
+
+/// Optional arguments of `valq push`, a positional delay followed by keyword overrides.
+#[derive(Debug, Default, PartialEq)]
+struct PushOptions {
+    /// Delay in seconds before the message becomes visible.
+    delay: u64,
+    /// Overrides the queue's max delivery attempts for this message.
+    max_delivery_attempts: Option<u64>,
+    /// Overrides the queue's visibility timeout for this message.
+    visibility_timeout: Option<u64>,
+    /// Group key used to limit in-flight messages per group.
+    group: Option<String>,
+}
+
+impl PushOptions {
+    fn parse(args: Vec<String>) -> Result<Self, ValkeyError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter().peekable();
+        // optional positional delay
+        if let Some(delay) = args.peek().and_then(|arg| arg.parse::<u64>().ok()) {
+            options.delay = delay;
+            args.next();
+        }
+        while let Some(keyword) = args.next() {
+            match keyword.to_lowercase().as_str() {
+                "maxattempts" => {
+                    let max_delivery_attempts = next_u64_option(&mut args, "max attempts")?;
+                    if !(1..=DELIVERY_ATTEMPTS_MAX).contains(&max_delivery_attempts) {
+                        return Err(ValkeyError::Str(
+                            "max delivery attempts must be between 1 and 20",
+                        ));
+                    }
+                    options.max_delivery_attempts = Some(max_delivery_attempts);
+                }
+                "timeout" => {
+                    let visibility_timeout = next_u64_option(&mut args, "timeout")?;
+                    if !(1..=VISIBILITY_TIMEOUT_MAX).contains(&visibility_timeout) {
+                        return Err(ValkeyError::Str(
+                            "timeout must be between 1 and 43_200 seconds (12 hours)",
+                        ));
+                    }
+                    options.visibility_timeout = Some(visibility_timeout);
+                }
+                "group" => {
+                    let group = args.next().ok_or(ValkeyError::Str("specify group key"))?;
+                    options.group = Some(group);
+                }
+                _ => {
+                    return Err(ValkeyError::String(format!("unknown option {}", keyword)));
+                }
+            }
+        }
+        Ok(options)
+    }
 }
 
-fn handler(value_arg: String, delay_arg: u64, value: Option<&mut ValqType>) -> ValkeyResult {
+fn handler(value_arg: String, options: PushOptions, value: Option<&mut ValqType>) -> ValkeyResult {
     match value {
         Some(tmp) => {
+            tmp.make_room(value_arg.len() as u64)?;
             // increment id_sequence
             let id = tmp.id_sequence() + 1;
             tmp.set_id_sequence(id);
-            let msg = ValqMsg::new(id, value_arg, None, 0);
-            if delay_arg == 0 {
-                // add new value to the queue
-                tmp.msgs_mut().push_back(msg);
-            } else {
-                // add new value to the delayed messages
-                tmp.delayed_msgs_mut()
-                    .insert(msg, utils::now_as_seconds().saturating_add(delay_arg));
-            }
+            let mut msg = ValqMsg::new(id, value_arg, None, 0);
+            msg.set_max_delivery_attempts(options.max_delivery_attempts);
+            msg.set_visibility_timeout(options.visibility_timeout);
+            msg.set_group(options.group);
+            tmp.store_body(&mut msg);
+            let (list, score) = match options.delay {
+                0 => ("msgs", 0),
+                delay => ("delayed", utils::now_as_seconds().saturating_add(delay)),
+            };
+            let effect = Effect::Push {
+                list: list.to_string(),
+                score,
+                msg: msg.clone(),
+            };
+            tmp.effects_mut().push(effect);
+            let effect = Effect::Counters {
+                id_sequence: id,
+                overflow_count: *tmp.overflow_count(),
+            };
+            tmp.effects_mut().push(effect);
+            tmp.add_event(QEvent::Push);
+            // add new value to the queue or the delayed messages
+            tmp.add_msg(list, score, msg)?;
             Ok(id.to_string().into())
         }
         None => Err(ValkeyError::Str("create the queue")),
     }
 }
 
+/// Takes the pushed message back out of the queue when its companion key can't be written.
+/// Its id stays used and the messages dropped or moved to the DLQ by `make_room` stay that way.
+fn cancel(valq: &mut ValqType) {
+    let id = *valq.id_sequence();
+    let Some(index) = valq
+        .effects()
+        .iter()
+        .position(|effect| matches!(effect, Effect::Push { msg, .. } if *msg.id() == id))
+    else {
+        return;
+    };
+    if let Effect::Push { list, .. } = valq.effects_mut().remove(index) {
+        let _ = valq.take_msg(&list, id);
+    }
+    valq.events_mut().retain(|event| *event != QEvent::Push);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structs::body_encoding::BodyEncoding;
     use valkey_module::ValkeyValue;
 
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
     #[test]
     fn test_with_nonexistent_queue() {
-        let test = handler("msg1".to_string(), 0, None);
+        let test = handler("msg1".to_string(), PushOptions::default(), None);
         assert!(test.is_err());
     }
 
     #[test]
     fn test_with_valid_queue() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
-        let test = handler("msg1".to_string(), 0, Some(&mut valq));
+        let test = handler("msg1".to_string(), PushOptions::default(), Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("1".to_string()));
-        let test = handler("msg2".to_string(), 0, Some(&mut valq));
+        let test = handler("msg2".to_string(), PushOptions::default(), Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("2".to_string()));
     }
 
+    #[test]
+    fn test_effects() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let options = PushOptions {
+            delay: 60,
+            ..Default::default()
+        };
+        let _ = handler("msg1".to_string(), options, Some(&mut valq));
+        let score = *valq.delayed_msgs().members().values().next().unwrap();
+        // the replica gets the score computed by the primary
+        assert_eq!(
+            *valq.effects(),
+            [
+                Effect::Push {
+                    list: "delayed".to_string(),
+                    score,
+                    msg: ValqMsg::new(1, "msg1".to_string(), None, 0),
+                },
+                Effect::Counters {
+                    id_sequence: 1,
+                    overflow_count: 0,
+                },
+            ]
+        );
+        assert_eq!(*valq.events(), [QEvent::Push]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = handler("msg1".to_string(), PushOptions::default(), Some(&mut valq));
+        valq.effects_mut().clear();
+        valq.events_mut().clear();
+        let _ = handler("msg2".to_string(), PushOptions::default(), Some(&mut valq));
+        cancel(&mut valq);
+        assert_eq!(valq.msgs().len(), 1);
+        assert_eq!(*valq.queued_bytes(), 4);
+        assert_eq!(
+            *valq.effects(),
+            [Effect::Counters {
+                id_sequence: 2,
+                overflow_count: 0,
+            }]
+        );
+        assert!(valq.events().is_empty());
+    }
+
     #[test]
     fn test_large_number_of_messages() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
         for i in 1..=10_000 {
-            let test = handler(format!("msg{}", i), 0, Some(&mut valq));
+            let test = handler(format!("msg{}", i), PushOptions::default(), Some(&mut valq));
             assert!(test.is_ok());
             assert_eq!(test.unwrap(), ValkeyValue::BulkString(i.to_string()));
         }
@@ -77,9 +239,97 @@ mod tests {
     #[test]
     fn test_with_delayed_message() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
-        let test = handler("delayed_msg".to_string(), 1, Some(&mut valq));
+        let options = PushOptions {
+            delay: 1,
+            ..Default::default()
+        };
+        let test = handler("delayed_msg".to_string(), options, Some(&mut valq));
         assert_eq!(test.unwrap(), ValkeyValue::BulkString("1".to_string()));
         assert_eq!(valq.delayed_msgs().len(), 1);
         assert_eq!(valq.msgs().len(), 0);
     }
+
+    #[test]
+    fn test_with_overrides() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let options = PushOptions::parse(to_args(&["MAXATTEMPTS", "2", "TIMEOUT", "600"])).unwrap();
+        let test = handler("msg1".to_string(), options, Some(&mut valq));
+        assert!(test.is_ok());
+        assert_eq!(*valq.msgs()[0].max_delivery_attempts(), Some(2));
+        assert_eq!(*valq.msgs()[0].visibility_timeout(), Some(600));
+        let options = PushOptions::parse(to_args(&["GROUP", "tenant1"])).unwrap();
+        let _ = handler("msg2".to_string(), options, Some(&mut valq));
+        assert_eq!(valq.msgs()[1].group().as_deref(), Some("tenant1"));
+    }
+
+    #[test]
+    fn test_with_max_length() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        valq.set_max_length(1);
+        let test = handler("msg1".to_string(), PushOptions::default(), Some(&mut valq));
+        assert!(test.is_ok());
+        let test = handler("msg2".to_string(), PushOptions::default(), Some(&mut valq));
+        assert!(test.is_err());
+        assert_eq!(*valq.overflow_count(), 1);
+        // rejected messages do not use up ids
+        assert_eq!(*valq.id_sequence(), 1);
+    }
+
+    #[test]
+    fn test_with_max_msg_size() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_max_msg_size(4);
+        let test = handler("msg1".to_string(), PushOptions::default(), Some(&mut valq));
+        assert!(test.is_ok());
+        let test = handler("msg10".to_string(), PushOptions::default(), Some(&mut valq));
+        assert!(test.is_err());
+        assert_eq!(valq.msgs().len(), 1);
+        assert_eq!(*valq.id_sequence(), 1);
+    }
+
+    #[test]
+    fn test_with_claim_check() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_claim_check_threshold(4);
+        let _ = handler("msg1".to_string(), PushOptions::default(), Some(&mut valq));
+        let _ = handler("msg10".to_string(), PushOptions::default(), Some(&mut valq));
+        assert_eq!(valq.msgs()[0].body(), "msg1");
+        assert_eq!(valq.msgs()[1].body(), "");
+        assert_eq!(valq.msgs()[1].body_key().as_deref(), Some("{q}:body:2"));
+        assert_eq!(valq.body_ops().len(), 1);
+    }
+
+    #[test]
+    fn test_with_compress() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let _ = valq.set_compress_threshold(16);
+        let body = "{\"status\":\"ok\"}".repeat(10);
+        let _ = handler("msg1".to_string(), PushOptions::default(), Some(&mut valq));
+        let _ = handler(body.clone(), PushOptions::default(), Some(&mut valq));
+        assert_eq!(*valq.msgs()[0].encoding(), BodyEncoding::Raw);
+        assert_eq!(*valq.msgs()[1].encoding(), BodyEncoding::Deflate);
+        assert_eq!(valq.msgs()[1].decoded_body().unwrap(), body);
+        assert!(valq.bytes() < body.len() as u64);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let test = PushOptions::parse(vec![]).unwrap();
+        assert_eq!(test, PushOptions::default());
+        let test = PushOptions::parse(to_args(&["10", "timeout", "60"])).unwrap();
+        assert_eq!(test.delay, 10);
+        assert_eq!(test.visibility_timeout, Some(60));
+        assert_eq!(test.max_delivery_attempts, None);
+        // invalid values and unknown options
+        let test = PushOptions::parse(to_args(&["maxattempts", "0"]));
+        assert!(test.is_err());
+        let test = PushOptions::parse(to_args(&["timeout", "43201"]));
+        assert!(test.is_err());
+        let test = PushOptions::parse(to_args(&["timeout"]));
+        assert!(test.is_err());
+        let test = PushOptions::parse(to_args(&["group"]));
+        assert!(test.is_err());
+        let test = PushOptions::parse(to_args(&["10", "20"]));
+        assert!(test.is_err());
+    }
 }
@@ -0,0 +1,175 @@
+use crate::structs::body_encoding::BodyEncoding;
+use crate::structs::valq_msg::ValqMsg;
+use crate::structs::valq_type::ValqType;
+use std::os::raw::c_void;
+use valkey_module::{RedisModuleIO, RedisModuleString, ValkeyString, raw};
+
+/// Rewrites a `ValqType` instance as the commands that rebuild it.
+///
+/// This function is called by the Valkey module during an AOF rewrite. It emits one
+/// `valq restore <key> QUEUE` command with the queue settings, followed by one
+/// `valq restore <key> MSG` command per message in the main queue, the DLQ and the delayed queue.
+/// The argument layout is produced by `restore_q_args` and `restore_msg_args` and parsed by `valq restore`.
+///
+/// # Arguments
+/// * `aof` - A pointer to the RedisModuleIO structure used for emitting commands.
+/// * `key` - The name of the key holding the queue.
+/// * `value` - A pointer to the `ValqType` instance to rewrite.
+///
+/// # Safety
+/// This function uses unsafe code to dereference raw pointers. It ensures that
+/// the pointers are not null before accessing the data.
+pub(crate) extern "C" fn aof_rewrite(
+    aof: *mut RedisModuleIO,
+    key: *mut RedisModuleString,
+    value: *mut c_void,
+) {
+    if aof.is_null() || key.is_null() || value.is_null() {
+        return;
+    }
+    let item = unsafe { &*value.cast::<ValqType>() };
+    emit(aof, key, restore_q_args(item));
+    for msg in item.msgs() {
+        emit(aof, key, restore_msg_args("msgs", 0, msg));
+    }
+    for msg in item.dlq_msgs() {
+        emit(aof, key, restore_msg_args("dlq", 0, msg));
+    }
+    for (msg, score) in item.delayed_msgs().members() {
+        emit(aof, key, restore_msg_args("delayed", *score, msg));
+    }
+}
+
+/// Emits `valq restore <key> <args>`, the arguments are passed as a binary safe vector.
+fn emit(aof: *mut RedisModuleIO, key: *mut RedisModuleString, args: Vec<Vec<u8>>) {
+    let subcmd = ValkeyString::create_from_slice(std::ptr::null_mut(), b"restore");
+    let args: Vec<ValkeyString> = args
+        .iter()
+        .map(|arg| ValkeyString::create_from_slice(std::ptr::null_mut(), arg))
+        .collect();
+    let mut argv = vec![subcmd.inner, key];
+    argv.extend(args.iter().map(|arg| arg.inner));
+    if let Some(emit_aof) = unsafe { raw::RedisModule_EmitAOF } {
+        unsafe {
+            emit_aof(
+                aof,
+                c"valq".as_ptr(),
+                c"v".as_ptr(),
+                argv.as_mut_ptr(),
+                argv.len(),
+            );
+        }
+    }
+}
+
+/// Arguments of `valq restore <key> QUEUE`.
+/// Positional visibility timeout, max delivery attempts, retention period, id sequence,
+/// paused and overflow count are followed by the name and the keyword settings of `valq create`.
+pub(crate) fn restore_q_args(item: &ValqType) -> Vec<Vec<u8>> {
+    let release_on_disconnect = if *item.release_on_disconnect() {
+        "yes"
+    } else {
+        "no"
+    };
+    [
+        "QUEUE".to_string(),
+        item.visibility_timeout().to_string(),
+        item.max_delivery_attempts().to_string(),
+        item.retention_period().to_string(),
+        item.id_sequence().to_string(),
+        (*item.paused() as u64).to_string(),
+        item.overflow_count().to_string(),
+        "NAME".to_string(),
+        item.name().clone(),
+        "BACKOFF".to_string(),
+        item.retry_policy().base().to_string(),
+        item.retry_policy().max().to_string(),
+        item.retry_policy().jitter().to_string(),
+        "RELEASEONDISCONNECT".to_string(),
+        release_on_disconnect.to_string(),
+        "MAXINFLIGHT".to_string(),
+        item.max_inflight().to_string(),
+        "GROUPMAXINFLIGHT".to_string(),
+        item.group_max_inflight().to_string(),
+        "RATE".to_string(),
+        item.rate_limiter().rate().to_string(),
+        "PER".to_string(),
+        item.rate_limiter().per().to_string(),
+        "MAXLENGTH".to_string(),
+        item.max_length().to_string(),
+        "MAXBYTES".to_string(),
+        item.max_bytes().to_string(),
+        "OVERFLOW".to_string(),
+        item.overflow_policy().as_str().to_string(),
+        "MAXMSGSIZE".to_string(),
+        item.max_msg_size().to_string(),
+        "CLAIMCHECK".to_string(),
+        item.claim_check_threshold().to_string(),
+        "COMPRESS".to_string(),
+        item.compress_threshold().to_string(),
+    ]
+    .into_iter()
+    .map(String::into_bytes)
+    .collect()
+}
+
+/// Arguments of `valq restore <key> MSG`.
+/// Optional values are passed as 0 or an empty string when not set, same as in the RDB.
+/// The body is passed as stored, compressed bodies stay compressed.
+pub(crate) fn restore_msg_args(list: &str, score: u64, msg: &ValqMsg) -> Vec<Vec<u8>> {
+    let body = match msg.encoding() {
+        BodyEncoding::Raw => msg.body().as_bytes().to_vec(),
+        BodyEncoding::Deflate => msg.compressed_body().clone(),
+    };
+    vec![
+        b"MSG".to_vec(),
+        list.as_bytes().to_vec(),
+        score.to_string().into_bytes(),
+        msg.id().to_string().into_bytes(),
+        msg.encoding().as_u64().to_string().into_bytes(),
+        body,
+        msg.timeout_at().unwrap_or(0).to_string().into_bytes(),
+        msg.delivery_attempts().to_string().into_bytes(),
+        msg.max_delivery_attempts()
+            .unwrap_or(0)
+            .to_string()
+            .into_bytes(),
+        msg.visibility_timeout()
+            .unwrap_or(0)
+            .to_string()
+            .into_bytes(),
+        msg.consumer().clone().unwrap_or_default().into_bytes(),
+        msg.leased_at().unwrap_or(0).to_string().into_bytes(),
+        msg.group().clone().unwrap_or_default().into_bytes(),
+        msg.body_key().clone().unwrap_or_default().into_bytes(),
+        msg.body_key_len().to_string().into_bytes(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aof_rewrite_null_pointer() {
+        aof_rewrite(
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        // ensuring no panic occurs
+    }
+
+    #[test]
+    fn test_restore_msg_args() {
+        let mut msg = ValqMsg::new(7, "msg1".to_string(), Some(100), 2);
+        msg.set_consumer(Some("worker1".to_string()));
+        let args = restore_msg_args("msgs", 0, &msg);
+        assert_eq!(args.len(), 15);
+        assert_eq!(args[3], b"7");
+        assert_eq!(args[5], b"msg1");
+        assert_eq!(args[6], b"100");
+        assert_eq!(args[10], b"worker1");
+        assert_eq!(args[12], b"");
+    }
+}
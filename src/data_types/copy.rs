@@ -0,0 +1,50 @@
+use crate::COPY_RESET_LEASES;
+use crate::structs::valq_type::ValqType;
+use std::os::raw::c_void;
+use std::sync::atomic::Ordering;
+use valkey_module::logging::log_notice;
+use valkey_module::{RedisModuleString, ValkeyString};
+
+/// Copies a `ValqType` instance for `COPY`.
+///
+/// The copy is named after the destination key, it is registered in `GLOBAL_Q_REGISTRY`
+/// by the `copy_to` keyspace event which knows the destination db.
+/// In-flight messages keep their lease unless `valq.copy-reset-leases` is enabled.
+///
+/// # Returns
+/// * A pointer to the new `ValqType` instance if successful.
+/// * A null pointer if the queue can't be copied, `COPY` then replies with an error.
+///
+/// # Safety
+/// This function uses unsafe code to dereference raw pointers. It ensures that
+/// the pointers are not null before accessing the data.
+pub(crate) extern "C" fn copy(
+    _fromkey: *mut RedisModuleString,
+    tokey: *mut RedisModuleString,
+    value: *const c_void,
+) -> *mut c_void {
+    if tokey.is_null() || value.is_null() {
+        return std::ptr::null_mut();
+    }
+    let item = unsafe { &*value.cast::<ValqType>() };
+    let q_name = String::from_utf8_lossy(ValkeyString::string_as_slice(tokey)).to_string();
+    let valq = match item.copy(&q_name, COPY_RESET_LEASES.load(Ordering::Relaxed)) {
+        Ok(valq) => valq,
+        Err(err) => {
+            log_notice(format!("copy err: {}", err));
+            return std::ptr::null_mut();
+        }
+    };
+    Box::into_raw(Box::new(valq)).cast::<c_void>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_null_pointer() {
+        let result = copy(std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null());
+        assert!(result.is_null());
+    }
+}
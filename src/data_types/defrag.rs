@@ -0,0 +1,82 @@
+use crate::structs::valq_type::ValqType;
+use std::mem::ManuallyDrop;
+use std::os::raw::{c_int, c_void};
+use valkey_module::defrag::Defrag;
+use valkey_module::{RedisModuleDefragCtx, RedisModuleString};
+
+/// Moves the allocations of a `ValqType` instance during active defrag.
+///
+/// The queue struct is moved on the first call, then the message buffers are walked with
+/// `ValqType::defrag`. When the server asks to stop, the position is saved with the defrag
+/// cursor and the next call resumes from it.
+///
+/// # Returns
+/// * `1` - If there is more work to do and the callback should be called again.
+/// * `0` - If the whole queue was walked.
+///
+/// # Safety
+/// This function uses unsafe code to dereference raw pointers. It ensures that
+/// the pointers are not null before accessing the data.
+pub(crate) extern "C" fn defrag(
+    ctx: *mut RedisModuleDefragCtx,
+    _key: *mut RedisModuleString,
+    value: *mut *mut c_void,
+) -> c_int {
+    // buffers from the system allocator can't be moved by the Valkey allocator
+    if cfg!(feature = "enable-system-alloc") || ctx.is_null() || value.is_null() {
+        return 0;
+    }
+    if unsafe { (*value).is_null() } {
+        return 0;
+    }
+    let defrag = Defrag::new(ctx);
+    // the cursor is only available when the server defrags the key incrementally
+    let cursor = unsafe { defrag.get_cursor() }.unwrap_or(0);
+    if cursor == 0 {
+        let moved = unsafe { defrag.alloc(*value) };
+        if !moved.is_null() {
+            unsafe { *value = moved };
+        }
+    }
+    let item = unsafe { &mut *(*value).cast::<ValqType>() };
+    let mut realloc = |buffer: &mut Vec<u8>| defrag_buffer(&defrag, buffer);
+    let should_stop = || unsafe { defrag.should_stop_defrag() };
+    match item.defrag(cursor, &mut realloc, &should_stop) {
+        Some(next) => {
+            unsafe { defrag.set_cursor(next) };
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Moves the buffer to the allocation returned by the server, if any.
+fn defrag_buffer(defrag: &Defrag, buffer: &mut Vec<u8>) {
+    if buffer.capacity() == 0 {
+        return;
+    }
+    let mut tmp = ManuallyDrop::new(std::mem::take(buffer));
+    let (ptr, len, capacity) = (tmp.as_mut_ptr(), tmp.len(), tmp.capacity());
+    let moved = unsafe { defrag.alloc(ptr.cast()) };
+    let ptr = if moved.is_null() {
+        ptr
+    } else {
+        moved.cast::<u8>()
+    };
+    *buffer = unsafe { Vec::from_raw_parts(ptr, len, capacity) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defrag_null_pointer() {
+        let result = defrag(
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(result, 0);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::structs::body_encoding::BodyEncoding;
+use crate::structs::valq_msg::ValqMsg;
+use crate::structs::valq_type::ValqType;
+use std::os::raw::c_void;
+use valkey_module::RedisModuleDigest;
+use valkey_module::digest::Digest;
+
+/// Element added to a digest sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DigestElement {
+    Int(u64),
+    Bytes(Vec<u8>),
+}
+
+/// Computes the digest of a `ValqType` instance for `DEBUG DIGEST` and `DEBUG DIGEST-VALUE`.
+///
+/// The queue settings are added as one sequence and every message as its own sequence.
+/// Valkey combines sequences regardless of their order, so each message sequence starts with
+/// its list and position, delayed messages use their score instead because they are not ordered.
+/// The rate limiter tokens and the client ID of a lease are local to the node and are not included.
+///
+/// # Safety
+/// This function uses unsafe code to dereference raw pointers. It ensures that
+/// the pointers are not null before accessing the data.
+pub(crate) extern "C" fn digest(md: *mut RedisModuleDigest, value: *mut c_void) {
+    if md.is_null() || value.is_null() {
+        return;
+    }
+    let item = unsafe { &*value.cast::<ValqType>() };
+    let mut md = Digest::new(md);
+    add_sequence(&mut md, q_sequence(item));
+    for (position, msg) in item.msgs().iter().enumerate() {
+        add_sequence(&mut md, msg_sequence("msgs", position as u64, msg));
+    }
+    for (position, msg) in item.dlq_msgs().iter().enumerate() {
+        add_sequence(&mut md, msg_sequence("dlq", position as u64, msg));
+    }
+    for (msg, score) in item.delayed_msgs().members() {
+        add_sequence(&mut md, msg_sequence("delayed", *score, msg));
+    }
+}
+
+fn add_sequence(md: &mut Digest, sequence: Vec<DigestElement>) {
+    for element in sequence {
+        match element {
+            DigestElement::Int(value) => md.add_long_long(value as i64),
+            DigestElement::Bytes(value) => md.add_string_buffer(&value),
+        }
+    }
+    md.end_sequence();
+}
+
+/// Queue settings and counters, in the same order as the RDB.
+pub(crate) fn q_sequence(item: &ValqType) -> Vec<DigestElement> {
+    vec![
+        DigestElement::Bytes(item.name().as_bytes().to_vec()),
+        DigestElement::Int(*item.id_sequence()),
+        DigestElement::Int(*item.visibility_timeout()),
+        DigestElement::Int(*item.max_delivery_attempts()),
+        DigestElement::Int(*item.retention_period()),
+        DigestElement::Int(*item.retry_policy().base()),
+        DigestElement::Int(*item.retry_policy().max()),
+        DigestElement::Int(*item.retry_policy().jitter()),
+        DigestElement::Int(*item.release_on_disconnect() as u64),
+        DigestElement::Int(*item.max_inflight()),
+        DigestElement::Int(*item.group_max_inflight()),
+        DigestElement::Int(*item.rate_limiter().rate()),
+        DigestElement::Int(*item.rate_limiter().per()),
+        DigestElement::Int(*item.paused() as u64),
+        DigestElement::Int(*item.max_length()),
+        DigestElement::Int(*item.max_bytes()),
+        DigestElement::Int(item.overflow_policy().as_u64()),
+        DigestElement::Int(*item.overflow_count()),
+        DigestElement::Int(*item.max_msg_size()),
+        DigestElement::Int(*item.claim_check_threshold()),
+        DigestElement::Int(*item.compress_threshold()),
+    ]
+}
+
+/// One message with its list and position, or its score for delayed messages.
+/// Optional values are added as 0 or an empty string when not set, same as in the RDB.
+pub(crate) fn msg_sequence(list: &str, position: u64, msg: &ValqMsg) -> Vec<DigestElement> {
+    let body = match msg.encoding() {
+        BodyEncoding::Raw => msg.body().as_bytes().to_vec(),
+        BodyEncoding::Deflate => msg.compressed_body().clone(),
+    };
+    vec![
+        DigestElement::Bytes(list.as_bytes().to_vec()),
+        DigestElement::Int(position),
+        DigestElement::Int(*msg.id()),
+        DigestElement::Int(msg.encoding().as_u64()),
+        DigestElement::Bytes(body),
+        DigestElement::Int(msg.timeout_at().unwrap_or(0)),
+        DigestElement::Int(*msg.delivery_attempts()),
+        DigestElement::Int(msg.max_delivery_attempts().unwrap_or(0)),
+        DigestElement::Int(msg.visibility_timeout().unwrap_or(0)),
+        DigestElement::Bytes(msg.consumer().clone().unwrap_or_default().into_bytes()),
+        DigestElement::Int(msg.leased_at().unwrap_or(0)),
+        DigestElement::Bytes(msg.group().clone().unwrap_or_default().into_bytes()),
+        DigestElement::Bytes(msg.body_key().clone().unwrap_or_default().into_bytes()),
+        DigestElement::Int(*msg.body_key_len()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_null_pointer() {
+        digest(std::ptr::null_mut(), std::ptr::null_mut());
+        // ensuring no panic occurs
+    }
+
+    #[test]
+    fn test_q_sequence() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let test = q_sequence(&valq);
+        valq.set_id_sequence(1);
+        assert_ne!(q_sequence(&valq), test);
+        let _ = valq.set_rate_limit(5, 60);
+        let test = q_sequence(&valq);
+        // tokens are local to the node
+        valq.rate_limiter_mut().acquire(0);
+        assert_eq!(q_sequence(&valq), test);
+    }
+
+    #[test]
+    fn test_msg_sequence() {
+        let mut msg = ValqMsg::new(1, "msg1".to_string(), None, 0);
+        let test = msg_sequence("msgs", 0, &msg);
+        assert_ne!(msg_sequence("dlq", 0, &msg), test);
+        assert_ne!(msg_sequence("msgs", 1, &msg), test);
+        msg.lease(Some("worker1".to_string()), Some(1), 100);
+        assert_ne!(msg_sequence("msgs", 0, &msg), test);
+        let test = msg_sequence("msgs", 0, &msg);
+        // client id is local to the node
+        msg.set_client_id(Some(2));
+        assert_eq!(msg_sequence("msgs", 0, &msg), test);
+    }
+}
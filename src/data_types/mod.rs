@@ -1,64 +1,107 @@
-use crate::GLOBAL_Q_LIST;
 use crate::structs::valq_type::ValqType;
+use crate::utils::{claim_check, q_registry};
 use std::os::raw::c_void;
-use valkey_module::logging::log_notice;
-use valkey_module::{RedisModuleTypeMethods, native_types::ValkeyType, raw};
+use valkey_module::{
+    RedisModuleString, RedisModuleTypeMethods, ValkeyString, native_types::ValkeyType, raw,
+};
 
+pub(crate) mod aof_rewrite;
 mod aux_load;
-mod aux_save;
+mod copy;
+mod defrag;
+mod digest;
+mod rdb_io;
 mod rdb_load;
 mod rdb_save;
 
 pub(crate) static VALQ_TYPE: ValkeyType = ValkeyType::new(
     "valq-type",
-    1,
+    rdb_io::ENCODING_VERSION,
     RedisModuleTypeMethods {
         version: valkey_module::TYPE_METHOD_VERSION,
         rdb_load: Some(rdb_load::rdb_load),
         rdb_save: Some(rdb_save::rdb_save),
-        aof_rewrite: None,
+        aof_rewrite: Some(aof_rewrite::aof_rewrite),
         free: Some(free),
-        mem_usage: None,
-        digest: None,
+        mem_usage: Some(mem_usage),
+        digest: Some(digest::digest),
         aux_load: Some(aux_load::aux_load),
-        aux_save: Some(aux_save::aux_save),
-        aux_save_triggers: raw::Aux::Before as i32,
-        free_effort: None,
+        // the queue registry is rebuilt from the keyspace, aux data is only read from older RDB files
+        aux_save: None,
+        aux_save_triggers: 0,
+        free_effort: Some(free_effort),
         unlink: None,
-        copy: None,
-        defrag: None,
+        copy: Some(copy::copy),
+        defrag: Some(defrag::defrag),
         free_effort2: None,
-        unlink2: None,
+        unlink2: Some(unlink2),
         copy2: None,
         mem_usage2: None,
         aux_save2: None,
     },
 );
 
+/// Deallocates a queue. Values with a high `free_effort` are freed on a background thread,
+/// so `GLOBAL_Q_REGISTRY` is updated in `unlink2` on the main thread instead.
 extern "C" fn free(value: *mut c_void) {
     if value.is_null() {
         return;
     }
-    unsafe {
-        let valq_type = value.cast::<ValqType>();
-        let q_name = (*valq_type).name();
-        // update GLOBAL_Q_LIST on free which happens for flushdb or del
-        match GLOBAL_Q_LIST.write() {
-            Ok(mut q_list) => {
-                q_list.remove(q_name);
-            }
-            Err(err) => {
-                log_notice(format!("free err: {}", err));
-            }
-        };
-        // Convert the raw pointer back to a Box to properly deallocate the memory.
-        let _ = Box::from_raw(valq_type);
+    // Convert the raw pointer back to a Box to properly deallocate the memory.
+    let _ = unsafe { Box::from_raw(value.cast::<ValqType>()) };
+}
+
+/// Removes the queue from `GLOBAL_Q_REGISTRY` and remembers its companion keys for
+/// `utils::claim_check` when its key is deleted, expired, evicted, overwritten, renamed or moved
+/// to another db. The key name and db come from the key,
+/// the queue keeps the name it was created with after `RENAME`.
+/// Called on the main thread before `free`, flushes are handled by `utils::flush_db`.
+extern "C" fn unlink2(ctx: *mut raw::RedisModuleKeyOptCtx, value: *const c_void) {
+    if ctx.is_null() || value.is_null() {
+        return;
+    }
+    let api = unsafe {
+        (
+            raw::RedisModule_GetKeyNameFromOptCtx,
+            raw::RedisModule_GetDbIdFromOptCtx,
+        )
+    };
+    let (Some(get_key_name), Some(get_db_id)) = api else {
+        return;
+    };
+    let key = unsafe { get_key_name(ctx) };
+    if key.is_null() {
+        return;
+    }
+    let key_name = String::from_utf8_lossy(ValkeyString::string_as_slice(key)).to_string();
+    let db = unsafe { get_db_id(ctx) };
+    q_registry::unregister(db, &key_name);
+    claim_check::unlinked(db, &key_name, unsafe { &*value.cast::<ValqType>() });
+}
+
+/// Number of allocations to free, above the lazyfree threshold the queue is freed in the background.
+extern "C" fn free_effort(_key: *mut RedisModuleString, value: *const c_void) -> usize {
+    if value.is_null() {
+        return 0;
+    }
+    let valq_type = unsafe { &*value.cast::<ValqType>() };
+    1 + valq_type.msgs().len()
+        + valq_type.dlq_msgs().len()
+        + valq_type.delayed_msgs().len() as usize
+}
+
+extern "C" fn mem_usage(value: *const c_void) -> usize {
+    if value.is_null() {
+        return 0;
     }
+    let valq_type = unsafe { &*value.cast::<ValqType>() };
+    valq_type.mem_usage()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structs::valq_msg::ValqMsg;
 
     #[test]
     fn free_null_pointer() {
@@ -66,6 +109,41 @@ mod tests {
         // ensuring no panic occurs
     }
 
+    #[test]
+    fn mem_usage_null_pointer() {
+        assert_eq!(mem_usage(std::ptr::null()), 0);
+    }
+
+    #[test]
+    fn mem_usage_non_null_pointer() {
+        let valq = ValqType::new("q", None, None, None).unwrap();
+        let ptr: *const ValqType = &valq;
+        assert_eq!(mem_usage(ptr.cast()), valq.mem_usage());
+    }
+
+    #[test]
+    fn unlink2_null_pointer() {
+        unlink2(std::ptr::null_mut(), std::ptr::null());
+        let valq = ValqType::new("q", None, None, None).unwrap();
+        let ptr: *const ValqType = &valq;
+        unlink2(std::ptr::null_mut(), ptr.cast());
+        // ensuring no panic occurs
+    }
+
+    #[test]
+    fn free_effort_counts_msgs() {
+        assert_eq!(free_effort(std::ptr::null_mut(), std::ptr::null()), 0);
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(1, "msg1".to_string(), None, 0));
+        valq.dlq_msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(3, "msg3".to_string(), None, 0), 100);
+        let ptr: *const ValqType = &valq;
+        assert_eq!(free_effort(std::ptr::null_mut(), ptr.cast()), 4);
+    }
+
     #[test]
     #[ignore]
     fn free_non_null_pointer() {
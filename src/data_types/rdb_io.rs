@@ -0,0 +1,154 @@
+//! RDB encoding of `valq-type` values.
+//!
+//! The type is registered with `ENCODING_VERSION` and Valkey passes the version a value was
+//! saved with to `rdb_load` as `encver`. Values are always saved with the latest version,
+//! older versions are loaded with the defaults for the fields they don't have.
+//! Any change to the saved fields must bump `ENCODING_VERSION`, add the new layout below
+//! and keep loading the older ones, with a fixture for it in the `rdb_load` tests.
+//!
+//! Every value is a list of unsigned integers and strings in this order:
+//! * queue - name, id sequence, visibility timeout, max delivery attempts, retention period
+//! * main queue - message count followed by the messages
+//! * DLQ - message count followed by the messages
+//! * delayed queue - message count followed by the score and message of each
+//!
+//! Message fields are id, body, timeout at and delivery attempts.
+//! Optional values are saved as 0 or an empty string when not set.
+//!
+//! Later versions append fields to the queue and message fields above:
+//! * 2 - queue: retry backoff base, max and jitter
+//! * 3 - message: max delivery attempts and visibility timeout
+//! * 4 - message: consumer and leased at
+//! * 5 - queue: release on disconnect
+//! * 6 - queue: max inflight
+//! * 7 - queue: group max inflight, message: group
+//! * 8 - queue: rate and per
+//! * 9 - queue: paused
+//! * 10 - queue: max length, max bytes, overflow policy and overflow count
+//! * 11 - queue: max message size
+//! * 12 - queue: claim check threshold, message: body key and body key length
+//! * 13 - queue: compress threshold, message: body encoding and compressed body
+//!
+//! Version 1, the first release, has none of them.
+
+use valkey_module::{
+    RedisModuleIO, load_string, load_string_buffer, load_unsigned, save_slice, save_string,
+    save_unsigned,
+};
+
+/// Encoding version the type is registered with and values are saved with.
+pub(crate) const ENCODING_VERSION: i32 = 13;
+
+/// Reads the values saved by `RdbWriter` in the same order.
+pub(crate) trait RdbReader {
+    fn read_unsigned(&mut self) -> Option<u64>;
+    fn read_string(&mut self) -> Option<String>;
+    fn read_buffer(&mut self) -> Option<Vec<u8>>;
+}
+
+/// Writes the values of a `ValqType` to the RDB.
+pub(crate) trait RdbWriter {
+    fn write_unsigned(&mut self, value: u64);
+    fn write_string(&mut self, value: &str);
+    fn write_buffer(&mut self, value: &[u8]);
+}
+
+/// RDB passed by Valkey to `rdb_load` and `rdb_save`, the pointer must not be null.
+pub(crate) struct RdbIo(pub(crate) *mut RedisModuleIO);
+
+impl RdbReader for RdbIo {
+    fn read_unsigned(&mut self) -> Option<u64> {
+        load_unsigned(self.0).ok()
+    }
+
+    fn read_string(&mut self) -> Option<String> {
+        load_string(self.0).ok().map(|tmp| tmp.to_string())
+    }
+
+    fn read_buffer(&mut self) -> Option<Vec<u8>> {
+        load_string_buffer(self.0)
+            .ok()
+            .map(|tmp| tmp.as_ref().to_vec())
+    }
+}
+
+impl RdbWriter for RdbIo {
+    fn write_unsigned(&mut self, value: u64) {
+        save_unsigned(self.0, value);
+    }
+
+    fn write_string(&mut self, value: &str) {
+        save_string(self.0, value);
+    }
+
+    fn write_buffer(&mut self, value: &[u8]) {
+        save_slice(self.0, value);
+    }
+}
+
+/// In-memory RDB used by the tests to save values and load fixtures of older versions.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use super::{RdbReader, RdbWriter};
+    use std::collections::VecDeque;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) enum Field {
+        Unsigned(u64),
+        Bytes(Vec<u8>),
+    }
+
+    /// Shorthand for a string field.
+    pub(crate) fn s(value: &str) -> Field {
+        Field::Bytes(value.as_bytes().to_vec())
+    }
+
+    /// Shorthand for an unsigned field.
+    pub(crate) fn u(value: u64) -> Field {
+        Field::Unsigned(value)
+    }
+
+    #[derive(Debug, Default)]
+    pub(crate) struct Rdb(pub(crate) VecDeque<Field>);
+
+    impl Rdb {
+        pub(crate) fn new(fields: Vec<Field>) -> Self {
+            Self(fields.into())
+        }
+    }
+
+    impl RdbReader for Rdb {
+        fn read_unsigned(&mut self) -> Option<u64> {
+            match self.0.pop_front()? {
+                Field::Unsigned(value) => Some(value),
+                Field::Bytes(_) => None,
+            }
+        }
+
+        fn read_string(&mut self) -> Option<String> {
+            self.read_buffer()
+                .map(|tmp| String::from_utf8_lossy(&tmp).to_string())
+        }
+
+        fn read_buffer(&mut self) -> Option<Vec<u8>> {
+            match self.0.pop_front()? {
+                Field::Bytes(value) => Some(value),
+                Field::Unsigned(_) => None,
+            }
+        }
+    }
+
+    impl RdbWriter for Rdb {
+        fn write_unsigned(&mut self, value: u64) {
+            self.0.push_back(Field::Unsigned(value));
+        }
+
+        fn write_string(&mut self, value: &str) {
+            self.0.push_back(s(value));
+        }
+
+        fn write_buffer(&mut self, value: &[u8]) {
+            self.0.push_back(Field::Bytes(value.to_vec()));
+        }
+    }
+}
@@ -1,17 +1,27 @@
+use crate::RDB_LOAD_SALVAGE;
+use crate::data_types::rdb_io::{ENCODING_VERSION, RdbIo, RdbReader};
+use crate::structs::body_encoding::BodyEncoding;
+use crate::structs::overflow_policy::OverflowPolicy;
+use crate::structs::retry_policy::RetryPolicy;
 use crate::structs::valq_msg::ValqMsg;
 use crate::structs::valq_type::ValqType;
 use std::os::raw::c_void;
-use valkey_module::{RedisModuleIO, load_string, load_unsigned, logging::log_notice};
+use std::sync::atomic::Ordering;
+use valkey_module::logging::log_warning;
+use valkey_module::{RedisModuleIO, ValkeyError, ValkeyString, raw};
 
 /// Loads the state of a `ValqType` instance from the Valkey database.
 ///
 /// This function is called by the Valkey module to restore the state of a `ValqType`
 /// instance. It deserializes the fields of the `ValqType` and its associated messages
 /// in the same order they were saved to ensure compatibility with the corresponding save function.
+/// The layout of each encoding version is described in `rdb_io`.
+/// Truncated or corrupt data fails the load with the key and field logged as a warning,
+/// see `load_valq` for what `valq.rdb-load-salvage` recovers.
 ///
 /// # Arguments
 /// * `rdb` - A pointer to the RedisModuleIO structure used for loading data.
-/// * `_encver` - The encoding version of the data being loaded.
+/// * `encver` - The encoding version of the data being loaded.
 ///
 /// # Returns
 /// * A pointer to the newly created `ValqType` instance if successful.
@@ -20,117 +30,684 @@ use valkey_module::{RedisModuleIO, load_string, load_unsigned, logging::log_noti
 /// # Safety
 /// This function uses unsafe code to dereference raw pointers. It ensures that
 /// the pointers are not null before accessing the data.
-pub(crate) extern "C" fn rdb_load(rdb: *mut RedisModuleIO, _encver: i32) -> *mut c_void {
+pub(crate) extern "C" fn rdb_load(rdb: *mut RedisModuleIO, encver: i32) -> *mut c_void {
     if rdb.is_null() {
         return std::ptr::null_mut();
     }
+    let salvage = RDB_LOAD_SALVAGE.load(Ordering::Relaxed);
+    match load_valq(&mut RdbIo(rdb), encver, salvage) {
+        Ok(valq) => Box::into_raw(Box::new(valq)) as *mut c_void,
+        Err(err) => {
+            log_warning(format!("rdb_load {}: {}", key_name(rdb), err));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Name of the key being loaded, empty when Valkey doesn't provide it.
+fn key_name(rdb: *mut RedisModuleIO) -> String {
+    let Some(get_key_name) = (unsafe { raw::RedisModule_GetKeyNameFromIO }) else {
+        return String::new();
+    };
+    let key = unsafe { get_key_name(rdb) };
+    if key.is_null() {
+        return String::new();
+    }
+    String::from_utf8_lossy(ValkeyString::string_as_slice(key)).to_string()
+}
+
+/// Loads a queue saved with encoding version `encver`, fields added in later versions get their defaults.
+///
+/// Missing or mistyped fields always fail the load, the rest of the value can't be located after them.
+/// Settings outside the current limits, unknown enum values and message ids above the id sequence
+/// also fail the load unless `salvage` is set, then settings keep their defaults,
+/// messages with an unknown body encoding are skipped and the id sequence is raised.
+pub(crate) fn load_valq(
+    rdb: &mut impl RdbReader,
+    encver: i32,
+    salvage: bool,
+) -> Result<ValqType, ValkeyError> {
+    if !(1..=ENCODING_VERSION).contains(&encver) {
+        return Err(ValkeyError::String(format!(
+            "unsupported encoding version {}, this module supports up to {}",
+            encver, ENCODING_VERSION
+        )));
+    }
     let mut valq = ValqType::new("", None, None, None).unwrap_or_default();
+    load_valq_attributes(rdb, encver, salvage, &mut valq)?;
+    load_msgs_attributes(rdb, encver, salvage, &mut valq)?;
+    load_dlq_msgs_attributes(rdb, encver, salvage, &mut valq)?;
+    load_delayed_msgs_attributes(rdb, encver, salvage, &mut valq)?;
+    let max_id = valq
+        .msgs()
+        .iter()
+        .chain(valq.dlq_msgs().iter())
+        .chain(valq.delayed_msgs().members().keys())
+        .map(|msg| *msg.id())
+        .max()
+        .unwrap_or(0);
+    if max_id > *valq.id_sequence() {
+        invalid(
+            salvage,
+            "id sequence",
+            format!(
+                "message id {} above id sequence {}",
+                max_id,
+                valq.id_sequence()
+            ),
+        )?;
+        valq.set_id_sequence(max_id);
+    }
+    Ok(valq)
+}
 
-    for loader in [
-        load_valq_attributes,
-        load_msgs_attributes,
-        load_dlq_msgs_attributes,
-        load_delayed_msgs_attributes,
-    ] {
-        match loader(rdb, &mut valq) {
-            Some(_) => {
-                return std::ptr::null_mut();
-            }
-            None => continue,
-        }
+fn read_unsigned(rdb: &mut impl RdbReader, field: &str) -> Result<u64, ValkeyError> {
+    rdb.read_unsigned()
+        .ok_or_else(|| ValkeyError::String(format!("truncated or corrupt {}", field)))
+}
+
+fn read_string(rdb: &mut impl RdbReader, field: &str) -> Result<String, ValkeyError> {
+    rdb.read_string()
+        .ok_or_else(|| ValkeyError::String(format!("truncated or corrupt {}", field)))
+}
+
+fn read_buffer(rdb: &mut impl RdbReader, field: &str) -> Result<Vec<u8>, ValkeyError> {
+    rdb.read_buffer()
+        .ok_or_else(|| ValkeyError::String(format!("truncated or corrupt {}", field)))
+}
+
+/// Fails the load on a readable but invalid value, in salvage mode it's logged and the load continues.
+fn invalid(salvage: bool, field: &str, reason: String) -> Result<(), ValkeyError> {
+    if salvage {
+        log_warning(format!("rdb_load salvage: invalid {}, {}", field, reason));
+        Ok(())
+    } else {
+        Err(ValkeyError::String(format!(
+            "invalid {}, {}",
+            field, reason
+        )))
     }
+}
 
-    log_notice(format!("rdb_load: {:?}", valq));
-    Box::into_raw(Box::new(valq)) as *mut c_void
+/// Checks the result of a validating setter, the setting keeps its default when it fails in salvage mode.
+fn check(
+    salvage: bool,
+    field: &str,
+    result: Result<String, ValkeyError>,
+) -> Result<(), ValkeyError> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => invalid(salvage, field, err.to_string()),
+    }
 }
 
-fn load_valq_attributes(rdb: *mut RedisModuleIO, valq: &mut ValqType) -> Option<*mut c_void> {
-    let q_name = load_string(rdb).ok()?.to_string();
+fn load_valq_attributes(
+    rdb: &mut impl RdbReader,
+    encver: i32,
+    salvage: bool,
+    valq: &mut ValqType,
+) -> Result<(), ValkeyError> {
+    let q_name = read_string(rdb, "name")?;
     valq.set_name(q_name);
 
-    valq.set_id_sequence(load_unsigned(rdb).ok()?);
+    valq.set_id_sequence(read_unsigned(rdb, "id sequence")?);
+
+    let visibility_timeout = read_unsigned(rdb, "visibility timeout")?;
+    check(
+        salvage,
+        "visibility timeout",
+        valq.set_visibility_timeout(visibility_timeout),
+    )?;
+
+    let max_delivery_attempts = read_unsigned(rdb, "max delivery attempts")?;
+    check(
+        salvage,
+        "max delivery attempts",
+        valq.set_max_delivery_attempts(max_delivery_attempts),
+    )?;
 
-    let visibility_timeout = load_unsigned(rdb).ok()?;
-    valq.set_visibility_timeout(visibility_timeout).ok()?;
+    let retention_period = read_unsigned(rdb, "retention period")?;
+    check(
+        salvage,
+        "retention period",
+        valq.set_retention_period(retention_period),
+    )?;
 
-    let max_delivery_attempts = load_unsigned(rdb).ok()?;
-    valq.set_max_delivery_attempts(max_delivery_attempts).ok()?;
+    if encver < 2 {
+        return Ok(());
+    }
+    let retry_backoff_base = read_unsigned(rdb, "retry backoff base")?;
+    let retry_backoff_max = read_unsigned(rdb, "retry backoff max")?;
+    let retry_backoff_jitter = read_unsigned(rdb, "retry backoff jitter")?;
+    check(
+        salvage,
+        "retry backoff",
+        valq.set_retry_policy(RetryPolicy::new(
+            retry_backoff_base,
+            retry_backoff_max,
+            retry_backoff_jitter,
+        )),
+    )?;
 
-    let retention_period = load_unsigned(rdb).ok()?;
-    valq.set_retention_period(retention_period).ok()?;
+    if encver < 5 {
+        return Ok(());
+    }
+    valq.set_release_on_disconnect(read_unsigned(rdb, "release on disconnect")? == 1);
 
-    None
+    if encver < 6 {
+        return Ok(());
+    }
+    let max_inflight = read_unsigned(rdb, "max inflight")?;
+    check(salvage, "max inflight", valq.set_max_inflight(max_inflight))?;
+
+    if encver < 7 {
+        return Ok(());
+    }
+    let group_max_inflight = read_unsigned(rdb, "group max inflight")?;
+    check(
+        salvage,
+        "group max inflight",
+        valq.set_group_max_inflight(group_max_inflight),
+    )?;
+
+    if encver < 8 {
+        return Ok(());
+    }
+    let rate = read_unsigned(rdb, "rate")?;
+    let per = read_unsigned(rdb, "rate per")?;
+    check(salvage, "rate limit", valq.set_rate_limit(rate, per))?;
+
+    if encver < 9 {
+        return Ok(());
+    }
+    valq.set_paused(read_unsigned(rdb, "paused")? == 1);
+
+    if encver < 10 {
+        return Ok(());
+    }
+    valq.set_max_length(read_unsigned(rdb, "max length")?);
+    valq.set_max_bytes(read_unsigned(rdb, "max bytes")?);
+    let overflow_policy = read_unsigned(rdb, "overflow policy")?;
+    match OverflowPolicy::from_u64(overflow_policy) {
+        Some(tmp) => {
+            valq.set_overflow_policy(tmp);
+        }
+        None => invalid(
+            salvage,
+            "overflow policy",
+            format!("unknown value {}", overflow_policy),
+        )?,
+    }
+    valq.set_overflow_count(read_unsigned(rdb, "overflow count")?);
+
+    if encver < 11 {
+        return Ok(());
+    }
+    let max_msg_size = read_unsigned(rdb, "max msg size")?;
+    check(salvage, "max msg size", valq.set_max_msg_size(max_msg_size))?;
+
+    if encver < 12 {
+        return Ok(());
+    }
+    let claim_check_threshold = read_unsigned(rdb, "claim check threshold")?;
+    check(
+        salvage,
+        "claim check threshold",
+        valq.set_claim_check_threshold(claim_check_threshold),
+    )?;
+
+    if encver < 13 {
+        return Ok(());
+    }
+    let compress_threshold = read_unsigned(rdb, "compress threshold")?;
+    check(
+        salvage,
+        "compress threshold",
+        valq.set_compress_threshold(compress_threshold),
+    )?;
+
+    Ok(())
 }
 
-fn load_msgs_attributes(rdb: *mut RedisModuleIO, valq: &mut ValqType) -> Option<*mut c_void> {
-    let msgs_size = load_unsigned(rdb).unwrap_or(0) as usize;
+fn load_msgs_attributes(
+    rdb: &mut impl RdbReader,
+    encver: i32,
+    salvage: bool,
+    valq: &mut ValqType,
+) -> Result<(), ValkeyError> {
+    let msgs_size = read_unsigned(rdb, "msgs count")?;
     for _ in 0..msgs_size {
-        match load_each_msg(rdb) {
-            Some(msg) => {
-                valq.msgs_mut().push_back(msg);
-            }
-            None => {
-                return Some(std::ptr::null_mut());
-            }
+        if let Some(msg) = load_each_msg(rdb, encver, salvage, "msgs")? {
+            valq.add_msg("msgs", 0, msg)?;
         }
     }
-    None
+    Ok(())
 }
 
-fn load_dlq_msgs_attributes(rdb: *mut RedisModuleIO, valq: &mut ValqType) -> Option<*mut c_void> {
-    let dlq_msgs_size = load_unsigned(rdb).unwrap_or(0) as usize;
+fn load_dlq_msgs_attributes(
+    rdb: &mut impl RdbReader,
+    encver: i32,
+    salvage: bool,
+    valq: &mut ValqType,
+) -> Result<(), ValkeyError> {
+    let dlq_msgs_size = read_unsigned(rdb, "dlq msgs count")?;
     for _ in 0..dlq_msgs_size {
-        match load_each_msg(rdb) {
-            Some(msg) => {
-                valq.dlq_msgs_mut().push_back(msg);
-            }
-            None => {
-                return Some(std::ptr::null_mut());
-            }
+        if let Some(msg) = load_each_msg(rdb, encver, salvage, "dlq msgs")? {
+            valq.dlq_msgs_mut().push_back(msg);
         }
     }
-    None
+    Ok(())
 }
 
 fn load_delayed_msgs_attributes(
-    rdb: *mut RedisModuleIO,
+    rdb: &mut impl RdbReader,
+    encver: i32,
+    salvage: bool,
     valq: &mut ValqType,
-) -> Option<*mut c_void> {
-    let delayed_msg_size = load_unsigned(rdb).unwrap_or(0);
+) -> Result<(), ValkeyError> {
+    let delayed_msg_size = read_unsigned(rdb, "delayed msgs count")?;
     for _ in 0..delayed_msg_size {
         // load the score for the delayed message
-        let score = load_unsigned(rdb).ok()?;
+        let score = read_unsigned(rdb, "delayed msgs score")?;
         // load the message itself
-        match load_each_msg(rdb) {
-            Some(msg) => {
-                valq.delayed_msgs_mut().insert(msg, score);
-            }
-            None => {
-                return Some(std::ptr::null_mut());
-            }
+        if let Some(msg) = load_each_msg(rdb, encver, salvage, "delayed msgs")? {
+            valq.add_msg("delayed", score, msg)?;
         }
     }
-    None
+    Ok(())
 }
 
-fn load_each_msg(rdb: *mut RedisModuleIO) -> Option<ValqMsg> {
-    let id = load_unsigned(rdb).ok()?;
-    let body = load_string(rdb).ok()?.to_string();
+/// Loads one message of `list`, `None` when it's skipped in salvage mode.
+fn load_each_msg(
+    rdb: &mut impl RdbReader,
+    encver: i32,
+    salvage: bool,
+    list: &str,
+) -> Result<Option<ValqMsg>, ValkeyError> {
+    let field = |name: &str| format!("{} {}", list, name);
+    let id = read_unsigned(rdb, &field("id"))?;
+    let field = |name: &str| format!("{} {} of id {}", list, name, id);
+    let body = read_string(rdb, &field("body"))?;
     // if the timeout_at is 0, it will be loaded as None
     // if the timeout_at is Some, it will be loaded as the actual value
-    let timeout_at = load_unsigned(rdb).ok().filter(|&tmp| tmp > 0);
-    let delivery_attempts = load_unsigned(rdb).ok()?;
-    let msg = ValqMsg::new(id, body, timeout_at, delivery_attempts);
-    Some(msg)
+    let timeout_at = Some(read_unsigned(rdb, &field("timeout at"))?).filter(|&tmp| tmp > 0);
+    let delivery_attempts = read_unsigned(rdb, &field("delivery attempts"))?;
+    let mut msg = ValqMsg::new(id, body, timeout_at, delivery_attempts);
+    if encver < 3 {
+        return Ok(Some(msg));
+    }
+    // per-message overrides are saved as 0 when not set
+    msg.set_max_delivery_attempts(
+        Some(read_unsigned(rdb, &field("max delivery attempts"))?).filter(|&tmp| tmp > 0),
+    );
+    msg.set_visibility_timeout(
+        Some(read_unsigned(rdb, &field("visibility timeout"))?).filter(|&tmp| tmp > 0),
+    );
+    if encver < 4 {
+        return Ok(Some(msg));
+    }
+    // lease owner is saved as empty string and leased_at as 0 when not set
+    let consumer = read_string(rdb, &field("consumer"))?;
+    msg.set_consumer(Some(consumer).filter(|tmp| !tmp.is_empty()));
+    msg.set_leased_at(Some(read_unsigned(rdb, &field("leased at"))?).filter(|&tmp| tmp > 0));
+    if encver < 7 {
+        return Ok(Some(msg));
+    }
+    // group is saved as empty string when not set
+    let group = read_string(rdb, &field("group"))?;
+    msg.set_group(Some(group).filter(|tmp| !tmp.is_empty()));
+    if encver < 12 {
+        return Ok(Some(msg));
+    }
+    // companion key is saved as empty string when the body is stored in the message
+    let body_key = read_string(rdb, &field("body key"))?;
+    msg.set_body_key(Some(body_key).filter(|tmp| !tmp.is_empty()));
+    msg.set_body_key_len(read_unsigned(rdb, &field("body key length"))?);
+    if encver < 13 {
+        return Ok(Some(msg));
+    }
+    // encoding, the compressed body is empty for raw bodies
+    let encoding = read_unsigned(rdb, &field("body encoding"))?;
+    msg.set_compressed_body(read_buffer(rdb, &field("compressed body"))?);
+    match BodyEncoding::from_u64(encoding) {
+        Some(tmp) => msg.set_encoding(tmp),
+        None => {
+            // the body can't be decoded, skip the message
+            invalid(
+                salvage,
+                &field("body encoding"),
+                format!("unknown value {}, message skipped", encoding),
+            )?;
+            return Ok(None);
+        }
+    };
+    Ok(Some(msg))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::VISIBILITY_TIMEOUT_DEFAULT;
+    use crate::data_types::rdb_io::fixture::{Field, Rdb, s, u};
+    use crate::data_types::rdb_save::save_valq;
 
     #[test]
     fn rdb_load_null_pointer() {
         let result = rdb_load(std::ptr::null_mut(), 0);
         assert!(result.is_null());
     }
+
+    fn v1_msg(id: u64, body: &str, timeout_at: u64, attempts: u64) -> Vec<Field> {
+        vec![u(id), s(body), u(timeout_at), u(attempts)]
+    }
+
+    /// Queue `q1` with one leased message, one DLQ message and one delayed message as saved by version 1.
+    fn v1_fixture() -> Vec<Field> {
+        let mut fields = vec![s("q1"), u(4), u(30), u(5), u(86400)];
+        fields.push(u(1));
+        fields.extend(v1_msg(2, "msg2", 1000, 1));
+        fields.push(u(1));
+        fields.extend(v1_msg(1, "msg1", 0, 5));
+        fields.push(u(1));
+        fields.push(u(2000));
+        fields.extend(v1_msg(3, "msg3", 0, 0));
+        fields
+    }
+
+    #[test]
+    fn test_load_v1() {
+        let valq = load_valq(&mut Rdb::new(v1_fixture()), 1, false).unwrap();
+        assert_eq!(valq.name(), "q1");
+        assert_eq!(*valq.id_sequence(), 4);
+        assert_eq!(*valq.visibility_timeout(), 30);
+        assert_eq!(*valq.max_delivery_attempts(), 5);
+        assert_eq!(*valq.retention_period(), 86400);
+        // settings added in later versions get their defaults
+        let default = ValqType::new("q1", Some(30), Some(5), Some(86400)).unwrap();
+        assert_eq!(valq.retry_policy(), default.retry_policy());
+        assert_eq!(valq.max_inflight(), default.max_inflight());
+        assert_eq!(valq.overflow_policy(), default.overflow_policy());
+        assert_eq!(valq.compress_threshold(), default.compress_threshold());
+        assert!(!*valq.paused());
+        let msg = valq.msgs().front().unwrap();
+        assert_eq!(*msg, ValqMsg::new(2, "msg2".to_string(), Some(1000), 1));
+        assert_eq!(*msg.encoding(), BodyEncoding::Raw);
+        assert_eq!(*msg.consumer(), None);
+        assert_eq!(valq.dlq_msgs().len(), 1);
+        let msg = ValqMsg::new(3, "msg3".to_string(), None, 0);
+        assert_eq!(valq.delayed_msgs().members().get(&msg), Some(&2000));
+    }
+
+    #[test]
+    fn test_load_v13() {
+        let mut fields = vec![s("q1"), u(2), u(30), u(5), u(86400)];
+        // backoff, release on disconnect, max inflight and group max inflight
+        fields.extend([u(1), u(300), u(10), u(1), u(10), u(2)]);
+        // rate limit and paused
+        fields.extend([u(5), u(60), u(1)]);
+        // max length, max bytes, overflow policy and count, max msg size, claim check, compress
+        fields.extend([u(100), u(0), u(2), u(3), u(0), u(0), u(16)]);
+        fields.push(u(1));
+        fields.extend(v1_msg(2, "msg2", 1000, 1));
+        fields.extend([u(3), u(60), s("worker1"), u(940), s("tenant1"), s(""), u(0)]);
+        fields.extend([u(0), Field::Bytes(vec![])]);
+        fields.extend([u(0), u(0)]);
+        let valq = load_valq(&mut Rdb::new(fields), 13, false).unwrap();
+        assert_eq!(*valq.retry_policy().max(), 300);
+        assert!(*valq.release_on_disconnect());
+        assert_eq!(*valq.group_max_inflight(), 2);
+        assert_eq!(*valq.rate_limiter().per(), 60);
+        assert!(*valq.paused());
+        assert_eq!(*valq.max_length(), 100);
+        assert_eq!(valq.overflow_policy().as_u64(), 2);
+        assert_eq!(*valq.overflow_count(), 3);
+        assert_eq!(*valq.compress_threshold(), 16);
+        let msg = valq.msgs().front().unwrap();
+        assert_eq!(*msg.max_delivery_attempts(), Some(3));
+        assert_eq!(*msg.consumer(), Some("worker1".to_string()));
+        assert_eq!(*msg.group(), Some("tenant1".to_string()));
+        assert_eq!(*msg.body_key(), None);
+    }
+
+    /// Queue `q1` with one leased message as saved by each version, see `rdb_io` for the fields.
+    fn versioned_fixture(encver: i32) -> Vec<Field> {
+        let since = |version: i32, fields: Vec<Field>| {
+            if encver >= version { fields } else { vec![] }
+        };
+        let mut fields = vec![s("q1"), u(2), u(30), u(5), u(86400)];
+        fields.extend(since(2, vec![u(1), u(300), u(10)]));
+        fields.extend(since(5, vec![u(1)]));
+        fields.extend(since(6, vec![u(10)]));
+        fields.extend(since(7, vec![u(2)]));
+        fields.extend(since(8, vec![u(5), u(60)]));
+        fields.extend(since(9, vec![u(1)]));
+        fields.extend(since(10, vec![u(100), u(0), u(2), u(3)]));
+        fields.extend(since(11, vec![u(1024)]));
+        fields.extend(since(12, vec![u(512)]));
+        fields.extend(since(13, vec![u(16)]));
+        fields.push(u(1));
+        fields.extend(v1_msg(2, "msg2", 1000, 1));
+        fields.extend(since(3, vec![u(3), u(60)]));
+        fields.extend(since(4, vec![s("worker1"), u(940)]));
+        fields.extend(since(7, vec![s("tenant1")]));
+        fields.extend(since(12, vec![s(""), u(0)]));
+        fields.extend(since(13, vec![u(0), Field::Bytes(vec![])]));
+        fields.extend([u(0), u(0)]);
+        fields
+    }
+
+    #[test]
+    fn test_load_each_version() {
+        for encver in 1..=ENCODING_VERSION {
+            let mut rdb = Rdb::new(versioned_fixture(encver));
+            let valq = load_valq(&mut rdb, encver, false).unwrap();
+            assert!(rdb.0.is_empty(), "version {}", encver);
+            // fields added after encver keep their defaults
+            let loaded = |version: i32| encver >= version;
+            assert_eq!(*valq.retry_policy().max() == 300, loaded(2));
+            assert_eq!(*valq.release_on_disconnect(), loaded(5));
+            assert_eq!(*valq.max_inflight() == 10, loaded(6));
+            assert_eq!(*valq.group_max_inflight() == 2, loaded(7));
+            assert_eq!(*valq.rate_limiter().per() == 60, loaded(8));
+            assert_eq!(*valq.paused(), loaded(9));
+            assert_eq!(*valq.overflow_count() == 3, loaded(10));
+            assert_eq!(*valq.max_msg_size() == 1024, loaded(11));
+            assert_eq!(*valq.claim_check_threshold() == 512, loaded(12));
+            assert_eq!(*valq.compress_threshold() == 16, loaded(13));
+            let msg = valq.msgs().front().unwrap();
+            assert_eq!(msg.body(), "msg2");
+            assert_eq!(*msg.visibility_timeout() == Some(60), loaded(3));
+            assert_eq!(msg.consumer().is_some(), loaded(4));
+            assert_eq!(msg.group().is_some(), loaded(7));
+        }
+    }
+
+    #[test]
+    fn test_load_latest_round_trip() {
+        let mut valq = ValqType::new("q1", Some(30), Some(5), Some(86400)).unwrap();
+        let _ = valq.set_compress_threshold(16);
+        let _ = valq.set_rate_limit(5, 60);
+        let mut msg = ValqMsg::new(1, "{\"status\":\"ok\"}".repeat(10), None, 0);
+        msg.compress();
+        valq.msgs_mut().push_back(msg);
+        let mut msg = ValqMsg::new(2, "msg2".to_string(), Some(1000), 1);
+        // client id is not saved
+        msg.lease(Some("worker1".to_string()), None, 940);
+        valq.dlq_msgs_mut().push_back(msg);
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(3, "msg3".to_string(), None, 0), 2000);
+        let mut rdb = Rdb::default();
+        save_valq(&mut rdb, &valq);
+        let test = load_valq(&mut rdb, ENCODING_VERSION, false).unwrap();
+        assert!(rdb.0.is_empty());
+        assert_eq!(format!("{:?}", test), format!("{:?}", valq));
+    }
+
+    #[test]
+    fn test_load_unsupported_version() {
+        assert!(load_valq(&mut Rdb::new(v1_fixture()), 0, false).is_err());
+        assert!(load_valq(&mut Rdb::new(v1_fixture()), ENCODING_VERSION + 1, false).is_err());
+    }
+
+    #[test]
+    fn test_load_wrong_version() {
+        // a version 1 value can't be read with the latest layout
+        assert!(load_valq(&mut Rdb::new(v1_fixture()), ENCODING_VERSION, false).is_err());
+    }
+
+    fn latest_fixture() -> Vec<Field> {
+        let mut valq = ValqType::new("q1", Some(30), Some(5), Some(86400)).unwrap();
+        valq.set_id_sequence(3);
+        let _ = valq.set_compress_threshold(16);
+        let mut msg = ValqMsg::new(1, "{\"status\":\"ok\"}".repeat(10), None, 0);
+        msg.compress();
+        valq.msgs_mut().push_back(msg);
+        let mut msg = ValqMsg::new(2, "msg2".to_string(), Some(1000), 1);
+        msg.lease(Some("worker1".to_string()), None, 940);
+        valq.dlq_msgs_mut().push_back(msg);
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(3, "msg3".to_string(), None, 0), 2000);
+        let mut rdb = Rdb::default();
+        save_valq(&mut rdb, &valq);
+        rdb.0.into()
+    }
+
+    fn error(result: Result<ValqType, ValkeyError>) -> String {
+        match result {
+            Ok(_) => panic!("load should fail"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let fields = latest_fixture();
+        for len in 0..fields.len() {
+            let mut rdb = Rdb::new(fields[..len].to_vec());
+            assert!(load_valq(&mut rdb, ENCODING_VERSION, false).is_err());
+            // salvage mode can't skip missing fields
+            let mut rdb = Rdb::new(fields[..len].to_vec());
+            assert!(load_valq(&mut rdb, ENCODING_VERSION, true).is_err());
+        }
+        let test = error(load_valq(
+            &mut Rdb::new(fields[..2].to_vec()),
+            ENCODING_VERSION,
+            false,
+        ));
+        assert!(test.contains("visibility timeout"));
+        let test = error(load_valq(
+            &mut Rdb::new(v1_fixture()[..7].to_vec()),
+            1,
+            false,
+        ));
+        assert!(test.contains("msgs body of id 2"));
+    }
+
+    #[test]
+    fn test_load_invalid_setting() {
+        let mut fields = latest_fixture();
+        // visibility timeout above the max
+        fields[2] = u(u64::MAX);
+        let test = error(load_valq(
+            &mut Rdb::new(fields.clone()),
+            ENCODING_VERSION,
+            false,
+        ));
+        assert!(test.contains("invalid visibility timeout"));
+        let valq = load_valq(&mut Rdb::new(fields.clone()), ENCODING_VERSION, true).unwrap();
+        assert_eq!(*valq.visibility_timeout(), VISIBILITY_TIMEOUT_DEFAULT);
+        assert_eq!(valq.msgs().len(), 1);
+        // unknown overflow policy
+        let mut fields = latest_fixture();
+        fields[16] = u(9);
+        let test = error(load_valq(
+            &mut Rdb::new(fields.clone()),
+            ENCODING_VERSION,
+            false,
+        ));
+        assert!(test.contains("invalid overflow policy"));
+        assert!(load_valq(&mut Rdb::new(fields), ENCODING_VERSION, true).is_ok());
+    }
+
+    #[test]
+    fn test_load_invalid_msg() {
+        let mut fields = latest_fixture();
+        // body encoding of the first message
+        fields[33] = u(9);
+        let test = error(load_valq(
+            &mut Rdb::new(fields.clone()),
+            ENCODING_VERSION,
+            false,
+        ));
+        assert!(test.contains("msgs body encoding of id 1"));
+        let valq = load_valq(&mut Rdb::new(fields), ENCODING_VERSION, true).unwrap();
+        assert_eq!(valq.msgs().len(), 0);
+        assert_eq!(valq.dlq_msgs().len(), 1);
+        assert_eq!(valq.delayed_msgs().len(), 1);
+        // message id above the id sequence
+        let mut fields = latest_fixture();
+        fields[1] = u(2);
+        let test = error(load_valq(
+            &mut Rdb::new(fields.clone()),
+            ENCODING_VERSION,
+            false,
+        ));
+        assert!(test.contains("id sequence"));
+        let valq = load_valq(&mut Rdb::new(fields), ENCODING_VERSION, true).unwrap();
+        assert_eq!(*valq.id_sequence(), 3);
+    }
+
+    /// Deterministic xorshift so failures can be reproduced from the seed.
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_load_fuzz() {
+        let fields = latest_fixture();
+        for seed in 1..2000u64 {
+            let mut state = seed;
+            let mut test = fields.clone();
+            for _ in 0..1 + next_random(&mut state) % 4 {
+                let pos = (next_random(&mut state) % test.len() as u64) as usize;
+                match next_random(&mut state) % 5 {
+                    0 => test[pos] = u(next_random(&mut state)),
+                    1 => test[pos] = u(next_random(&mut state) % 8),
+                    2 => {
+                        let len = (next_random(&mut state) % 32) as usize;
+                        let bytes = (0..len).map(|_| next_random(&mut state) as u8).collect();
+                        test[pos] = Field::Bytes(bytes);
+                    }
+                    3 => {
+                        test.remove(pos);
+                    }
+                    _ => test.insert(pos, u(next_random(&mut state) % 8)),
+                }
+                if test.is_empty() {
+                    break;
+                }
+            }
+            for salvage in [false, true] {
+                // corrupt values must fail or load a consistent queue, never panic
+                if let Ok(valq) = load_valq(&mut Rdb::new(test.clone()), ENCODING_VERSION, salvage)
+                {
+                    let max_id = valq
+                        .msgs()
+                        .iter()
+                        .chain(valq.dlq_msgs().iter())
+                        .map(|msg| *msg.id())
+                        .max()
+                        .unwrap_or(0);
+                    assert!(max_id <= *valq.id_sequence(), "seed {}", seed);
+                    let _ = valq.mem_usage();
+                    for msg in valq.msgs() {
+                        let _ = msg.decoded_body();
+                    }
+                }
+            }
+        }
+    }
 }
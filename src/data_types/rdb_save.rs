@@ -1,14 +1,15 @@
+use crate::data_types::rdb_io::{RdbIo, RdbWriter};
 use crate::structs::valq_msg::ValqMsg;
 use crate::structs::valq_type::ValqType;
 use std::os::raw::c_void;
-use valkey_module::logging::log_notice;
-use valkey_module::{RedisModuleIO, save_string, save_unsigned};
+use valkey_module::RedisModuleIO;
 
 /// Saves the state of a `ValqType` instance to the Valkey database.
 ///
 /// This function is called by the Valkey module to persist the state of a `ValqType`
 /// instance. It serializes the fields of the `ValqType` and its associated messages
 /// in a specific order to ensure compatibility with the corresponding load function.
+/// Values are always saved with the latest layout described in `rdb_io`.
 ///
 /// # Arguments
 /// * `rdb` - A pointer to the RedisModuleIO structure used for saving data.
@@ -22,69 +23,112 @@ pub(crate) extern "C" fn rdb_save(rdb: *mut RedisModuleIO, value: *mut c_void) {
         return;
     }
     let item = unsafe { &*value.cast::<ValqType>() };
-    // save and load must be in the same order
+    save_valq(&mut RdbIo(rdb), item);
+}
+
+/// Saves the queue and its messages, save and load must be in the same order.
+pub(crate) fn save_valq(rdb: &mut impl RdbWriter, item: &ValqType) {
     save_valq_attributes(rdb, item);
     save_msgs_attributes(rdb, item);
     save_dlq_msgs_attributes(rdb, item);
     save_delayed_msgs_attributes(rdb, item);
-    // log the saved item
-    log_notice(format!("rdb_save: {:?}", item));
 }
 
-fn save_valq_attributes(rdb: *mut RedisModuleIO, item: &ValqType) {
+fn save_valq_attributes(rdb: &mut impl RdbWriter, item: &ValqType) {
     // save name
-    save_string(rdb, item.name().as_str());
+    rdb.write_string(item.name().as_str());
     // save id_sequence
-    save_unsigned(rdb, *item.id_sequence());
+    rdb.write_unsigned(*item.id_sequence());
     // save visibility_timeout
-    save_unsigned(rdb, *item.visibility_timeout());
+    rdb.write_unsigned(*item.visibility_timeout());
     // save max_delivery_attempts
-    save_unsigned(rdb, *item.max_delivery_attempts());
+    rdb.write_unsigned(*item.max_delivery_attempts());
     // save retention_period
-    save_unsigned(rdb, *item.retention_period());
+    rdb.write_unsigned(*item.retention_period());
+    // save retry_policy
+    rdb.write_unsigned(*item.retry_policy().base());
+    rdb.write_unsigned(*item.retry_policy().max());
+    rdb.write_unsigned(*item.retry_policy().jitter());
+    // save release_on_disconnect as 0 or 1
+    rdb.write_unsigned(*item.release_on_disconnect() as u64);
+    // save max_inflight
+    rdb.write_unsigned(*item.max_inflight());
+    // save group_max_inflight
+    rdb.write_unsigned(*item.group_max_inflight());
+    // save rate limit, the bucket starts full after load
+    rdb.write_unsigned(*item.rate_limiter().rate());
+    rdb.write_unsigned(*item.rate_limiter().per());
+    // save paused as 0 or 1
+    rdb.write_unsigned(*item.paused() as u64);
+    // save max_length, max_bytes, overflow_policy and overflow_count
+    rdb.write_unsigned(*item.max_length());
+    rdb.write_unsigned(*item.max_bytes());
+    rdb.write_unsigned(item.overflow_policy().as_u64());
+    rdb.write_unsigned(*item.overflow_count());
+    // save max_msg_size, 0 when the module default applies
+    rdb.write_unsigned(*item.max_msg_size());
+    // save claim_check_threshold
+    rdb.write_unsigned(*item.claim_check_threshold());
+    // save compress_threshold
+    rdb.write_unsigned(*item.compress_threshold());
 }
 
-fn save_msgs_attributes(rdb: *mut RedisModuleIO, item: &ValqType) {
+fn save_msgs_attributes(rdb: &mut impl RdbWriter, item: &ValqType) {
     // save the size of the msgs VecDeque
-    save_unsigned(rdb, item.msgs().len() as u64);
+    rdb.write_unsigned(item.msgs().len() as u64);
     // save each message in the msgs VecDeque
     item.msgs().iter().for_each(|msg| {
         save_each_msg(rdb, msg);
     });
 }
 
-fn save_dlq_msgs_attributes(rdb: *mut RedisModuleIO, item: &ValqType) {
+fn save_dlq_msgs_attributes(rdb: &mut impl RdbWriter, item: &ValqType) {
     // save the size of the dlq_msgs VecDeque
-    save_unsigned(rdb, item.dlq_msgs().len() as u64);
+    rdb.write_unsigned(item.dlq_msgs().len() as u64);
     // save each message in the dlq_msgs VecDeque
     item.dlq_msgs().iter().for_each(|msg| {
         save_each_msg(rdb, msg);
     });
 }
 
-fn save_delayed_msgs_attributes(rdb: *mut RedisModuleIO, item: &ValqType) {
+fn save_delayed_msgs_attributes(rdb: &mut impl RdbWriter, item: &ValqType) {
     // save the size of the delayed_msgs
-    save_unsigned(rdb, item.delayed_msgs().len());
+    rdb.write_unsigned(item.delayed_msgs().len());
     // save each message in the delayed_msgs
     item.delayed_msgs()
         .members()
         .iter()
         .for_each(|(msg, score)| {
             // save the score for the delayed message
-            save_unsigned(rdb, *score);
+            rdb.write_unsigned(*score);
             // save the message itself
             save_each_msg(rdb, msg);
         });
 }
 
-fn save_each_msg(rdb: *mut RedisModuleIO, msg: &ValqMsg) {
+fn save_each_msg(rdb: &mut impl RdbWriter, msg: &ValqMsg) {
     // save id
-    save_unsigned(rdb, *msg.id());
+    rdb.write_unsigned(*msg.id());
     // save body
-    save_string(rdb, msg.body().as_str());
+    rdb.write_string(msg.body().as_str());
     // if timeout_at is None, it will be saved as 0
     // if timeout_at is Some, it will be saved as the actual value
-    save_unsigned(rdb, msg.timeout_at().unwrap_or(0));
+    rdb.write_unsigned(msg.timeout_at().unwrap_or(0));
     // save delivery_attempts
-    save_unsigned(rdb, *msg.delivery_attempts());
+    rdb.write_unsigned(*msg.delivery_attempts());
+    // per-message overrides are saved as 0 when not set
+    rdb.write_unsigned(msg.max_delivery_attempts().unwrap_or(0));
+    rdb.write_unsigned(msg.visibility_timeout().unwrap_or(0));
+    // lease owner is saved as empty string and leased_at as 0 when not set
+    rdb.write_string(msg.consumer().as_deref().unwrap_or(""));
+    rdb.write_unsigned(msg.leased_at().unwrap_or(0));
+    // group is saved as empty string when not set
+    rdb.write_string(msg.group().as_deref().unwrap_or(""));
+    // companion key is saved as empty string when the body is stored in the message
+    rdb.write_string(msg.body_key().as_deref().unwrap_or(""));
+    rdb.write_unsigned(*msg.body_key_len());
+    // save encoding, the compressed body is empty for raw bodies
+    rdb.write_unsigned(msg.encoding().as_u64());
+    rdb.write_buffer(msg.compressed_body());
+    // client_id is not saved, client IDs are only unique within one server run
 }
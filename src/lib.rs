@@ -3,14 +3,20 @@ mod data_types;
 mod structs;
 mod utils;
 
-use crate::commands::valq_cmd;
 use crate::data_types::VALQ_TYPE;
-use crate::utils::{retention_period_gc, valid_server_version};
-use std::collections::HashSet;
+use crate::structs::q_registry::QRegistry;
+use crate::utils::{
+    claim_check, flush_db, q_registry, release_on_disconnect, retention_period_gc,
+    valid_server_version,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicI64};
 use std::sync::{LazyLock, RwLock};
 use std::thread;
 use std::time::Duration;
 use valkey_module::alloc::ValkeyAlloc;
+use valkey_module::configuration::ConfigurationFlags;
+use valkey_module::raw::ModuleOptions;
 use valkey_module::{Context, Status, ThreadSafeContext, ValkeyString, valkey_module};
 
 static MIN_VALID_SERVER_VERSION: &[i32; 3] = &[7, 2, 8];
@@ -21,7 +27,27 @@ static DELIVERY_ATTEMPTS_MAX: u64 = 20;
 static RETENTION_PERIOD_DEFAULT: u64 = 86_400; // 1 day
 static RETENTION_PERIOD_MAX: u64 = 604_800; // 7 days
 static RETENTION_PERIOD_MIN: u64 = 60;
-static GLOBAL_Q_LIST: LazyLock<RwLock<HashSet<String>>> =
+static RETRY_BACKOFF_MAX: u64 = 43_200; // 12 hours
+static RETRY_JITTER_MAX: u64 = 100; // percent
+static MAX_INFLIGHT_MAX: u64 = 100_000;
+static RATE_LIMIT_MAX: u64 = 1_000_000;
+static RATE_PER_MAX: u64 = 86_400; // 1 day
+static MAX_MSG_SIZE_MAX: u64 = 536_870_912; // 512 MB, same as proto-max-bulk-len
+static MAX_MSG_SIZE_DEFAULT: i64 = MAX_MSG_SIZE_MAX as i64; // as unlimited as pushes were before
+/// Module-wide max message body size in bytes, set with `valq.max-msg-size`.
+static MAX_MSG_SIZE: AtomicI64 = AtomicI64::new(MAX_MSG_SIZE_DEFAULT);
+/// Release in-flight messages in the copy made by `COPY`, set with `valq.copy-reset-leases`.
+static COPY_RESET_LEASES: AtomicBool = AtomicBool::new(false);
+/// Load invalid settings as defaults and skip unreadable messages in `rdb_load`, set with `valq.rdb-load-salvage`.
+static RDB_LOAD_SALVAGE: AtomicBool = AtomicBool::new(false);
+/// Queues of the keyspace by db and key, see `utils::q_registry`.
+static GLOBAL_Q_REGISTRY: LazyLock<RwLock<QRegistry>> =
+    LazyLock::new(|| RwLock::new(QRegistry::new()));
+/// Companion keys of queues unlinked by the current command by db and key, see `utils::claim_check`.
+static UNLINKED_BODY_KEYS: LazyLock<RwLock<BTreeMap<(i32, String), HashSet<String>>>> =
+    LazyLock::new(|| RwLock::new(BTreeMap::new()));
+/// Clients holding leases on queues with release on disconnect, see `utils::release_on_disconnect`.
+static RELEASE_ON_DISCONNECT_CLIENTS: LazyLock<RwLock<HashSet<u64>>> =
     LazyLock::new(|| RwLock::new(HashSet::new()));
 
 fn preload(ctx: &Context, _args: &[ValkeyString]) -> Status {
@@ -34,7 +60,28 @@ fn preload(ctx: &Context, _args: &[ValkeyString]) -> Status {
     }
 }
 
-fn init(_ctx: &Context, _args: &[ValkeyString]) -> Status {
+fn init(ctx: &Context, _args: &[ValkeyString]) -> Status {
+    // report truncated or corrupt RDB data to the loaders instead of aborting the server,
+    // keys are signalled as modified by `utils::notify` only when a command changes them
+    ctx.set_module_options(
+        ModuleOptions::HANDLE_IO_ERRORS | ModuleOptions::NO_IMPLICIT_SIGNAL_MODIFIED,
+    );
+    if release_on_disconnect::subscribe(ctx) == Status::Err {
+        ctx.log_notice("can't subscribe to client change events");
+        return Status::Err;
+    }
+    if flush_db::subscribe(ctx) == Status::Err {
+        ctx.log_notice("can't subscribe to flush events");
+        return Status::Err;
+    }
+    if q_registry::subscribe(ctx) == Status::Err {
+        ctx.log_notice("can't subscribe to keyspace, swapdb and loading events");
+        return Status::Err;
+    }
+    if claim_check::subscribe(ctx) == Status::Err {
+        ctx.log_notice("can't subscribe to keyspace events for companion keys");
+        return Status::Err;
+    }
     thread::spawn(move || {
         let ts_ctx = ThreadSafeContext::new();
         loop {
@@ -55,6 +102,36 @@ valkey_module! {
     preload: preload,
     init: init,
     commands: [
-        ["valq", valq_cmd, "", 0, 0, 0],
+        // the key follows the subcommand, `valq list` and `valq help` have no key.
+        // flags apply to all subcommands, so the read subcommands count as writes and are rejected
+        // on replicas, and deny-oom also rejects ack, delete and purge when out of memory
+        ["valq", commands::valq_cmd, "write deny-oom", 2, 2, 1],
+        ["valq.create", commands::valq_create, "write deny-oom", 1, 1, 1],
+        ["valq.delete", commands::valq_delete, "write", 1, 1, 1],
+        ["valq.update", commands::valq_update, "write", 1, 1, 1],
+        ["valq.list", commands::valq_list, "readonly", 0, 0, 0],
+        ["valq.info", commands::valq_info, "readonly", 1, 1, 1],
+        ["valq.purge", commands::valq_purge, "write", 1, 1, 1],
+        ["valq.pause", commands::valq_pause, "write fast", 1, 1, 1],
+        ["valq.resume", commands::valq_resume, "write fast", 1, 1, 1],
+        ["valq.push", commands::valq_push, "write deny-oom", 1, 1, 1],
+        ["valq.pop", commands::valq_pop, "write", 1, 1, 1],
+        ["valq.ack", commands::valq_ack, "write", 1, 1, 1],
+        ["valq.extend", commands::valq_extend, "write", 1, 1, 1],
+        ["valq.pending", commands::valq_pending, "readonly", 1, 1, 1],
+        ["valq.claim", commands::valq_claim, "write", 1, 1, 1],
+        ["valq.restore", commands::valq_restore, "write deny-oom", 1, 1, 1],
+        ["valq.apply", commands::valq_apply, "write deny-oom", 1, 1, 1],
+        ["valq.help", commands::valq_help, "readonly fast", 0, 0, 0],
     ],
+    configurations: [
+        i64: [
+            ["max-msg-size", &MAX_MSG_SIZE, MAX_MSG_SIZE_DEFAULT, 1, MAX_MSG_SIZE_MAX as i64, ConfigurationFlags::MEMORY, None],
+        ],
+        bool: [
+            ["copy-reset-leases", &COPY_RESET_LEASES, false, ConfigurationFlags::DEFAULT, None],
+            ["rdb-load-salvage", &RDB_LOAD_SALVAGE, false, ConfigurationFlags::DEFAULT, None],
+        ],
+        module_args_as_configuration: true,
+    ]
 }
@@ -0,0 +1,41 @@
+/// How the body of a message is stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum BodyEncoding {
+    /// Body is stored as is.
+    #[default]
+    Raw,
+    /// Body is compressed with deflate.
+    Deflate,
+}
+
+impl BodyEncoding {
+    /// Converts the encoding to the number saved in the RDB.
+    pub(crate) fn as_u64(&self) -> u64 {
+        match self {
+            Self::Raw => 0,
+            Self::Deflate => 1,
+        }
+    }
+
+    /// Converts the number saved in the RDB back to the encoding.
+    pub(crate) fn from_u64(input: u64) -> Option<Self> {
+        match input {
+            0 => Some(Self::Raw),
+            1 => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u64_round_trip() {
+        for encoding in [BodyEncoding::Raw, BodyEncoding::Deflate] {
+            assert_eq!(BodyEncoding::from_u64(encoding.as_u64()), Some(encoding));
+        }
+        assert_eq!(BodyEncoding::from_u64(2), None);
+    }
+}
@@ -2,6 +2,7 @@ use crate::structs::valq_msg::ValqMsg;
 use crate::utils;
 use getset::Getters;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
 
 #[derive(Debug, Clone, Default, Getters)]
 pub(crate) struct DelayedMsgs {
@@ -52,6 +53,13 @@ impl DelayedMsgs {
         }
     }
 
+    /// Removes the message with `id` and returns it.
+    pub(crate) fn take(&mut self, id: u64) -> Option<ValqMsg> {
+        let member = self.members.keys().find(|msg| *msg.id() == id).cloned()?;
+        self.remove(&member);
+        Some(member)
+    }
+
     pub(crate) fn clear(&mut self) {
         self.scores.clear();
         self.members.clear();
@@ -61,6 +69,56 @@ impl DelayedMsgs {
         self.members.len() as u64
     }
 
+    /// Memory used by both maps in bytes, each message is stored once in `members` and once in `scores`.
+    /// The hash map adds one control byte per bucket, B-tree node overhead is not included.
+    pub(crate) fn mem_usage(&self) -> usize {
+        let members = self.members.capacity() * (size_of::<ValqMsg>() + size_of::<u64>() + 1);
+        let scores = self.scores.len() * (size_of::<u64>() + size_of::<BTreeSet<ValqMsg>>());
+        let msgs = self
+            .members
+            .keys()
+            .map(|msg| size_of::<ValqMsg>() + 2 * msg.heap_usage())
+            .sum::<usize>();
+        size_of::<Self>() + members + scores + msgs
+    }
+
+    /// Passes the heap buffers of the messages to `realloc`, one score bucket at a time starting at `from`.
+    /// Returns the score to resume from when `should_stop` asks to stop before all buckets are done.
+    pub(crate) fn defrag(
+        &mut self,
+        from: u64,
+        realloc: &mut impl FnMut(&mut Vec<u8>),
+        should_stop: &impl Fn() -> bool,
+    ) -> Option<u64> {
+        let mut next = self.scores.range(from..).next().map(|(score, _)| *score);
+        while let Some(score) = next {
+            if should_stop() {
+                return Some(score);
+            }
+            // set members and map keys can't be borrowed mutably, so they are taken out and put back
+            if let Some(set) = self.scores.remove(&score) {
+                let set = set
+                    .into_iter()
+                    .map(|mut msg| {
+                        if let Some((mut member, score)) = self.members.remove_entry(&msg) {
+                            member.defrag(realloc);
+                            self.members.insert(member, score);
+                        }
+                        msg.defrag(realloc);
+                        msg
+                    })
+                    .collect();
+                self.scores.insert(score, set);
+            }
+            next = self
+                .scores
+                .range((Bound::Excluded(score), Bound::Unbounded))
+                .next()
+                .map(|(score, _)| *score);
+        }
+        None
+    }
+
     pub(crate) fn ready_to_process(&self) -> Vec<(u64, &ValqMsg)> {
         // get all members with their scores in the range of 0 to now
         let min = 0;
@@ -101,6 +159,17 @@ mod tests {
         assert_eq!(delayed_msgs.len(), 0);
     }
 
+    #[test]
+    fn test_take() {
+        let mut delayed_msgs = DelayedMsgs::new();
+        let msg1 = ValqMsg::new(1, "message1".to_string(), None, 0);
+        delayed_msgs.insert(msg1.clone(), 100);
+        assert_eq!(delayed_msgs.take(2), None);
+        assert_eq!(delayed_msgs.take(1), Some(msg1));
+        assert_eq!(delayed_msgs.len(), 0);
+        assert!(delayed_msgs.scores.is_empty());
+    }
+
     #[test]
     fn test_ready_to_process() {
         let mut delayed_msgs = DelayedMsgs::new();
@@ -114,4 +183,41 @@ mod tests {
         assert_eq!(ready.len(), 1);
         assert_eq!(ready[0].1, &msg1);
     }
+
+    #[test]
+    fn test_mem_usage() {
+        let mut delayed_msgs = DelayedMsgs::new();
+        let empty = delayed_msgs.mem_usage();
+        delayed_msgs.insert(ValqMsg::new(1, "message1".to_string(), None, 0), 100);
+        // the body is counted in both maps
+        assert!(delayed_msgs.mem_usage() >= empty + 2 * size_of::<ValqMsg>() + 16);
+    }
+
+    #[test]
+    fn test_defrag() {
+        let mut delayed_msgs = DelayedMsgs::new();
+        for (id, score) in [(1, 100), (2, 100), (3, 200)] {
+            delayed_msgs.insert(ValqMsg::new(id, format!("message{}", id), None, 0), score);
+        }
+        let mut buffers = 0;
+        let mut realloc = |buffer: &mut Vec<u8>| {
+            buffers += 1;
+            *buffer = buffer.clone();
+        };
+        // stop before the second bucket
+        let calls = std::cell::Cell::new(0);
+        let should_stop = || {
+            calls.set(calls.get() + 1);
+            calls.get() > 1
+        };
+        assert_eq!(
+            delayed_msgs.defrag(0, &mut realloc, &should_stop),
+            Some(200)
+        );
+        assert_eq!(delayed_msgs.defrag(200, &mut realloc, &|| false), None);
+        // body and compressed body of each message in both maps
+        assert_eq!(buffers, 12);
+        assert_eq!(delayed_msgs.len(), 3);
+        assert_eq!(delayed_msgs.scores()[&100].len(), 2);
+    }
 }
@@ -1,4 +1,10 @@
+pub(crate) mod body_encoding;
 mod delayed_msgs;
+pub(crate) mod overflow_policy;
+pub(crate) mod q_event;
+pub(crate) mod q_registry;
 pub(crate) mod q_type;
+pub(crate) mod rate_limiter;
+pub(crate) mod retry_policy;
 pub(crate) mod valq_msg;
 pub(crate) mod valq_type;
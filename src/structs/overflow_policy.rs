@@ -0,0 +1,89 @@
+/// What `valq push` does when the queue is at its max length or max bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum OverflowPolicy {
+    /// Reject the new message with an error.
+    #[default]
+    Reject,
+    /// Drop the oldest messages to make room for the new one.
+    DropOldest,
+    /// Move the oldest messages to the dead-letter queue to make room for the new one.
+    Dlq,
+}
+
+impl OverflowPolicy {
+    /// Converts a string representation to an `OverflowPolicy` enum.
+    ///
+    /// # Arguments
+    /// * `input` - A string slice, "reject", "dropoldest" or "dlq" in any case.
+    ///
+    /// # Returns
+    /// * `Some(OverflowPolicy)` - If the input is a known policy.
+    /// * `None` - For any other input.
+    pub(crate) fn parse(input: &str) -> Option<Self> {
+        match input.to_lowercase().as_str() {
+            "reject" => Some(Self::Reject),
+            "dropoldest" => Some(Self::DropOldest),
+            "dlq" => Some(Self::Dlq),
+            _ => None,
+        }
+    }
+
+    /// Converts the policy to the number saved in the RDB.
+    pub(crate) fn as_u64(&self) -> u64 {
+        match self {
+            Self::Reject => 0,
+            Self::DropOldest => 1,
+            Self::Dlq => 2,
+        }
+    }
+
+    /// Converts the number saved in the RDB back to the policy.
+    pub(crate) fn from_u64(input: u64) -> Option<Self> {
+        match input {
+            0 => Some(Self::Reject),
+            1 => Some(Self::DropOldest),
+            2 => Some(Self::Dlq),
+            _ => None,
+        }
+    }
+
+    /// Name of the policy shown in `valq info`.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reject => "reject",
+            Self::DropOldest => "dropoldest",
+            Self::Dlq => "dlq",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            OverflowPolicy::parse("reject"),
+            Some(OverflowPolicy::Reject)
+        );
+        assert_eq!(
+            OverflowPolicy::parse("DROPOLDEST"),
+            Some(OverflowPolicy::DropOldest)
+        );
+        assert_eq!(OverflowPolicy::parse("dlq"), Some(OverflowPolicy::Dlq));
+        assert_eq!(OverflowPolicy::parse("invalid"), None);
+    }
+
+    #[test]
+    fn test_u64_round_trip() {
+        for policy in [
+            OverflowPolicy::Reject,
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::Dlq,
+        ] {
+            assert_eq!(OverflowPolicy::from_u64(policy.as_u64()), Some(policy));
+        }
+        assert_eq!(OverflowPolicy::from_u64(3), None);
+    }
+}
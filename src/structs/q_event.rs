@@ -0,0 +1,51 @@
+/// Change to a queue announced to keyspace subscribers as a `module` class event named `valq.<event>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum QEvent {
+    /// A message was added to the main or the delayed queue.
+    Push,
+    /// A message was leased to a consumer.
+    Pop,
+    /// A leased message was acknowledged and removed.
+    Ack,
+    /// The lease of a message was extended.
+    Extend,
+    /// Idle leases were taken over by another consumer.
+    Claim,
+    /// Leases of a disconnected client were released.
+    Release,
+    /// Messages were moved to the dead-letter queue (DLQ).
+    Dlq,
+    /// A message was leased again after its previous lease expired.
+    Redelivered,
+    /// Messages were removed from the DLQ after the retention period.
+    Expired,
+}
+
+impl QEvent {
+    /// Name of the keyspace event.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Push => "valq.push",
+            Self::Pop => "valq.pop",
+            Self::Ack => "valq.ack",
+            Self::Extend => "valq.extend",
+            Self::Claim => "valq.claim",
+            Self::Release => "valq.release",
+            Self::Dlq => "valq.dlq",
+            Self::Redelivered => "valq.redelivered",
+            Self::Expired => "valq.expired",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_as_str() {
+        assert_eq!(QEvent::Push.as_str(), "valq.push");
+        assert_eq!(QEvent::Redelivered.as_str(), "valq.redelivered");
+        assert_eq!(QEvent::Expired.as_str(), "valq.expired");
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeSet;
+
+/// Queues of the keyspace, keyed by db number and key name.
+/// Kept in sync by `utils::q_registry` and rebuilt from the keyspace after loads and full syncs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct QRegistry {
+    queues: BTreeSet<(i32, String)>,
+}
+
+impl QRegistry {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the queue stored in `key` of `db`, returns false if it was already registered.
+    pub(crate) fn insert(&mut self, db: i32, key: &str) -> bool {
+        self.queues.insert((db, key.to_string()))
+    }
+
+    /// Removes the queue stored in `key` of `db`, returns false if it was not registered.
+    pub(crate) fn remove(&mut self, db: i32, key: &str) -> bool {
+        self.queues.remove(&(db, key.to_string()))
+    }
+
+    /// All queues as db number and key name, ordered by db and key.
+    pub(crate) fn queues(&self) -> Vec<(i32, String)> {
+        self.queues.iter().cloned().collect()
+    }
+
+    /// Key names of the queues in `db`, ordered by key.
+    pub(crate) fn queues_in_db(&self, db: i32) -> Vec<String> {
+        self.queues
+            .iter()
+            .filter(|(tmp, _)| *tmp == db)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// Swaps the queues of two dbs for `SWAPDB`.
+    pub(crate) fn swap_db(&mut self, first: i32, second: i32) {
+        self.queues = std::mem::take(&mut self.queues)
+            .into_iter()
+            .map(|(db, key)| match db {
+                db if db == first => (second, key),
+                db if db == second => (first, key),
+                _ => (db, key),
+            })
+            .collect();
+    }
+
+    /// Removes the queues of `db`, or of all dbs when `db` is -1 same as the flush server event.
+    pub(crate) fn flush_db(&mut self, db: i32) {
+        self.queues.retain(|(tmp, _)| db != -1 && *tmp != db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> QRegistry {
+        let mut registry = QRegistry::new();
+        registry.insert(0, "q1");
+        registry.insert(0, "q2");
+        registry.insert(1, "q1");
+        registry
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut registry = registry();
+        assert_eq!(registry.queues().len(), 3);
+        assert!(!registry.insert(0, "q1"));
+        assert!(registry.remove(0, "q1"));
+        assert!(!registry.remove(0, "q1"));
+        // same name in another db is a different queue
+        assert_eq!(
+            registry.queues(),
+            [(0, "q2".to_string()), (1, "q1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_queues_in_db() {
+        let registry = registry();
+        assert_eq!(registry.queues_in_db(0), ["q1", "q2"]);
+        assert_eq!(registry.queues_in_db(1), ["q1"]);
+        assert!(registry.queues_in_db(2).is_empty());
+    }
+
+    #[test]
+    fn test_swap_db() {
+        let mut registry = registry();
+        registry.swap_db(1, 2);
+        assert_eq!(registry.queues_in_db(0), ["q1", "q2"]);
+        assert!(registry.queues_in_db(1).is_empty());
+        assert_eq!(registry.queues_in_db(2), ["q1"]);
+        registry.swap_db(0, 2);
+        assert_eq!(registry.queues_in_db(0), ["q1"]);
+        assert_eq!(registry.queues_in_db(2), ["q1", "q2"]);
+    }
+
+    #[test]
+    fn test_flush_db() {
+        let mut registry = registry();
+        registry.flush_db(1);
+        assert_eq!(registry.queues_in_db(0), ["q1", "q2"]);
+        assert!(registry.queues_in_db(1).is_empty());
+        registry.insert(1, "q1");
+        registry.flush_db(-1);
+        assert!(registry.queues().is_empty());
+    }
+}
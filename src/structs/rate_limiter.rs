@@ -0,0 +1,98 @@
+use getset::Getters;
+
+/// Token bucket limiting how many messages a queue hands out per time window.
+/// The bucket holds up to `rate` tokens and refills at `rate` tokens every `per` seconds.
+/// A `rate` of 0 disables the limiter.
+/// The tokens are local to the node, only the primary hands out messages and the bucket is neither
+/// replicated, persisted nor shown in `valq info`, a promoted replica or a restarted node starts full.
+#[derive(Debug, Clone, Default, Getters, PartialEq)]
+pub(crate) struct RateLimiter {
+    /// Maximum number of messages per window.
+    #[getset(get = "pub")]
+    rate: u64,
+    /// Length of the window, in seconds.
+    #[getset(get = "pub")]
+    per: u64,
+    /// Tokens left in the bucket as of `refilled_at`.
+    tokens: f64,
+    /// Timestamp (in milliseconds) of the last refill.
+    refilled_at: u64,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter` with a full bucket. Validation happens in `ValqType::set_rate_limit`.
+    pub(crate) fn new(rate: u64, per: u64) -> Self {
+        Self {
+            rate,
+            per,
+            tokens: rate as f64,
+            refilled_at: 0,
+        }
+    }
+
+    /// Tokens available at `now` (in milliseconds) without consuming any.
+    fn available(&self, now: u64) -> f64 {
+        if self.refilled_at == 0 {
+            return self.rate as f64;
+        }
+        let elapsed = now.saturating_sub(self.refilled_at) as f64;
+        let refill = elapsed * self.rate as f64 / (self.per as f64 * 1_000.0);
+        (self.tokens + refill).min(self.rate as f64)
+    }
+
+    /// Checks if a message can be handed out at `now` (in milliseconds).
+    ///
+    /// # Returns
+    /// * `None` - If the limiter is disabled or a token is available.
+    /// * `Some(seconds)` - How long to wait until the next token is available, at least 1 second.
+    pub(crate) fn retry_after(&self, now: u64) -> Option<u64> {
+        if self.rate == 0 {
+            return None;
+        }
+        let missing = 1.0 - self.available(now);
+        if missing <= 0.0 {
+            return None;
+        }
+        let seconds = missing * self.per as f64 / self.rate as f64;
+        Some((seconds.ceil() as u64).max(1))
+    }
+
+    /// Consumes a token at `now` (in milliseconds), call only after `retry_after` returned `None`.
+    pub(crate) fn acquire(&mut self, now: u64) {
+        if self.rate == 0 {
+            return;
+        }
+        self.tokens = (self.available(now) - 1.0).max(0.0);
+        self.refilled_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled() {
+        let mut limiter = RateLimiter::default();
+        for _ in 0..100 {
+            assert_eq!(limiter.retry_after(1_000), None);
+            limiter.acquire(1_000);
+        }
+    }
+
+    #[test]
+    fn limits_tokens_per_window() {
+        let mut limiter = RateLimiter::new(2, 10);
+        assert_eq!(limiter.available(1_000), 2.0);
+        limiter.acquire(1_000);
+        limiter.acquire(1_000);
+        assert_eq!(limiter.available(1_000), 0.0);
+        // one token refills every 5 seconds
+        assert_eq!(limiter.retry_after(1_000), Some(5));
+        assert_eq!(limiter.retry_after(4_000), Some(2));
+        assert_eq!(limiter.retry_after(6_000), None);
+        assert_eq!(limiter.available(6_000), 1.0);
+        // the bucket never holds more than rate tokens
+        assert_eq!(limiter.available(100_000), 2.0);
+    }
+}
@@ -0,0 +1,87 @@
+use getset::Getters;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Redelivery policy applied after a message lease expires.
+/// The delay grows exponentially with the number of delivery attempts and is capped at `max`.
+/// A `base` of 0 disables the backoff and expired messages become visible right away.
+#[derive(Debug, Clone, Default, Getters, PartialEq)]
+pub(crate) struct RetryPolicy {
+    /// Delay after the first failed delivery, in seconds.
+    #[getset(get = "pub")]
+    base: u64,
+    /// Upper bound for the delay, in seconds.
+    #[getset(get = "pub")]
+    max: u64,
+    /// Percentage (0-100) of the delay that is randomly subtracted to spread out redeliveries.
+    #[getset(get = "pub")]
+    jitter: u64,
+}
+
+impl RetryPolicy {
+    /// Creates a new `RetryPolicy` instance. Validation happens in `ValqType::set_retry_policy`.
+    pub(crate) fn new(base: u64, max: u64, jitter: u64) -> Self {
+        Self { base, max, jitter }
+    }
+
+    /// Calculates how long a message stays invisible after its lease expired.
+    ///
+    /// # Arguments
+    /// * `delivery_attempts` - Number of times the message has been delivered so far.
+    /// * `msg_id` - Message ID, used to derive a stable jitter for the message.
+    ///
+    /// # Returns
+    /// The delay in seconds, `base * 2^(delivery_attempts - 1)` capped at `max` and reduced by jitter.
+    /// The jitter is derived from the message ID and attempts so repeated checks return the same delay.
+    pub(crate) fn delay(&self, delivery_attempts: u64, msg_id: u64) -> u64 {
+        if self.base == 0 || delivery_attempts == 0 {
+            return 0;
+        }
+        let exponent = delivery_attempts.saturating_sub(1).min(63) as u32;
+        let delay = self
+            .base
+            .saturating_mul(2u64.saturating_pow(exponent))
+            .min(self.max);
+        let jitter_range = delay.saturating_mul(self.jitter) / 100;
+        if jitter_range == 0 {
+            return delay;
+        }
+        let mut hasher = DefaultHasher::new();
+        (msg_id, delivery_attempts).hash(&mut hasher);
+        delay - hasher.finish() % (jitter_range + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_disabled() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, 1), 0);
+        assert_eq!(policy.delay(10, 1), 0);
+    }
+
+    #[test]
+    fn delay_grows_exponentially_until_max() {
+        let policy = RetryPolicy::new(2, 30, 0);
+        assert_eq!(policy.delay(0, 1), 0);
+        assert_eq!(policy.delay(1, 1), 2);
+        assert_eq!(policy.delay(2, 1), 4);
+        assert_eq!(policy.delay(3, 1), 8);
+        assert_eq!(policy.delay(4, 1), 16);
+        assert_eq!(policy.delay(5, 1), 30);
+        assert_eq!(policy.delay(100, 1), 30);
+    }
+
+    #[test]
+    fn delay_with_jitter() {
+        let policy = RetryPolicy::new(100, 1_000, 50);
+        for msg_id in 1..100 {
+            let delay = policy.delay(2, msg_id);
+            assert!((100..=200).contains(&delay));
+            // same message and attempts always get the same delay
+            assert_eq!(delay, policy.delay(2, msg_id));
+        }
+    }
+}
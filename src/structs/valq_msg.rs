@@ -1,7 +1,11 @@
+use crate::MAX_MSG_SIZE;
+use crate::structs::body_encoding::BodyEncoding;
 use crate::utils;
 use getset::{Getters, Setters};
 use std::collections::BTreeMap;
-use valkey_module::ValkeyValue;
+use std::sync::atomic::Ordering;
+use valkey_module::logging::log_warning;
+use valkey_module::{ValkeyError, ValkeyValue};
 
 /// Represents a message in the queue with metadata such as ID, body, timeout, and delivery attempts.
 #[derive(Debug, Clone, Default, Getters, Setters, Ord, Eq, PartialEq, PartialOrd, Hash)]
@@ -10,8 +14,8 @@ pub(crate) struct ValqMsg {
     #[getset(get = "pub")]
     id: u64,
 
-    /// The content or payload of the message.
-    #[getset(get = "pub")]
+    /// The content or payload of the message, empty when the body is stored in `body_key`.
+    #[getset(get = "pub", set = "pub")]
     body: String,
 
     /// timestamp (in seconds) indicating when the message becomes available to another consumer.
@@ -21,6 +25,46 @@ pub(crate) struct ValqMsg {
     /// The number of times the message has been delivered.
     #[getset(get = "pub", set = "pub")]
     delivery_attempts: u64,
+
+    /// Optional per-message override of the queue's maximum delivery attempts.
+    #[getset(get = "pub", set = "pub")]
+    max_delivery_attempts: Option<u64>,
+
+    /// Optional per-message override of the queue's visibility timeout, in seconds.
+    #[getset(get = "pub", set = "pub")]
+    visibility_timeout: Option<u64>,
+
+    /// Name of the consumer holding the current lease.
+    #[getset(get = "pub", set = "pub")]
+    consumer: Option<String>,
+
+    /// Timestamp (in seconds) when the current lease was taken or last extended.
+    #[getset(get = "pub", set = "pub")]
+    leased_at: Option<u64>,
+
+    /// ID of the client connection holding the current lease.
+    #[getset(get = "pub", set = "pub")]
+    client_id: Option<u64>,
+
+    /// Optional group key, such as a tenant, used to limit in-flight messages per group.
+    #[getset(get = "pub", set = "pub")]
+    group: Option<String>,
+
+    /// Companion key holding the body of a large message.
+    #[getset(get = "pub", set = "pub")]
+    body_key: Option<String>,
+
+    /// Size in bytes of the body stored in `body_key`.
+    #[getset(get = "pub", set = "pub")]
+    body_key_len: u64,
+
+    /// Encoding of the body, `Deflate` when it is kept in `compressed_body`.
+    #[getset(get = "pub", set = "pub")]
+    encoding: BodyEncoding,
+
+    /// Compressed body, empty unless `encoding` is `Deflate`.
+    #[getset(get = "pub", set = "pub")]
+    compressed_body: Vec<u8>,
 }
 
 impl ValqMsg {
@@ -45,9 +89,133 @@ impl ValqMsg {
             body,
             timeout_at,
             delivery_attempts,
+            max_delivery_attempts: None,
+            visibility_timeout: None,
+            consumer: None,
+            leased_at: None,
+            client_id: None,
+            group: None,
+            body_key: None,
+            body_key_len: 0,
+            encoding: BodyEncoding::Raw,
+            compressed_body: Vec::new(),
         }
     }
 
+    /// Leases the message to a consumer until `timeout_at`.
+    ///
+    /// # Arguments
+    /// * `consumer` - Name of the consumer taking the lease.
+    /// * `client_id` - ID of the client connection taking the lease.
+    /// * `timeout_at` - Timestamp (in seconds) when the lease expires.
+    pub(crate) fn lease(
+        &mut self,
+        consumer: Option<String>,
+        client_id: Option<u64>,
+        timeout_at: u64,
+    ) {
+        self.consumer = consumer;
+        self.client_id = client_id;
+        self.leased_at = Some(utils::now_as_seconds());
+        self.timeout_at = Some(timeout_at);
+    }
+
+    /// Releases the current lease so the message is visible right away.
+    /// The delivery attempt of the released lease is not counted.
+    pub(crate) fn release(&mut self) {
+        self.consumer = None;
+        self.client_id = None;
+        self.leased_at = None;
+        self.timeout_at = None;
+        self.delivery_attempts = self.delivery_attempts.saturating_sub(1);
+    }
+
+    /// Takes the body out of the message, leaving it empty.
+    pub(crate) fn take_body(&mut self) -> String {
+        std::mem::take(&mut self.body)
+    }
+
+    /// Compresses the body with deflate, keeping it raw if compression does not make it smaller.
+    pub(crate) fn compress(&mut self) {
+        if self.encoding != BodyEncoding::Raw {
+            return;
+        }
+        let compressed = miniz_oxide::deflate::compress_to_vec(self.body.as_bytes(), 6);
+        if compressed.len() < self.body.len() {
+            self.body = String::new();
+            self.compressed_body = compressed;
+            self.encoding = BodyEncoding::Deflate;
+        }
+    }
+
+    /// Body of the message as pushed, decompressing it if needed.
+    /// Fails when the compressed body is corrupt or inflates to more than `max-msg-size` bytes.
+    pub(crate) fn decoded_body(&self) -> Result<String, ValkeyError> {
+        self.decoded_body_with_limit(MAX_MSG_SIZE.load(Ordering::Relaxed) as usize)
+    }
+
+    fn decoded_body_with_limit(&self, limit: usize) -> Result<String, ValkeyError> {
+        match self.encoding {
+            BodyEncoding::Raw => Ok(self.body.clone()),
+            BodyEncoding::Deflate => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(&self.compressed_body, limit)
+                    .map(|body| String::from_utf8_lossy(&body).to_string())
+                    .map_err(|err| {
+                        log_warning(format!(
+                            "message {} has a corrupt compressed body: {}",
+                            self.id, err
+                        ));
+                        ValkeyError::String(format!("message {} has a corrupt body", self.id))
+                    })
+            }
+        }
+    }
+
+    /// Size of the message body in bytes as stored, including the part stored in `body_key`.
+    pub(crate) fn size(&self) -> u64 {
+        match (&self.body_key, self.encoding) {
+            (Some(_), _) => self.body_key_len,
+            (None, BodyEncoding::Deflate) => self.compressed_body.len() as u64,
+            (None, BodyEncoding::Raw) => self.body.len() as u64,
+        }
+    }
+
+    /// Passes the heap buffers of the message to `realloc`, which may move them to a new allocation.
+    pub(crate) fn defrag(&mut self, realloc: &mut impl FnMut(&mut Vec<u8>)) {
+        let strings = [
+            Some(&mut self.body),
+            self.consumer.as_mut(),
+            self.group.as_mut(),
+            self.body_key.as_mut(),
+        ];
+        for value in strings.into_iter().flatten() {
+            let mut bytes = std::mem::take(value).into_bytes();
+            realloc(&mut bytes);
+            *value = String::from_utf8(bytes).unwrap_or_default();
+        }
+        realloc(&mut self.compressed_body);
+    }
+
+    /// Memory allocated by the message outside of the struct itself, in bytes.
+    pub(crate) fn heap_usage(&self) -> usize {
+        self.body.capacity()
+            + self.compressed_body.capacity()
+            + self.consumer.as_ref().map_or(0, String::capacity)
+            + self.group.as_ref().map_or(0, String::capacity)
+            + self.body_key.as_ref().map_or(0, String::capacity)
+    }
+
+    /// Checks if the message is leased to a consumer and the lease has not expired yet.
+    pub(crate) fn is_in_flight(&self) -> bool {
+        !self.check_timeout_at()
+    }
+
+    /// Number of seconds since the message was leased or the lease was last extended.
+    pub(crate) fn idle(&self) -> u64 {
+        let now = utils::now_as_seconds();
+        now.saturating_sub(self.leased_at.unwrap_or(now))
+    }
+
     /// Checks if the message has expired based on its `timeout_at` value.
     ///
     /// # Returns
@@ -60,29 +228,48 @@ impl ValqMsg {
         }
     }
 
+    /// Checks if the redelivery delay after the message timeout has passed.
+    ///
+    /// # Arguments
+    /// * `delay` - The redelivery delay in seconds, added to `timeout_at`.
+    ///
+    /// # Returns
+    /// * `true` - If `timeout_at` is `None` or `timeout_at + delay` is in the past.
+    /// * `false` - If `timeout_at + delay` is in the future.
+    pub(crate) fn check_redelivery_delay(&self, delay: u64) -> bool {
+        match self.timeout_at {
+            Some(timeout) => timeout.saturating_add(delay) <= utils::now_as_seconds(),
+            None => true,
+        }
+    }
+
     /// Checks if the message can still be delivered based on the maximum allowed delivery attempts.
+    /// The message's own `max_delivery_attempts` takes precedence over the queue setting.
     ///
     /// # Arguments
-    /// * `max_delivery_attempts` - The maximum number of delivery attempts allowed.
+    /// * `max_delivery_attempts` - The queue's maximum number of delivery attempts allowed.
     ///
     /// # Returns
     /// * `true` - If the current delivery attempts are less than the maximum allowed.
     /// * `false` - If the delivery attempts have reached or exceeded the maximum allowed.
     pub(crate) fn check_max_delivery_attempts(&self, max_delivery_attempts: u64) -> bool {
-        self.delivery_attempts < max_delivery_attempts
+        self.delivery_attempts < self.max_delivery_attempts.unwrap_or(max_delivery_attempts)
     }
 }
 
-impl From<ValqMsg> for ValkeyValue {
+impl TryFrom<ValqMsg> for ValkeyValue {
+    type Error = ValkeyError;
+
     /// Converts a `ValqMsg` instance into a `ValkeyValue` representation.
     ///
     /// # Returns
-    /// A `ValkeyValue::OrderedMap` containing the message's ID and body as key-value pairs.
-    fn from(msg: ValqMsg) -> Self {
-        ValkeyValue::OrderedMap(BTreeMap::from([
+    /// A `ValkeyValue::OrderedMap` containing the message's ID and body as key-value pairs,
+    /// or an error if the body can't be decompressed.
+    fn try_from(msg: ValqMsg) -> Result<Self, Self::Error> {
+        Ok(ValkeyValue::OrderedMap(BTreeMap::from([
             ("id".into(), msg.id().to_string().into()),
-            ("body".into(), msg.body().into()),
-        ]))
+            ("body".into(), msg.decoded_body()?.into()),
+        ])))
     }
 }
 
@@ -134,6 +321,41 @@ mod tests {
         assert!(!msg.check_timeout_at());
     }
 
+    #[test]
+    fn redelivery_delay() {
+        let msg = ValqMsg::new(42, "test msg".to_string(), None, 0);
+        assert!(msg.check_redelivery_delay(10));
+        let msg = ValqMsg::new(42, "test msg".to_string(), Some(now_as_seconds() - 10), 1);
+        assert!(msg.check_redelivery_delay(0));
+        assert!(msg.check_redelivery_delay(10));
+        assert!(!msg.check_redelivery_delay(11));
+    }
+
+    #[test]
+    fn lease_in_flight_idle() {
+        let mut msg = ValqMsg::new(42, "test msg".to_string(), None, 0);
+        assert!(!msg.is_in_flight());
+        assert_eq!(msg.idle(), 0);
+        msg.lease(Some("c1".to_string()), Some(7), now_as_seconds() + 10);
+        assert!(msg.is_in_flight());
+        assert_eq!(msg.consumer().as_deref(), Some("c1"));
+        assert_eq!(*msg.client_id(), Some(7));
+        msg.set_leased_at(Some(now_as_seconds() - 5));
+        assert!(msg.idle() >= 5);
+    }
+
+    #[test]
+    fn release() {
+        let mut msg = ValqMsg::new(42, "test msg".to_string(), None, 1);
+        msg.lease(Some("c1".to_string()), Some(7), now_as_seconds() + 10);
+        msg.release();
+        assert!(!msg.is_in_flight());
+        assert_eq!(*msg.consumer(), None);
+        assert_eq!(*msg.client_id(), None);
+        assert_eq!(*msg.leased_at(), None);
+        assert_eq!(*msg.delivery_attempts(), 0);
+    }
+
     #[test]
     fn max_delivery_attempts() {
         let msg = ValqMsg::new(1, "msg".to_string(), None, 2);
@@ -146,6 +368,16 @@ mod tests {
         assert!(!msg.check_max_delivery_attempts(DELIVERY_ATTEMPTS_DEFAULT));
     }
 
+    #[test]
+    fn max_delivery_attempts_override() {
+        let mut msg = ValqMsg::new(1, "msg".to_string(), None, 2);
+        assert!(msg.check_max_delivery_attempts(DELIVERY_ATTEMPTS_DEFAULT));
+        msg.set_max_delivery_attempts(Some(2));
+        assert!(!msg.check_max_delivery_attempts(DELIVERY_ATTEMPTS_DEFAULT));
+        msg.set_max_delivery_attempts(Some(10));
+        assert!(msg.check_max_delivery_attempts(1));
+    }
+
     #[test]
     fn valq_msg_update_delivery_attempts() {
         let mut msg = ValqMsg::new(42, "test msg".to_string(), None, 0);
@@ -157,7 +389,7 @@ mod tests {
     #[test]
     fn valq_msg_impl_valkey_value() {
         let msg = ValqMsg::new(42, "test msg".to_string(), None, 0);
-        match msg.into() {
+        match ValkeyValue::try_from(msg).unwrap() {
             ValkeyValue::OrderedMap(map) => {
                 assert_eq!(
                     map.get(&ValkeyValueKey::String("id".to_string())).unwrap(),
@@ -172,4 +404,54 @@ mod tests {
             _ => panic!("Expected ValkeyValue::OrderedMap"),
         }
     }
+
+    #[test]
+    fn test_compress() {
+        let body = "{\"status\":\"ok\"}".repeat(20);
+        let mut msg = ValqMsg::new(1, body.clone(), None, 0);
+        msg.compress();
+        assert_eq!(*msg.encoding(), BodyEncoding::Deflate);
+        assert_eq!(msg.body(), "");
+        assert!(msg.size() < body.len() as u64);
+        assert_eq!(msg.decoded_body().unwrap(), body);
+
+        // not compressible
+        let mut msg = ValqMsg::new(2, "ab".to_string(), None, 0);
+        msg.compress();
+        assert_eq!(*msg.encoding(), BodyEncoding::Raw);
+        assert_eq!(msg.body(), "ab");
+        assert_eq!(msg.decoded_body().unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_decoded_body_corrupt() {
+        let body = "{\"status\":\"ok\"}".repeat(20);
+        let mut msg = ValqMsg::new(1, body, None, 0);
+        msg.compress();
+        msg.compressed_body.truncate(msg.compressed_body.len() / 2);
+        assert!(msg.decoded_body().is_err());
+        assert!(ValkeyValue::try_from(msg).is_err());
+
+        // inflates to more than max-msg-size
+        let mut msg = ValqMsg::new(2, "a".repeat(1_025), None, 0);
+        msg.compress();
+        assert_eq!(msg.encoding, BodyEncoding::Deflate);
+        assert!(msg.decoded_body_with_limit(1_024).is_err());
+        assert!(msg.decoded_body_with_limit(1_025).is_ok());
+    }
+
+    #[test]
+    fn test_defrag() {
+        let mut msg = ValqMsg::new(1, "msg1".to_string(), None, 0);
+        msg.set_consumer(Some("worker1".to_string()));
+        let test = msg.clone();
+        let mut buffers = 0;
+        // move every buffer to a new allocation
+        msg.defrag(&mut |buffer: &mut Vec<u8>| {
+            buffers += 1;
+            *buffer = buffer.clone();
+        });
+        assert_eq!(buffers, 3);
+        assert_eq!(msg, test);
+    }
 }
@@ -1,13 +1,25 @@
 use crate::structs::delayed_msgs::DelayedMsgs;
+use crate::structs::overflow_policy::OverflowPolicy;
+use crate::structs::q_event::QEvent;
+use crate::structs::rate_limiter::RateLimiter;
+use crate::structs::retry_policy::RetryPolicy;
 use crate::structs::valq_msg::ValqMsg;
+use crate::utils;
+use crate::utils::claim_check::{self, BodyOp};
+use crate::utils::replication::Effect;
 use crate::{
-    DELIVERY_ATTEMPTS_DEFAULT, DELIVERY_ATTEMPTS_MAX, RETENTION_PERIOD_DEFAULT,
-    RETENTION_PERIOD_MAX, RETENTION_PERIOD_MIN, VISIBILITY_TIMEOUT_DEFAULT, VISIBILITY_TIMEOUT_MAX,
+    DELIVERY_ATTEMPTS_DEFAULT, DELIVERY_ATTEMPTS_MAX, MAX_INFLIGHT_MAX, MAX_MSG_SIZE_MAX,
+    RATE_LIMIT_MAX, RATE_PER_MAX, RETENTION_PERIOD_DEFAULT, RETENTION_PERIOD_MAX,
+    RETENTION_PERIOD_MIN, RETRY_BACKOFF_MAX, RETRY_JITTER_MAX, VISIBILITY_TIMEOUT_DEFAULT,
+    VISIBILITY_TIMEOUT_MAX,
 };
 use getset::{Getters, MutGetters, Setters};
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use valkey_module::ValkeyError;
 
+/// Cursor bit marking that `defrag` resumes in the delayed queue, the rest of the cursor is the score.
+const DEFRAG_DELAYED_CURSOR: u64 = 1 << 63;
+
 /// Represents a job queue with configurable visibility timeout, delivery attempts and retention period.
 /// This structure manages a queue of messages, delayed messages and a dead-letter queue for failed messages.
 #[derive(Debug, Clone, Getters, Setters, MutGetters, Default)]
@@ -27,6 +39,58 @@ pub(crate) struct ValqType {
     /// Retention period untill messages in the DLQ are removed via BG thread, in seconds.
     #[getset(get = "pub")]
     retention_period: u64,
+    /// Backoff applied before an expired message is redelivered.
+    #[getset(get = "pub")]
+    retry_policy: RetryPolicy,
+    /// Make in-flight messages visible again when the client that popped them disconnects.
+    #[getset(get = "pub", set = "pub")]
+    release_on_disconnect: bool,
+    /// Maximum number of messages leased at the same time, 0 means unlimited.
+    #[getset(get = "pub")]
+    max_inflight: u64,
+    /// Maximum number of messages of the same group leased at the same time, 0 means unlimited.
+    #[getset(get = "pub")]
+    group_max_inflight: u64,
+    /// Token bucket limiting how many messages are handed out per time window.
+    #[getset(get = "pub", get_mut = "pub")]
+    rate_limiter: RateLimiter,
+    /// Paused queues accept pushes but do not hand out messages.
+    #[getset(get = "pub", set = "pub")]
+    paused: bool,
+    /// Maximum number of main and delayed messages, 0 means unlimited.
+    #[getset(get = "pub", set = "pub")]
+    max_length: u64,
+    /// Maximum total size of main and delayed message bodies in bytes, 0 means unlimited.
+    #[getset(get = "pub", set = "pub")]
+    max_bytes: u64,
+    /// What push does when `max_length` or `max_bytes` is reached.
+    #[getset(get = "pub", set = "pub")]
+    overflow_policy: OverflowPolicy,
+    /// Number of pushes that hit `max_length` or `max_bytes`.
+    #[getset(get = "pub", set = "pub")]
+    overflow_count: u64,
+    /// Maximum message body size in bytes, 0 means the module-wide `valq.max-msg-size`.
+    #[getset(get = "pub")]
+    max_msg_size: u64,
+    /// Bodies larger than this many bytes are stored in a companion key, 0 disables it.
+    #[getset(get = "pub")]
+    claim_check_threshold: u64,
+    /// Bodies larger than this many bytes are compressed, 0 disables it.
+    #[getset(get = "pub")]
+    compress_threshold: u64,
+    /// Size of the main and delayed message bodies in bytes, kept up to date by `add_msg` and
+    /// `take_msg` so `make_room` doesn't sum the queue on every push, not persisted.
+    #[getset(get = "pub")]
+    queued_bytes: u64,
+    /// Companion key changes waiting to be applied by the current command, not persisted.
+    #[getset(get = "pub", get_mut = "pub")]
+    body_ops: Vec<BodyOp>,
+    /// Replication effects waiting to be sent by the current command, not persisted.
+    #[getset(get = "pub", get_mut = "pub")]
+    effects: Vec<Effect>,
+    /// Keyspace events waiting to be emitted by the current command, not persisted.
+    #[getset(get = "pub", get_mut = "pub")]
+    events: Vec<QEvent>,
     /// Queue of messages currently being processed.
     #[getset(get = "pub", get_mut = "pub")]
     msgs: VecDeque<ValqMsg>,
@@ -97,6 +161,23 @@ impl ValqType {
             visibility_timeout: visibility_timeout.unwrap_or(VISIBILITY_TIMEOUT_DEFAULT),
             max_delivery_attempts: max_delivery_attempts.unwrap_or(DELIVERY_ATTEMPTS_DEFAULT),
             retention_period: retention_period.unwrap_or(RETENTION_PERIOD_DEFAULT),
+            retry_policy: RetryPolicy::default(),
+            release_on_disconnect: false,
+            max_inflight: 0,
+            group_max_inflight: 0,
+            rate_limiter: RateLimiter::default(),
+            paused: false,
+            max_length: 0,
+            max_bytes: 0,
+            overflow_policy: OverflowPolicy::default(),
+            overflow_count: 0,
+            max_msg_size: 0,
+            claim_check_threshold: 0,
+            compress_threshold: 0,
+            queued_bytes: 0,
+            body_ops: Vec::new(),
+            effects: Vec::new(),
+            events: Vec::new(),
             msgs: VecDeque::new(),
             dlq_msgs: VecDeque::new(),
             delayed_msgs: DelayedMsgs::new(),
@@ -144,13 +225,523 @@ impl ValqType {
             Ok("OK".to_string())
         }
     }
+
+    pub(crate) fn set_retry_policy(
+        &mut self,
+        retry_policy: RetryPolicy,
+    ) -> Result<String, ValkeyError> {
+        if *retry_policy.max() > RETRY_BACKOFF_MAX || retry_policy.base() > retry_policy.max() {
+            Err(ValkeyError::String(format!(
+                "backoff base must be less than or equal to max and max less than or equal to {} seconds",
+                RETRY_BACKOFF_MAX
+            )))
+        } else if *retry_policy.jitter() > RETRY_JITTER_MAX {
+            Err(ValkeyError::String(format!(
+                "backoff jitter must be between 0 and {} percent",
+                RETRY_JITTER_MAX
+            )))
+        } else {
+            self.retry_policy = retry_policy;
+            Ok("OK".to_string())
+        }
+    }
+
+    pub(crate) fn set_max_inflight(&mut self, max_inflight: u64) -> Result<String, ValkeyError> {
+        if max_inflight > MAX_INFLIGHT_MAX {
+            Err(ValkeyError::String(format!(
+                "max inflight must be between 0 (unlimited) and {}",
+                MAX_INFLIGHT_MAX
+            )))
+        } else {
+            self.max_inflight = max_inflight;
+            Ok("OK".to_string())
+        }
+    }
+
+    pub(crate) fn set_group_max_inflight(
+        &mut self,
+        group_max_inflight: u64,
+    ) -> Result<String, ValkeyError> {
+        if group_max_inflight > MAX_INFLIGHT_MAX {
+            Err(ValkeyError::String(format!(
+                "group max inflight must be between 0 (unlimited) and {}",
+                MAX_INFLIGHT_MAX
+            )))
+        } else {
+            self.group_max_inflight = group_max_inflight;
+            Ok("OK".to_string())
+        }
+    }
+
+    /// Sets the token bucket to `rate` messages per `per` seconds, a `rate` of 0 disables it.
+    pub(crate) fn set_rate_limit(&mut self, rate: u64, per: u64) -> Result<String, ValkeyError> {
+        if rate > RATE_LIMIT_MAX {
+            Err(ValkeyError::String(format!(
+                "rate must be between 0 (unlimited) and {}",
+                RATE_LIMIT_MAX
+            )))
+        } else if rate > 0 && (per < 1 || per > RATE_PER_MAX) {
+            Err(ValkeyError::String(format!(
+                "rate period must be between 1 and {} seconds",
+                RATE_PER_MAX
+            )))
+        } else {
+            self.rate_limiter = RateLimiter::new(rate, per);
+            Ok("OK".to_string())
+        }
+    }
+
+    pub(crate) fn set_max_msg_size(&mut self, max_msg_size: u64) -> Result<String, ValkeyError> {
+        if max_msg_size > MAX_MSG_SIZE_MAX {
+            Err(ValkeyError::String(format!(
+                "max message size must be between 0 (module default) and {} bytes",
+                MAX_MSG_SIZE_MAX
+            )))
+        } else {
+            self.max_msg_size = max_msg_size;
+            Ok("OK".to_string())
+        }
+    }
+
+    pub(crate) fn set_claim_check_threshold(
+        &mut self,
+        claim_check_threshold: u64,
+    ) -> Result<String, ValkeyError> {
+        if claim_check_threshold > MAX_MSG_SIZE_MAX {
+            Err(ValkeyError::String(format!(
+                "claim check threshold must be between 0 (disabled) and {} bytes",
+                MAX_MSG_SIZE_MAX
+            )))
+        } else {
+            self.claim_check_threshold = claim_check_threshold;
+            Ok("OK".to_string())
+        }
+    }
+
+    pub(crate) fn set_compress_threshold(
+        &mut self,
+        compress_threshold: u64,
+    ) -> Result<String, ValkeyError> {
+        if compress_threshold > MAX_MSG_SIZE_MAX {
+            Err(ValkeyError::String(format!(
+                "compress threshold must be between 0 (disabled) and {} bytes",
+                MAX_MSG_SIZE_MAX
+            )))
+        } else {
+            self.compress_threshold = compress_threshold;
+            Ok("OK".to_string())
+        }
+    }
+
+    /// Moves the body of a large message to a companion key, keeping only a reference in the message.
+    /// Bodies that stay in the message are compressed when they are above the compress threshold.
+    pub(crate) fn store_body(&mut self, msg: &mut ValqMsg) {
+        let body_len = msg.body().len() as u64;
+        if self.claim_check_threshold == 0 || body_len <= self.claim_check_threshold {
+            if self.compress_threshold > 0 && body_len > self.compress_threshold {
+                msg.compress();
+            }
+            return;
+        }
+        let key = claim_check::body_key(&self.name, *msg.id());
+        let body = msg.take_body();
+        self.body_ops.push(BodyOp::Store {
+            key: key.clone(),
+            body,
+        });
+        msg.set_body_key(Some(key));
+        msg.set_body_key_len(body_len);
+    }
+
+    /// Adds a message to the back of `msgs` or `dlq`, or to `delayed` with `score`.
+    pub(crate) fn add_msg(
+        &mut self,
+        list: &str,
+        score: u64,
+        msg: ValqMsg,
+    ) -> Result<(), ValkeyError> {
+        match list {
+            "msgs" => {
+                self.queued_bytes += msg.size();
+                self.msgs.push_back(msg);
+            }
+            "dlq" => self.dlq_msgs.push_back(msg),
+            "delayed" => {
+                self.queued_bytes += msg.size();
+                self.delayed_msgs.insert(msg, score);
+            }
+            _ => return Err(ValkeyError::Str("specify list msgs, dlq or delayed")),
+        }
+        Ok(())
+    }
+
+    /// Removes the message with `id` from `msgs`, `dlq` or `delayed` and returns it.
+    pub(crate) fn take_msg(&mut self, list: &str, id: u64) -> Result<ValqMsg, ValkeyError> {
+        let msg = match list {
+            "msgs" => self
+                .msgs
+                .iter()
+                .position(|msg| *msg.id() == id)
+                .and_then(|index| self.msgs.remove(index)),
+            "dlq" => self
+                .dlq_msgs
+                .iter()
+                .position(|msg| *msg.id() == id)
+                .and_then(|index| self.dlq_msgs.remove(index)),
+            "delayed" => self.delayed_msgs.take(id),
+            _ => return Err(ValkeyError::Str("specify list msgs, dlq or delayed")),
+        };
+        let msg =
+            msg.ok_or_else(|| ValkeyError::String(format!("message not found with id {}", id)))?;
+        if list != "dlq" {
+            self.queued_bytes = self.queued_bytes.saturating_sub(msg.size());
+        }
+        Ok(msg)
+    }
+
+    /// Recomputes `queued_bytes` after messages are removed in bulk.
+    pub(crate) fn count_queued_bytes(&mut self) {
+        self.queued_bytes = self.msgs_bytes() + self.delayed_bytes();
+    }
+
+    /// Queues a keyspace event for the current command, each event is emitted once per command.
+    pub(crate) fn add_event(&mut self, event: QEvent) {
+        if !self.events.contains(&event) {
+            self.events.push(event);
+        }
+    }
+
+    /// Deletes the companion key of a message that is removed from the queue.
+    pub(crate) fn release_body(&mut self, msg: &ValqMsg) {
+        if let Some(key) = msg.body_key() {
+            self.body_ops.push(BodyOp::Delete(key.clone()));
+        }
+    }
+
+    /// Deletes the companion keys of all messages, used when the queue is deleted.
+    pub(crate) fn release_all_bodies(&mut self) {
+        let msgs: Vec<ValqMsg> = self
+            .msgs
+            .iter()
+            .chain(self.dlq_msgs.iter())
+            .chain(self.delayed_msgs.members().keys())
+            .filter(|msg| msg.body_key().is_some())
+            .cloned()
+            .collect();
+        msgs.iter().for_each(|msg| self.release_body(msg));
+    }
+
+    /// Total size of all message bodies in bytes, including companion keys.
+    pub(crate) fn bytes(&self) -> u64 {
+        self.msgs_bytes() + self.dlq_bytes() + self.delayed_bytes()
+    }
+
+    /// Size of the message bodies in the main queue in bytes, including companion keys.
+    pub(crate) fn msgs_bytes(&self) -> u64 {
+        self.msgs.iter().map(|msg| msg.size()).sum()
+    }
+
+    /// Size of the message bodies in the DLQ in bytes, including companion keys.
+    pub(crate) fn dlq_bytes(&self) -> u64 {
+        self.dlq_msgs.iter().map(|msg| msg.size()).sum()
+    }
+
+    /// Size of the message bodies in the delayed queue in bytes, including companion keys.
+    pub(crate) fn delayed_bytes(&self) -> u64 {
+        self.delayed_msgs
+            .members()
+            .keys()
+            .map(|msg| msg.size())
+            .sum()
+    }
+
+    /// Memory used by the queue in bytes, reported by `MEMORY USAGE`.
+    /// Companion keys are separate keys and are not included.
+    pub(crate) fn mem_usage(&self) -> usize {
+        let msgs_mem_usage = |msgs: &VecDeque<ValqMsg>| {
+            msgs.capacity() * size_of::<ValqMsg>()
+                + msgs.iter().map(|msg| msg.heap_usage()).sum::<usize>()
+        };
+        size_of::<Self>()
+            + self.name.capacity()
+            + self.body_ops.capacity() * size_of::<BodyOp>()
+            + self.effects.capacity() * size_of::<Effect>()
+            + self.events.capacity() * size_of::<QEvent>()
+            + msgs_mem_usage(&self.msgs)
+            + msgs_mem_usage(&self.dlq_msgs)
+            + self.delayed_msgs.mem_usage()
+    }
+
+    /// Checks the size of a message body before it is pushed.
+    ///
+    /// # Arguments
+    /// * `body_len` - Size of the message body in bytes.
+    /// * `default` - Module-wide max message size used when the queue has no override.
+    ///
+    /// # Errors
+    /// Returns `message too large` if the body is larger than the max message size.
+    pub(crate) fn check_msg_size(&self, body_len: u64, default: u64) -> Result<(), ValkeyError> {
+        let max_msg_size = match self.max_msg_size {
+            0 => default,
+            max_msg_size => max_msg_size,
+        };
+        if body_len > max_msg_size {
+            Err(ValkeyError::Str("message too large"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Makes room for a new message of `body_len` bytes according to the overflow policy.
+    /// Leased messages are never evicted, so their consumers can still ack or extend them.
+    ///
+    /// # Errors
+    /// Returns an error if the queue is full and the policy is `Reject`,
+    /// if the message alone is larger than `max_bytes`,
+    /// or if evicting every message that is not leased would not make enough room.
+    pub(crate) fn make_room(&mut self, body_len: u64) -> Result<(), ValkeyError> {
+        let (max_length, max_bytes) = (self.max_length, self.max_bytes);
+        if max_length == 0 && max_bytes == 0 {
+            return Ok(());
+        }
+        let is_full = |length: u64, bytes: u64| {
+            (max_length > 0 && length >= max_length)
+                || (max_bytes > 0 && bytes.saturating_add(body_len) > max_bytes)
+        };
+        let mut length = self.msgs.len() as u64 + self.delayed_msgs.len();
+        let mut bytes = self.queued_bytes;
+        if !is_full(length, bytes) {
+            return Ok(());
+        }
+        self.overflow_count += 1;
+        self.effects.push(Effect::Counters {
+            id_sequence: self.id_sequence,
+            overflow_count: self.overflow_count,
+        });
+        if max_bytes > 0 && body_len > max_bytes {
+            return Err(ValkeyError::Str(
+                "message is larger than max bytes of the queue",
+            ));
+        }
+        if self.overflow_policy == OverflowPolicy::Reject {
+            return Err(ValkeyError::Str("queue is full"));
+        }
+        // oldest main messages that are not leased first, then the delayed messages scheduled first
+        let mut evicted = Vec::new();
+        let candidates = self
+            .msgs
+            .iter()
+            .filter(|msg| !msg.is_in_flight())
+            .map(|msg| ("msgs", msg))
+            .chain(
+                self.delayed_msgs
+                    .scores()
+                    .values()
+                    .flatten()
+                    .map(|msg| ("delayed", msg)),
+            );
+        for (list, msg) in candidates {
+            if !is_full(length, bytes) {
+                break;
+            }
+            length -= 1;
+            bytes = bytes.saturating_sub(msg.size());
+            evicted.push((list, *msg.id()));
+        }
+        // nothing is evicted when the leased messages alone fill the queue
+        if is_full(length, bytes) {
+            return Err(ValkeyError::Str("queue is full of leased messages"));
+        }
+        for (list, id) in evicted {
+            if self.overflow_policy == OverflowPolicy::Dlq {
+                self.move_to_dlq(list, id)?;
+            } else {
+                let oldest = self.take_msg(list, id)?;
+                self.release_body(&oldest);
+                self.effects.push(Effect::Remove {
+                    list: list.to_string(),
+                    id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the message with `id` from `list` to the back of the DLQ.
+    /// Messages that were never leased get the current time as `timeout_at`, so the retention
+    /// period starts when they are moved instead of never.
+    pub(crate) fn move_to_dlq(&mut self, list: &str, id: u64) -> Result<(), ValkeyError> {
+        let mut msg = self.take_msg(list, id)?;
+        if msg.timeout_at().is_none() {
+            msg.set_timeout_at(Some(utils::now_as_seconds()));
+        }
+        self.effects.push(Effect::Dlq {
+            list: list.to_string(),
+            id,
+            timeout_at: msg.timeout_at().unwrap_or(0),
+        });
+        self.dlq_msgs.push_back(msg);
+        self.add_event(QEvent::Dlq);
+        Ok(())
+    }
+
+    /// Passes the heap buffers of the messages to `realloc`, which may move them to a new allocation.
+    /// Main queue and DLQ messages are walked by position, the delayed queue by score.
+    ///
+    /// # Arguments
+    /// * `cursor` - Where to resume, 0 to start from the first message.
+    /// * `realloc` - Called with each buffer, may replace it with a new allocation.
+    /// * `should_stop` - Checked after each message, returns true when the time budget is used up.
+    ///
+    /// # Returns
+    /// The cursor to resume from, or `None` when all messages are done.
+    pub(crate) fn defrag(
+        &mut self,
+        cursor: u64,
+        realloc: &mut impl FnMut(&mut Vec<u8>),
+        should_stop: &impl Fn() -> bool,
+    ) -> Option<u64> {
+        // a cursor without the delayed bit is a position in msgs and dlq, the delayed walk starts over
+        let from = if cursor & DEFRAG_DELAYED_CURSOR == 0 {
+            let msgs_len = self.msgs.len() as u64;
+            let total = msgs_len + self.dlq_msgs.len() as u64;
+            for position in cursor..total {
+                let msg = if position < msgs_len {
+                    self.msgs.get_mut(position as usize)
+                } else {
+                    self.dlq_msgs.get_mut((position - msgs_len) as usize)
+                };
+                if let Some(msg) = msg {
+                    msg.defrag(realloc);
+                }
+                if should_stop() {
+                    return Some(position + 1);
+                }
+            }
+            0
+        } else {
+            cursor & !DEFRAG_DELAYED_CURSOR
+        };
+        self.delayed_msgs
+            .defrag(from, realloc, should_stop)
+            .map(|score| score | DEFRAG_DELAYED_CURSOR)
+    }
+
+    /// Deep copy of the queue under a new name, used by `COPY`.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the new queue.
+    /// * `reset_leases` - Make in-flight messages visible again in the copy without counting the delivery attempt.
+    ///
+    /// # Errors
+    /// Returns an error if the queue has claim-checked messages, the copy would share their companion keys.
+    pub(crate) fn copy(&self, name: &str, reset_leases: bool) -> Result<ValqType, ValkeyError> {
+        let claim_checked = self
+            .msgs
+            .iter()
+            .chain(self.dlq_msgs.iter())
+            .chain(self.delayed_msgs.members().keys())
+            .any(|msg| msg.body_key().is_some());
+        if claim_checked {
+            return Err(ValkeyError::Str(
+                "cannot copy q with message bodies stored in companion keys",
+            ));
+        }
+        let mut valq = self.clone();
+        valq.name = name.to_string();
+        valq.body_ops.clear();
+        valq.effects.clear();
+        valq.events.clear();
+        if reset_leases {
+            valq.msgs
+                .iter_mut()
+                .filter(|msg| msg.is_in_flight())
+                .for_each(|msg| msg.release());
+        }
+        Ok(valq)
+    }
+
+    /// Number of messages currently leased by consumers.
+    pub(crate) fn inflight_msgs(&self) -> usize {
+        self.msgs.iter().filter(|msg| msg.is_in_flight()).count()
+    }
+
+    /// Whether the number of leased messages has reached `max_inflight`.
+    pub(crate) fn check_max_inflight(&self) -> bool {
+        self.max_inflight > 0 && self.inflight_msgs() as u64 >= self.max_inflight
+    }
+
+    /// Whether the number of leased messages is above `max_inflight`, e.g. after it was lowered.
+    pub(crate) fn check_above_max_inflight(&self) -> bool {
+        self.max_inflight > 0 && self.inflight_msgs() as u64 > self.max_inflight
+    }
+
+    /// Groups that have reached `group_max_inflight` leased messages.
+    pub(crate) fn groups_at_max_inflight(&self) -> HashSet<String> {
+        self.groups_with_inflight(|count| count >= self.group_max_inflight)
+    }
+
+    /// Groups that have more than `group_max_inflight` leased messages, e.g. after it was lowered.
+    pub(crate) fn groups_above_max_inflight(&self) -> HashSet<String> {
+        self.groups_with_inflight(|count| count > self.group_max_inflight)
+    }
+
+    /// Groups whose number of leased messages matches `limit_reached`.
+    fn groups_with_inflight(&self, limit_reached: impl Fn(u64) -> bool) -> HashSet<String> {
+        if self.group_max_inflight == 0 {
+            return HashSet::new();
+        }
+        let mut inflight: HashMap<&String, u64> = HashMap::new();
+        for group in self
+            .msgs
+            .iter()
+            .filter(|msg| msg.is_in_flight())
+            .filter_map(|msg| msg.group().as_ref())
+        {
+            *inflight.entry(group).or_default() += 1;
+        }
+        inflight
+            .into_iter()
+            .filter(|(_, count)| limit_reached(*count))
+            .map(|(group, _)| group.clone())
+            .collect()
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::structs::body_encoding::BodyEncoding;
     use crate::structs::valq_type::ValqType;
 
+    /// Lease of a test message: consumer, client id and seconds since it was leased.
+    pub(crate) type TestLease = (Option<&'static str>, Option<u64>, u64);
+
+    /// Queue `q` with messages `msg1` to `msgN` in the main queue, leased for another 30 seconds
+    /// when their lease is set, followed by one delayed message with score 100.
+    /// Shared by the unit tests of the commands and utils.
+    pub(crate) fn valq_with_msgs(leases: &[Option<TestLease>]) -> ValqType {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        for (index, lease) in leases.iter().enumerate() {
+            let id = index as u64 + 1;
+            let mut msg = ValqMsg::new(id, format!("msg{}", id), None, 0);
+            if let Some((consumer, client_id, idle)) = lease {
+                msg.lease(
+                    consumer.map(str::to_string),
+                    *client_id,
+                    utils::now_as_seconds() + 30,
+                );
+                msg.set_leased_at(Some(utils::now_as_seconds() - idle));
+                msg.set_delivery_attempts(1);
+            }
+            valq.add_msg("msgs", 0, msg).unwrap();
+        }
+        let id = leases.len() as u64 + 1;
+        let msg = ValqMsg::new(id, format!("msg{}", id), None, 0);
+        valq.add_msg("delayed", 100, msg).unwrap();
+        valq
+    }
+
     #[test]
     fn valq_type_init_empty() {
         let valq = ValqType::new("q", None, None, None).unwrap();
@@ -254,4 +845,377 @@ mod tests {
         assert!(test.is_err());
         assert_eq!(*valq.retention_period(), RETENTION_PERIOD_DEFAULT);
     }
+
+    #[test]
+    fn valq_type_set_retry_policy() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert_eq!(*valq.retry_policy(), RetryPolicy::default());
+        let test = valq.set_retry_policy(RetryPolicy::new(5, 600, 20));
+        assert!(test.is_ok());
+        assert_eq!(*valq.retry_policy(), RetryPolicy::new(5, 600, 20));
+    }
+
+    #[test]
+    fn valq_type_set_retry_policy_invalid() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let test = valq.set_retry_policy(RetryPolicy::new(10, 5, 0));
+        assert!(test.is_err());
+        let test = valq.set_retry_policy(RetryPolicy::new(1, RETRY_BACKOFF_MAX + 1, 0));
+        assert!(test.is_err());
+        let test = valq.set_retry_policy(RetryPolicy::new(1, 10, RETRY_JITTER_MAX + 1));
+        assert!(test.is_err());
+        assert_eq!(*valq.retry_policy(), RetryPolicy::default());
+    }
+
+    #[test]
+    fn valq_type_set_max_inflight() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert_eq!(*valq.max_inflight(), 0);
+        assert!(valq.set_max_inflight(2).is_ok());
+        assert_eq!(*valq.max_inflight(), 2);
+        let test = valq.set_max_inflight(MAX_INFLIGHT_MAX + 1);
+        assert!(test.is_err());
+        assert_eq!(*valq.max_inflight(), 2);
+    }
+
+    #[test]
+    fn valq_type_check_max_inflight() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let mut msg1 = ValqMsg::new(1, "msg1".to_string(), None, 1);
+        msg1.lease(None, None, crate::utils::now_as_seconds() + 30);
+        valq.msgs_mut().push_back(msg1);
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        assert_eq!(valq.inflight_msgs(), 1);
+        // unlimited
+        assert!(!valq.check_max_inflight());
+        let _ = valq.set_max_inflight(2);
+        assert!(!valq.check_max_inflight());
+        let _ = valq.set_max_inflight(1);
+        assert!(valq.check_max_inflight());
+    }
+
+    #[test]
+    fn valq_type_groups_at_max_inflight() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        for (id, group, leased) in [(1, "t1", true), (2, "t1", false), (3, "t2", true)] {
+            let mut msg = ValqMsg::new(id, format!("msg{}", id), None, 0);
+            msg.set_group(Some(group.to_string()));
+            if leased {
+                msg.lease(None, None, crate::utils::now_as_seconds() + 30);
+            }
+            valq.msgs_mut().push_back(msg);
+        }
+        // unlimited
+        assert!(valq.groups_at_max_inflight().is_empty());
+        let _ = valq.set_group_max_inflight(1);
+        assert_eq!(
+            valq.groups_at_max_inflight(),
+            HashSet::from(["t1".to_string(), "t2".to_string()])
+        );
+        let _ = valq.set_group_max_inflight(2);
+        assert!(valq.groups_at_max_inflight().is_empty());
+        assert!(valq.set_group_max_inflight(MAX_INFLIGHT_MAX + 1).is_err());
+    }
+
+    #[test]
+    fn valq_type_set_rate_limit() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert_eq!(*valq.rate_limiter(), RateLimiter::default());
+        assert!(valq.set_rate_limit(10, 1).is_ok());
+        assert_eq!(*valq.rate_limiter().rate(), 10);
+        assert_eq!(*valq.rate_limiter().per(), 1);
+        assert!(valq.set_rate_limit(RATE_LIMIT_MAX + 1, 1).is_err());
+        assert!(valq.set_rate_limit(10, 0).is_err());
+        assert!(valq.set_rate_limit(10, RATE_PER_MAX + 1).is_err());
+        assert_eq!(*valq.rate_limiter().rate(), 10);
+        // disabling does not need a period
+        assert!(valq.set_rate_limit(0, 0).is_ok());
+    }
+
+    fn valq_with_limits(max_length: u64, max_bytes: u64, policy: OverflowPolicy) -> ValqType {
+        let mut valq = valq_with_msgs(&[None]);
+        valq.set_max_length(max_length);
+        valq.set_max_bytes(max_bytes);
+        valq.set_overflow_policy(policy);
+        valq
+    }
+
+    #[test]
+    fn valq_type_add_and_take_msg() {
+        let mut valq = valq_with_limits(0, 0, OverflowPolicy::Reject);
+        let msg = ValqMsg::new(3, "dlq_msg3".to_string(), None, 0);
+        valq.add_msg("dlq", 0, msg).unwrap();
+        assert!(valq.add_msg("invalid", 0, ValqMsg::default()).is_err());
+        // the dlq is not counted
+        assert_eq!(*valq.queued_bytes(), 8);
+        assert_eq!(*valq.take_msg("delayed", 2).unwrap().id(), 2);
+        assert_eq!(*valq.queued_bytes(), 4);
+        assert_eq!(*valq.take_msg("dlq", 3).unwrap().id(), 3);
+        assert_eq!(*valq.queued_bytes(), 4);
+        assert!(valq.take_msg("msgs", 2).is_err());
+        assert!(valq.take_msg("invalid", 1).is_err());
+        valq.msgs_mut().clear();
+        valq.count_queued_bytes();
+        assert_eq!(*valq.queued_bytes(), 0);
+    }
+
+    #[test]
+    fn valq_type_make_room_unlimited() {
+        let mut valq = valq_with_limits(0, 0, OverflowPolicy::Reject);
+        assert!(valq.make_room(100).is_ok());
+        assert_eq!(*valq.overflow_count(), 0);
+        assert!(valq.effects().is_empty());
+    }
+
+    #[test]
+    fn valq_type_make_room_reject() {
+        let mut valq = valq_with_limits(2, 0, OverflowPolicy::Reject);
+        assert!(valq.make_room(4).is_err());
+        assert_eq!(*valq.overflow_count(), 1);
+        assert_eq!(valq.msgs().len(), 1);
+        let mut valq = valq_with_limits(0, 10, OverflowPolicy::Reject);
+        assert!(valq.make_room(2).is_ok());
+        assert!(valq.make_room(3).is_err());
+    }
+
+    #[test]
+    fn valq_type_make_room_drop_oldest() {
+        let mut valq = valq_with_limits(1, 0, OverflowPolicy::DropOldest);
+        assert!(valq.make_room(4).is_ok());
+        assert!(valq.msgs().is_empty());
+        assert!(valq.dlq_msgs().is_empty());
+        assert_eq!(*valq.overflow_count(), 1);
+        // drops delayed messages when the main queue is empty
+        let mut valq = valq_with_limits(0, 8, OverflowPolicy::DropOldest);
+        assert!(valq.make_room(8).is_ok());
+        assert!(valq.msgs().is_empty());
+        assert_eq!(valq.delayed_msgs().len(), 0);
+        assert_eq!(*valq.queued_bytes(), 0);
+        assert_eq!(
+            valq.effects()[2],
+            Effect::Remove {
+                list: "delayed".to_string(),
+                id: 2
+            }
+        );
+        // message alone is larger than max bytes
+        assert!(valq.make_room(9).is_err());
+    }
+
+    #[test]
+    fn valq_type_make_room_keeps_leased_msgs() {
+        let mut valq = valq_with_limits(2, 0, OverflowPolicy::DropOldest);
+        let mut msg = ValqMsg::new(3, "msg3".to_string(), None, 1);
+        msg.lease(None, None, crate::utils::now_as_seconds() + 30);
+        valq.msgs_mut().push_front(msg);
+        // msg1 and the delayed message go, the leased message stays
+        assert!(valq.make_room(4).is_ok());
+        assert_eq!(*valq.msgs()[0].id(), 3);
+        assert_eq!(valq.delayed_msgs().len(), 0);
+        // only leased messages left
+        let _ = valq.set_max_length(1);
+        assert!(valq.make_room(4).is_err());
+        assert_eq!(*valq.msgs()[0].id(), 3);
+        assert!(valq.msgs()[0].is_in_flight());
+    }
+
+    #[test]
+    fn valq_type_make_room_dlq() {
+        let mut valq = valq_with_limits(2, 0, OverflowPolicy::Dlq);
+        assert!(valq.make_room(4).is_ok());
+        assert!(valq.msgs().is_empty());
+        assert_eq!(*valq.dlq_msgs()[0].id(), 1);
+        assert!(valq.dlq_msgs()[0].timeout_at().is_some());
+        assert_eq!(valq.delayed_msgs().len(), 1);
+        assert_eq!(
+            valq.effects()[1],
+            Effect::Dlq {
+                list: "msgs".to_string(),
+                id: 1,
+                timeout_at: valq.dlq_msgs()[0].timeout_at().unwrap(),
+            }
+        );
+        assert_eq!(*valq.events(), [QEvent::Dlq]);
+    }
+
+    #[test]
+    fn valq_type_add_event() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        valq.add_event(QEvent::Pop);
+        valq.add_event(QEvent::Redelivered);
+        valq.add_event(QEvent::Pop);
+        assert_eq!(*valq.events(), [QEvent::Pop, QEvent::Redelivered]);
+    }
+
+    #[test]
+    fn valq_type_check_msg_size() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        assert!(valq.check_msg_size(10, 10).is_ok());
+        assert!(valq.check_msg_size(11, 10).is_err());
+        // queue override takes precedence over the module default
+        assert!(valq.set_max_msg_size(20).is_ok());
+        assert!(valq.check_msg_size(11, 10).is_ok());
+        assert!(valq.check_msg_size(21, 10).is_err());
+        assert!(valq.set_max_msg_size(MAX_MSG_SIZE_MAX + 1).is_err());
+        assert_eq!(*valq.max_msg_size(), 20);
+    }
+
+    #[test]
+    fn valq_type_store_and_release_body() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let mut msg = ValqMsg::new(1, "large body".to_string(), None, 0);
+        // disabled
+        valq.store_body(&mut msg);
+        assert_eq!(msg.body(), "large body");
+        assert!(valq.set_claim_check_threshold(5).is_ok());
+        valq.store_body(&mut msg);
+        assert_eq!(msg.body(), "");
+        assert_eq!(msg.body_key().as_deref(), Some("{q}:body:1"));
+        assert_eq!(msg.size(), 10);
+        valq.release_body(&msg);
+        assert_eq!(
+            *valq.body_ops(),
+            vec![
+                BodyOp::Store {
+                    key: "{q}:body:1".to_string(),
+                    body: "large body".to_string()
+                },
+                BodyOp::Delete("{q}:body:1".to_string())
+            ]
+        );
+        // small bodies stay in the message
+        let mut msg = ValqMsg::new(2, "small".to_string(), None, 0);
+        valq.store_body(&mut msg);
+        assert_eq!(msg.body(), "small");
+        assert!(
+            valq.set_claim_check_threshold(MAX_MSG_SIZE_MAX + 1)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn valq_type_store_body_compress() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let body = "{\"status\":\"ok\"}".repeat(20);
+        // disabled
+        let mut msg = ValqMsg::new(1, body.clone(), None, 0);
+        valq.store_body(&mut msg);
+        assert_eq!(*msg.encoding(), BodyEncoding::Raw);
+        assert!(valq.set_compress_threshold(100).is_ok());
+        valq.store_body(&mut msg);
+        assert_eq!(*msg.encoding(), BodyEncoding::Deflate);
+        assert_eq!(msg.decoded_body().unwrap(), body);
+        assert!(valq.body_ops().is_empty());
+        // below the threshold
+        let mut msg = ValqMsg::new(2, "small".to_string(), None, 0);
+        valq.store_body(&mut msg);
+        assert_eq!(*msg.encoding(), BodyEncoding::Raw);
+        // claim-checked bodies are stored raw
+        assert!(valq.set_claim_check_threshold(200).is_ok());
+        let mut msg = ValqMsg::new(3, body.clone(), None, 0);
+        valq.store_body(&mut msg);
+        assert_eq!(*msg.encoding(), BodyEncoding::Raw);
+        assert!(msg.body_key().is_some());
+        assert!(valq.set_compress_threshold(MAX_MSG_SIZE_MAX + 1).is_err());
+    }
+
+    #[test]
+    fn valq_type_bytes_and_mem_usage() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        let empty = valq.mem_usage();
+        assert!(empty >= size_of::<ValqType>());
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(1, "msg1".to_string(), None, 0));
+        valq.dlq_msgs_mut()
+            .push_back(ValqMsg::new(2, "dlq_msg1".to_string(), None, 0));
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(3, "delayed1".to_string(), None, 0), 100);
+        assert_eq!(valq.msgs_bytes(), 4);
+        assert_eq!(valq.dlq_bytes(), 8);
+        assert_eq!(valq.delayed_bytes(), 8);
+        assert_eq!(valq.bytes(), 20);
+        assert!(valq.mem_usage() > empty + valq.bytes() as usize);
+    }
+
+    #[test]
+    fn valq_type_copy() {
+        let mut valq = ValqType::new("q1", None, None, None).unwrap();
+        valq.set_id_sequence(2);
+        let mut msg = ValqMsg::new(1, "msg1".to_string(), None, 1);
+        msg.lease(
+            Some("worker1".to_string()),
+            None,
+            crate::utils::now_as_seconds() + 30,
+        );
+        valq.msgs_mut().push_back(msg);
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        let test = valq.copy("q2", false).unwrap();
+        assert_eq!(test.name(), "q2");
+        assert_eq!(*test.id_sequence(), 2);
+        assert_eq!(test.inflight_msgs(), 1);
+        assert_eq!(valq.name(), "q1");
+        let test = valq.copy("q2", true).unwrap();
+        assert_eq!(test.inflight_msgs(), 0);
+        assert_eq!(test.msgs()[0].consumer(), &None);
+        assert_eq!(*test.msgs()[0].delivery_attempts(), 0);
+        // the source keeps its leases
+        assert_eq!(valq.inflight_msgs(), 1);
+        // claim-checked messages would share companion keys
+        assert!(valq.set_claim_check_threshold(2).is_ok());
+        let mut msg = ValqMsg::new(3, "msg3".to_string(), None, 0);
+        valq.store_body(&mut msg);
+        valq.msgs_mut().push_back(msg);
+        assert!(valq.copy("q2", false).is_err());
+    }
+
+    #[test]
+    fn valq_type_defrag() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        valq.msgs_mut()
+            .push_back(ValqMsg::new(1, "msg1".to_string(), None, 0));
+        valq.dlq_msgs_mut()
+            .push_back(ValqMsg::new(2, "msg2".to_string(), None, 0));
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(3, "msg3".to_string(), None, 0), 100);
+        let mut bodies = vec![];
+        let mut realloc = |buffer: &mut Vec<u8>| {
+            if !buffer.is_empty() {
+                bodies.push(String::from_utf8_lossy(buffer).to_string());
+            }
+        };
+        // stop after every message
+        let test = valq.defrag(0, &mut realloc, &|| true);
+        assert_eq!(test, Some(1));
+        let test = valq.defrag(1, &mut realloc, &|| true);
+        assert_eq!(test, Some(2));
+        // the delayed queue checks before each score bucket
+        let test = valq.defrag(2, &mut realloc, &|| true);
+        assert_eq!(test, Some(100 | DEFRAG_DELAYED_CURSOR));
+        let test = valq.defrag(100 | DEFRAG_DELAYED_CURSOR, &mut realloc, &|| false);
+        assert_eq!(test, None);
+        assert_eq!(bodies, ["msg1", "msg2", "msg3", "msg3"]);
+    }
+
+    #[test]
+    fn valq_type_defrag_resume_in_msgs() {
+        let mut valq = ValqType::new("q", None, None, None).unwrap();
+        for id in 1..=3 {
+            valq.msgs_mut()
+                .push_back(ValqMsg::new(id, format!("msg{}", id), None, 0));
+        }
+        // a score below the cursor position is still walked
+        valq.delayed_msgs_mut()
+            .insert(ValqMsg::new(4, "msg4".to_string(), None, 0), 1);
+        let mut bodies = vec![];
+        let mut realloc = |buffer: &mut Vec<u8>| {
+            if !buffer.is_empty() {
+                bodies.push(String::from_utf8_lossy(buffer).to_string());
+            }
+        };
+        let test = valq.defrag(2, &mut realloc, &|| false);
+        assert_eq!(test, None);
+        assert_eq!(bodies, ["msg3", "msg4", "msg4"]);
+    }
 }
@@ -0,0 +1,342 @@
+use crate::UNLINKED_BODY_KEYS;
+use crate::data_types::VALQ_TYPE;
+use crate::structs::valq_type::ValqType;
+use crate::utils::{notify, q_registry};
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use valkey_module::key::ValkeyKeyWritable;
+use valkey_module::logging::log_notice;
+use valkey_module::redisvalue::ValkeyValueKey;
+use valkey_module::{Context, ContextFlags, Status, ValkeyError, ValkeyString, ValkeyValue, raw};
+
+/// Change to a companion key that holds the body of a large message.
+/// Handlers queue these on `ValqType` and the command applies them with `apply_body_ops`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum BodyOp {
+    /// Write the body to the companion key.
+    Store { key: String, body: String },
+    /// Delete the companion key.
+    Delete(String),
+}
+
+/// Name of the companion key for a message body.
+/// The queue name is used as a hash tag so both keys live in the same cluster slot.
+pub(crate) fn body_key(q_name: &str, msg_id: u64) -> String {
+    let has_hash_tag = q_name
+        .find('{')
+        .is_some_and(|start| q_name[start + 1..].find('}').is_some_and(|len| len > 0));
+    if has_hash_tag {
+        format!("{}:body:{}", q_name, msg_id)
+    } else {
+        format!("{{{}}}:body:{}", q_name, msg_id)
+    }
+}
+
+/// Refuses a push whose body would be stored in a companion key that holds another type.
+pub(crate) fn check_body_key(
+    ctx: &Context,
+    valq: &ValqType,
+    body_len: u64,
+) -> Result<(), ValkeyError> {
+    let threshold = *valq.claim_check_threshold();
+    if threshold == 0 || body_len <= threshold {
+        return Ok(());
+    }
+    let key_name = body_key(valq.name(), valq.id_sequence() + 1);
+    check_key_type(
+        ctx.open_key(&ctx.create_string(key_name.as_str()))
+            .key_type(),
+        &key_name,
+    )
+}
+
+fn check_key_type(key_type: raw::KeyType, key_name: &str) -> Result<(), ValkeyError> {
+    match key_type {
+        raw::KeyType::Empty | raw::KeyType::String => Ok(()),
+        _ => Err(ValkeyError::String(format!(
+            "companion key {} holds another type",
+            key_name
+        ))),
+    }
+}
+
+/// Writes the bodies queued by `ValqType::store_body` on the queue stored in `key` to their
+/// companion keys and sends the `SET` to replicas and the AOF ahead of the message.
+pub(crate) fn store_bodies(ctx: &Context, key: &ValkeyKeyWritable) -> Result<(), ValkeyError> {
+    let stores: Vec<BodyOp> = match key.get_value::<ValqType>(&VALQ_TYPE)? {
+        Some(valq) => {
+            let (stores, others) = std::mem::take(valq.body_ops_mut())
+                .into_iter()
+                .partition(|body_op| matches!(body_op, BodyOp::Store { .. }));
+            *valq.body_ops_mut() = others;
+            stores
+        }
+        None => return Ok(()),
+    };
+    for body_op in stores {
+        if let BodyOp::Store { key, body } = body_op {
+            let key_arg = ctx.create_string(key.as_str());
+            let body_key = ctx.open_key_writable(&key_arg);
+            check_key_type(body_key.key_type(), &key)?;
+            body_key.write(body.as_str())?;
+            ctx.replicate("SET", &[key.as_str(), body.as_str()]);
+            notify::signal_modified(ctx, &key_arg);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the companion key changes queued on the queue stored in `key`.
+pub(crate) fn apply_body_ops(ctx: &Context, key: &ValkeyKeyWritable) -> Result<(), ValkeyError> {
+    let body_ops = match key.get_value::<ValqType>(&VALQ_TYPE)? {
+        Some(valq) => std::mem::take(valq.body_ops_mut()),
+        None => return Ok(()),
+    };
+    for body_op in body_ops {
+        match body_op {
+            BodyOp::Store { key, body } => {
+                let key_arg = ctx.create_string(key.as_str());
+                let body_key = ctx.open_key_writable(&key_arg);
+                check_key_type(body_key.key_type(), &key)?;
+                body_key.write(body.as_str())?;
+                notify::signal_modified(ctx, &key_arg);
+            }
+            BodyOp::Delete(key) => {
+                let key_arg = ctx.create_string(key.as_str());
+                let body_key = ctx.open_key_writable(&key_arg);
+                // leave keys that were overwritten with another type alone
+                if body_key.key_type() != raw::KeyType::String {
+                    continue;
+                }
+                body_key.delete()?;
+                notify::signal_modified(ctx, &key_arg);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Subscribes to generic, expired and evicted keyspace events to delete the companion keys of
+/// queues that are removed by `DEL`, `UNLINK`, `RESTORE REPLACE`, expiry or eviction, see `unlinked`.
+/// Queues overwritten by `SET` or another command of a data type only fire that type's event, their
+/// companion keys are deleted at the next generic, expired or evicted event of any key.
+/// https://valkey.io/topics/modules-api-ref/#ValkeyModule_AddPostNotificationJob
+pub(crate) fn subscribe(ctx: &Context) -> Status {
+    let Some(subscribe_to_keyspace_events) =
+        (unsafe { raw::RedisModule_SubscribeToKeyspaceEvents })
+    else {
+        return Status::Err;
+    };
+    let status = unsafe {
+        subscribe_to_keyspace_events(
+            ctx.ctx,
+            (raw::NotifyEvent::GENERIC | raw::NotifyEvent::EXPIRED | raw::NotifyEvent::EVICTED)
+                .bits(),
+            Some(on_keyspace_event),
+        )
+    };
+    Status::from(status)
+}
+
+/// Remembers the companion keys of the queue unlinked from `key_name` of `db`, called by `unlink2`.
+/// `RENAME` and `MOVE` also unlink the source key, so the companion keys are only deleted by the
+/// job added on the keyspace event that follows when it's not `rename_from` or `move_from`.
+pub(crate) fn unlinked(db: i32, key_name: &str, valq: &ValqType) {
+    let body_keys = referenced_body_keys(valq);
+    if body_keys.is_empty() {
+        return;
+    }
+    match UNLINKED_BODY_KEYS.write() {
+        Ok(mut unlinked) => {
+            unlinked.insert((db, key_name.to_string()), body_keys);
+        }
+        Err(err) => log_notice(format!("claim_check unlinked err: {}", err)),
+    }
+}
+
+/// Companion keys of all messages in the queue.
+fn referenced_body_keys(valq: &ValqType) -> HashSet<String> {
+    valq.msgs()
+        .iter()
+        .chain(valq.dlq_msgs().iter())
+        .chain(valq.delayed_msgs().members().keys())
+        .filter_map(|msg| msg.body_key().clone())
+        .collect()
+}
+
+extern "C" fn on_keyspace_event(
+    ctx: *mut raw::RedisModuleCtx,
+    _type: c_int,
+    event: *const c_char,
+    key: *mut raw::RedisModuleString,
+) -> c_int {
+    // return early unless a queue was unlinked
+    if !UNLINKED_BODY_KEYS
+        .read()
+        .is_ok_and(|unlinked| !unlinked.is_empty())
+        || event.is_null()
+        || key.is_null()
+    {
+        return Status::Ok as c_int;
+    }
+    let ctx = Context::new(ctx);
+    let event = unsafe { CStr::from_ptr(event) }.to_string_lossy();
+    if event == "rename_from" || event == "move_from" {
+        let key_name = String::from_utf8_lossy(ValkeyString::string_as_slice(key)).to_string();
+        if let Ok(mut unlinked) = UNLINKED_BODY_KEYS.write() {
+            unlinked.remove(&(q_registry::selected_db(&ctx), key_name));
+            if unlinked.is_empty() {
+                return Status::Ok as c_int;
+            }
+        }
+    }
+    // keys can't be written in a keyspace event callback
+    if let Some(add_post_notification_job) = unsafe { raw::RedisModule_AddPostNotificationJob } {
+        unsafe {
+            add_post_notification_job(
+                ctx.ctx,
+                Some(delete_unlinked_body_keys),
+                ptr::null_mut(),
+                None,
+            )
+        };
+    }
+    Status::Ok as c_int
+}
+
+/// Deletes the companion keys remembered by `unlinked` and sends the deletes to replicas and the AOF.
+/// Keys still referenced by a queue stored under the same key, e.g. restored from a dump of the
+/// same queue, are kept.
+extern "C" fn delete_unlinked_body_keys(ctx: *mut raw::RedisModuleCtx, _pd: *mut c_void) {
+    let unlinked = match UNLINKED_BODY_KEYS.write() {
+        Ok(mut unlinked) => std::mem::take(&mut *unlinked),
+        Err(_) => return,
+    };
+    let ctx = Context::new(ctx);
+    // replicas get the deletes from the primary
+    if unlinked.is_empty() || ctx.get_flags().contains(ContextFlags::SLAVE) {
+        return;
+    }
+    let selected = q_registry::selected_db(&ctx);
+    for ((db, key_name), body_keys) in unlinked {
+        if !q_registry::select_db(&ctx, db) {
+            continue;
+        }
+        let referenced = match ctx
+            .open_key(&ctx.create_string(key_name.as_str()))
+            .get_value::<ValqType>(&VALQ_TYPE)
+        {
+            Ok(Some(valq)) => referenced_body_keys(valq),
+            _ => HashSet::new(),
+        };
+        for body_key in body_keys.difference(&referenced) {
+            let key_arg = ctx.create_string(body_key.as_str());
+            let key = ctx.open_key_writable(&key_arg);
+            if key.key_type() != raw::KeyType::String {
+                continue;
+            }
+            if key.delete().is_ok() {
+                ctx.replicate("DEL", &[body_key.as_str()]);
+                notify::signal_modified(&ctx, &key_arg);
+            }
+        }
+    }
+    q_registry::select_db(&ctx, selected);
+}
+
+/// Replaces the empty body of claim-checked messages in a pop or claim reply with the companion key value.
+/// Returns an error if a companion key was deleted, expired or evicted, the message stays leased
+/// and is delivered again or moved to the DLQ like any other unacknowledged message.
+pub(crate) fn load_bodies(
+    ctx: &Context,
+    valq: &ValqType,
+    value: ValkeyValue,
+) -> Result<ValkeyValue, ValkeyError> {
+    match value {
+        ValkeyValue::OrderedMap(map) => Ok(ValkeyValue::OrderedMap(load_body(ctx, valq, map)?)),
+        ValkeyValue::Array(msgs) => Ok(ValkeyValue::Array(
+            msgs.into_iter()
+                .map(|msg| load_bodies(ctx, valq, msg))
+                .collect::<Result<_, _>>()?,
+        )),
+        value => Ok(value),
+    }
+}
+
+fn load_body(
+    ctx: &Context,
+    valq: &ValqType,
+    mut map: BTreeMap<ValkeyValueKey, ValkeyValue>,
+) -> Result<BTreeMap<ValkeyValueKey, ValkeyValue>, ValkeyError> {
+    let msg = match map.get(&ValkeyValueKey::String("id".to_string())) {
+        Some(ValkeyValue::BulkString(id)) => {
+            valq.msgs().iter().find(|msg| msg.id().to_string() == *id)
+        }
+        _ => None,
+    };
+    if let Some(msg) = msg {
+        if let Some(body_key) = msg.body_key() {
+            let key = ctx.open_key(&ctx.create_string(body_key.as_str()));
+            let body = match key.read() {
+                Ok(Some(body)) => String::from_utf8_lossy(body).to_string(),
+                _ => {
+                    return Err(ValkeyError::String(format!(
+                        "message {} body key missing",
+                        msg.id()
+                    )));
+                }
+            };
+            map.insert(
+                ValkeyValueKey::String("body".to_string()),
+                ValkeyValue::BulkString(body),
+            );
+        }
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::valq_msg::ValqMsg;
+
+    #[test]
+    fn test_check_key_type() {
+        assert!(check_key_type(raw::KeyType::Empty, "{q}:body:1").is_ok());
+        assert!(check_key_type(raw::KeyType::String, "{q}:body:1").is_ok());
+        assert!(check_key_type(raw::KeyType::Hash, "{q}:body:1").is_err());
+    }
+
+    #[test]
+    fn test_unlinked() {
+        let mut valq = ValqType::new("unlinked-q", None, None, None).unwrap();
+        unlinked(98, "unlinked-q", &valq);
+        assert!(
+            !UNLINKED_BODY_KEYS
+                .read()
+                .unwrap()
+                .contains_key(&(98, "unlinked-q".to_string()))
+        );
+        assert!(valq.set_claim_check_threshold(2).is_ok());
+        let mut msg = ValqMsg::new(1, "msg1".to_string(), None, 0);
+        valq.store_body(&mut msg);
+        valq.msgs_mut().push_back(msg);
+        unlinked(98, "unlinked-q", &valq);
+        let test = UNLINKED_BODY_KEYS
+            .write()
+            .unwrap()
+            .remove(&(98, "unlinked-q".to_string()));
+        assert_eq!(
+            test,
+            Some(HashSet::from(["{unlinked-q}:body:1".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_body_key() {
+        assert_eq!(body_key("q1", 5), "{q1}:body:5");
+        assert_eq!(body_key("{tenant1}:q1", 5), "{tenant1}:q1:body:5");
+    }
+}
@@ -0,0 +1,43 @@
+use crate::GLOBAL_Q_REGISTRY;
+use std::os::raw::c_void;
+use valkey_module::logging::log_notice;
+use valkey_module::{Context, Status, raw};
+
+/// Subscribes to the flush server event to remove flushed queues from `GLOBAL_Q_REGISTRY`.
+/// FLUSHALL and FLUSHDB free the keys without calling the `unlink` callback.
+/// https://valkey.io/topics/modules-api-ref/#ValkeyModule_SubscribeToServerEvent
+pub(crate) fn subscribe(ctx: &Context) -> Status {
+    raw::subscribe_to_server_event(
+        ctx.ctx,
+        raw::RedisModuleEvent {
+            id: raw::REDISMODULE_EVENT_FLUSHDB,
+            dataver: 1,
+        },
+        Some(on_flush),
+    )
+}
+
+extern "C" fn on_flush(
+    _ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    subevent: u64,
+    data: *mut c_void,
+) {
+    // the queues are unregistered before the keys are freed, FLUSHALL ASYNC frees them later
+    if subevent != raw::REDISMODULE_SUBEVENT_FLUSHDB_START || data.is_null() {
+        return;
+    }
+    let dbnum = unsafe { (*data.cast::<raw::RedisModuleFlushInfoV1>()).dbnum };
+    run(dbnum);
+}
+
+fn run(dbnum: i32) {
+    match GLOBAL_Q_REGISTRY.write() {
+        Ok(mut registry) => {
+            registry.flush_db(dbnum);
+        }
+        Err(err) => {
+            log_notice(format!("flush_db err: {}", err));
+        }
+    };
+}
@@ -1,3 +1,9 @@
+pub(crate) mod claim_check;
+pub(crate) mod flush_db;
+pub(crate) mod notify;
+pub(crate) mod q_registry;
+pub(crate) mod release_on_disconnect;
+pub(crate) mod replication;
 pub(crate) mod retention_period_gc;
 
 use crate::MIN_VALID_SERVER_VERSION;
@@ -11,24 +17,62 @@ pub(crate) fn now_as_seconds() -> u64 {
         .as_secs()
 }
 
+pub(crate) fn now_as_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
 pub(crate) fn valid_server_version(version: Version) -> bool {
     let server_version = &[version.major, version.minor, version.patch];
     server_version >= MIN_VALID_SERVER_VERSION
 }
 
+/// Reads the numeric value that follows a keyword argument such as `TIMEOUT 10`.
+pub(crate) fn next_u64_option(
+    args: &mut impl Iterator<Item = String>,
+    name: &str,
+) -> Result<u64, ValkeyError> {
+    args.next()
+        .and_then(|arg| arg.parse::<u64>().ok())
+        .ok_or_else(|| ValkeyError::String(format!("specify {} as a positive integer", name)))
+}
+
+/// Rejects commands sent directly to a replica, commands replicated from the primary are allowed.
 /// https://valkey.io/topics/modules-api-ref/#ValkeyModule_GetContextFlagsAll
-pub(crate) fn replicate_cmd_check(ctx: &Context) -> ValkeyResult {
+pub(crate) fn replica_cmd_check(ctx: &Context) -> ValkeyResult {
     let flags = ctx.get_flags();
     if flags.contains(ContextFlags::READONLY) && !flags.contains(ContextFlags::REPLICATED) {
         Err(ValkeyError::Str(
             "cannot execute command directly on a replica node",
         ))
     } else {
-        ctx.replicate_verbatim();
         Ok("OK".into())
     }
 }
 
+/// Rejects commands sent by clients, only commands replicated from the primary or loaded from the
+/// AOF are allowed. Used by the commands that write queue state as is.
+pub(crate) fn internal_cmd_check(ctx: &Context) -> ValkeyResult {
+    let flags = ctx.get_flags();
+    if flags.contains(ContextFlags::REPLICATED) || flags.contains(ContextFlags::LOADING) {
+        Ok("OK".into())
+    } else {
+        Err(ValkeyError::Str(
+            "command is only accepted from the primary or the AOF",
+        ))
+    }
+}
+
+/// Same as `replica_cmd_check` and replicates the command verbatim,
+/// commands that read the clock or pick messages send `replication::Effect`s instead.
+pub(crate) fn replicate_cmd_check(ctx: &Context) -> ValkeyResult {
+    replica_cmd_check(ctx)?;
+    ctx.replicate_verbatim();
+    Ok("OK".into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,4 +100,12 @@ mod tests {
         };
         assert!(!valid_server_version(version));
     }
+
+    #[test]
+    fn test_next_u64_option() {
+        let mut args = vec!["10".to_string(), "invalid".to_string()].into_iter();
+        assert_eq!(next_u64_option(&mut args, "timeout").unwrap(), 10);
+        assert!(next_u64_option(&mut args, "timeout").is_err());
+        assert!(next_u64_option(&mut args, "timeout").is_err());
+    }
 }
@@ -0,0 +1,34 @@
+use crate::data_types::VALQ_TYPE;
+use crate::structs::valq_type::ValqType;
+use valkey_module::key::ValkeyKeyWritable;
+use valkey_module::{Context, NotifyEvent, ValkeyString, raw};
+
+/// Emits the keyspace events queued on the queue stored in `key` as `module` class events.
+/// The key is signalled as modified when the command queued events or replication effects,
+/// call this before `replication::replicate_effects` which drains the effects.
+/// https://valkey.io/topics/modules-api-ref/#ValkeyModule_NotifyKeyspaceEvent
+pub(crate) fn notify_events(ctx: &Context, key_arg: &ValkeyString, key: &ValkeyKeyWritable) {
+    let (events, modified) = match key.get_value::<ValqType>(&VALQ_TYPE) {
+        Ok(Some(valq)) => {
+            let events = std::mem::take(valq.events_mut());
+            let modified = !events.is_empty() || !valq.effects().is_empty();
+            (events, modified)
+        }
+        _ => return,
+    };
+    if modified {
+        signal_modified(ctx, key_arg);
+    }
+    for event in events {
+        ctx.notify_keyspace_event(NotifyEvent::MODULE, event.as_str(), key_arg);
+    }
+}
+
+/// Invalidates `WATCH` and client side caching of `key_arg`. The module sets
+/// `NO_IMPLICIT_SIGNAL_MODIFIED`, so keys opened for writing are only signalled when they change.
+/// https://valkey.io/topics/modules-api-ref/#ValkeyModule_SignalModifiedKey
+pub(crate) fn signal_modified(ctx: &Context, key_arg: &ValkeyString) {
+    if let Some(signal_modified_key) = unsafe { raw::RedisModule_SignalModifiedKey } {
+        unsafe { signal_modified_key(ctx.ctx, key_arg.inner) };
+    }
+}
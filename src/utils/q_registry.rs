@@ -0,0 +1,222 @@
+use crate::GLOBAL_Q_REGISTRY;
+use crate::data_types::VALQ_TYPE;
+use crate::structs::q_registry::QRegistry;
+use crate::structs::valq_type::ValqType;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use valkey_module::logging::log_notice;
+use valkey_module::{Context, KeysCursor, Status, ValkeyError, ValkeyString, raw};
+
+/// Keyspace events that store an existing value under a new key, `rename_to` and `move_to`
+/// also cover `RENAME` and `MOVE` of queues. Removed and overwritten queues are handled by
+/// the `unlink2` callback of the type, `FLUSHALL` and `FLUSHDB` by `utils::flush_db`.
+const ADD_EVENTS: [&str; 4] = ["rename_to", "move_to", "restore", "copy_to"];
+
+/// Subscribes to the keyspace and server events that change the queues of the keyspace
+/// without a valq command, so `GLOBAL_Q_REGISTRY` stays in sync with the keyspace.
+/// https://valkey.io/topics/modules-api-ref/#ValkeyModule_SubscribeToKeyspaceEvents
+pub(crate) fn subscribe(ctx: &Context) -> Status {
+    let Some(subscribe_to_keyspace_events) =
+        (unsafe { raw::RedisModule_SubscribeToKeyspaceEvents })
+    else {
+        return Status::Err;
+    };
+    let status = unsafe {
+        subscribe_to_keyspace_events(
+            ctx.ctx,
+            raw::NotifyEvent::GENERIC.bits(),
+            Some(on_keyspace_event),
+        )
+    };
+    if Status::from(status) == Status::Err {
+        return Status::Err;
+    }
+    let events: [(u64, raw::RedisModuleEventCallback); 3] = [
+        (raw::REDISMODULE_EVENT_SWAPDB, Some(on_swapdb)),
+        // RDB loads, DEBUG RELOAD and full syncs of replicas
+        (raw::REDISMODULE_EVENT_LOADING, Some(on_loading)),
+        // full syncs of replicas with `repl-diskless-load swapdb`
+        (raw::REDISMODULE_EVENT_REPL_ASYNC_LOAD, Some(on_loading)),
+    ];
+    for (id, callback) in events {
+        let event = raw::RedisModuleEvent { id, dataver: 1 };
+        if raw::subscribe_to_server_event(ctx.ctx, event, callback) == Status::Err {
+            return Status::Err;
+        }
+    }
+    Status::Ok
+}
+
+extern "C" fn on_keyspace_event(
+    ctx: *mut raw::RedisModuleCtx,
+    _type: c_int,
+    event: *const c_char,
+    key: *mut raw::RedisModuleString,
+) -> c_int {
+    if event.is_null() || key.is_null() {
+        return Status::Ok as c_int;
+    }
+    let event = unsafe { CStr::from_ptr(event) }.to_string_lossy();
+    if ADD_EVENTS.contains(&event.as_ref()) {
+        let key_name = String::from_utf8_lossy(ValkeyString::string_as_slice(key)).to_string();
+        sync_key(&Context::new(ctx), &key_name);
+    }
+    Status::Ok as c_int
+}
+
+extern "C" fn on_swapdb(
+    _ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    _subevent: u64,
+    data: *mut c_void,
+) {
+    if data.is_null() {
+        return;
+    }
+    let info = unsafe { &*data.cast::<raw::RedisModuleSwapDbInfo>() };
+    match GLOBAL_Q_REGISTRY.write() {
+        Ok(mut registry) => registry.swap_db(info.dbnum_first, info.dbnum_second),
+        Err(err) => log_notice(format!("q_registry swapdb err: {}", err)),
+    }
+}
+
+extern "C" fn on_loading(
+    ctx: *mut raw::RedisModuleCtx,
+    eid: raw::RedisModuleEvent,
+    subevent: u64,
+    _data: *mut c_void,
+) {
+    let loaded = match eid.id {
+        raw::REDISMODULE_EVENT_LOADING => subevent == raw::REDISMODULE_SUBEVENT_LOADING_ENDED,
+        _ => subevent == raw::REDISMODULE_SUBEVENT_REPL_ASYNC_LOAD_COMPLETED,
+    };
+    if loaded {
+        rebuild(&Context::new(ctx));
+    }
+}
+
+/// Registers `key` of the selected db if it holds a queue.
+fn sync_key(ctx: &Context, key_name: &str) {
+    let key = ctx.open_key(&ctx.create_string(key_name));
+    if !matches!(key.get_value::<ValqType>(&VALQ_TYPE), Ok(Some(_))) {
+        return;
+    }
+    match GLOBAL_Q_REGISTRY.write() {
+        Ok(mut registry) => {
+            registry.insert(selected_db(ctx), key_name);
+        }
+        Err(err) => log_notice(format!("q_registry sync err: {}", err)),
+    }
+}
+
+/// Replaces `GLOBAL_Q_REGISTRY` with the `valq-type` keys found by scanning every db.
+pub(crate) fn rebuild(ctx: &Context) {
+    let mut registry = QRegistry::new();
+    let selected = selected_db(ctx);
+    let mut db = 0;
+    while select_db(ctx, db) {
+        for key_name in scan_db(ctx) {
+            registry.insert(db, &key_name);
+        }
+        db += 1;
+    }
+    select_db(ctx, selected);
+    log_notice(format!(
+        "q_registry rebuild queues: {}",
+        registry.queues().len()
+    ));
+    match GLOBAL_Q_REGISTRY.write() {
+        Ok(mut tmp) => *tmp = registry,
+        Err(err) => log_notice(format!("q_registry rebuild err: {}", err)),
+    }
+}
+
+/// Key names of the queues in the selected db.
+fn scan_db(ctx: &Context) -> Vec<String> {
+    let found = RefCell::new(vec![]);
+    let cursor = KeysCursor::new();
+    while cursor.scan(ctx, &|ctx, key_name, key| {
+        let is_valq = match key {
+            Some(key) => matches!(key.get_value::<ValqType>(&VALQ_TYPE), Ok(Some(_))),
+            None => matches!(
+                ctx.open_key(&key_name).get_value::<ValqType>(&VALQ_TYPE),
+                Ok(Some(_))
+            ),
+        };
+        if is_valq {
+            found.borrow_mut().push(key_name.to_string());
+        }
+    }) {}
+    found.into_inner()
+}
+
+/// Runs `f` for every registered queue with its db selected, the selected db is restored afterwards.
+pub(crate) fn for_each_queue(ctx: &Context, mut f: impl FnMut(&ValkeyString)) {
+    let queues = match GLOBAL_Q_REGISTRY.read() {
+        Ok(registry) => registry.queues(),
+        Err(_) => vec![],
+    };
+    let selected = selected_db(ctx);
+    for (db, key_name) in queues {
+        if select_db(ctx, db) {
+            f(&ctx.create_string(key_name.as_str()));
+        }
+    }
+    select_db(ctx, selected);
+}
+
+/// Db number selected on `ctx`, 0 if the API is not available.
+pub(crate) fn selected_db(ctx: &Context) -> i32 {
+    match unsafe { raw::RedisModule_GetSelectedDb } {
+        Some(get_selected_db) => unsafe { get_selected_db(ctx.ctx) },
+        None => 0,
+    }
+}
+
+/// Selects `db` on `ctx`, returns false if it doesn't exist.
+pub(crate) fn select_db(ctx: &Context, db: i32) -> bool {
+    match unsafe { raw::RedisModule_SelectDb } {
+        Some(select_db) => Status::from(unsafe { select_db(ctx.ctx, db) }) == Status::Ok,
+        None => false,
+    }
+}
+
+/// Adds the queue stored in `key_name` of the selected db to `GLOBAL_Q_REGISTRY`.
+pub(crate) fn register(ctx: &Context, key_name: &str) -> Result<(), ValkeyError> {
+    GLOBAL_Q_REGISTRY
+        .write()?
+        .insert(selected_db(ctx), key_name);
+    Ok(())
+}
+
+/// Removes the queue stored in `key_name` of `db` from `GLOBAL_Q_REGISTRY`.
+pub(crate) fn unregister(db: i32, key_name: &str) {
+    match GLOBAL_Q_REGISTRY.write() {
+        Ok(mut registry) => {
+            registry.remove(db, key_name);
+        }
+        Err(err) => log_notice(format!("q_registry unregister err: {}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unregister() {
+        GLOBAL_Q_REGISTRY
+            .write()
+            .unwrap()
+            .insert(99, "unregister-q");
+        unregister(99, "unregister-q");
+        assert!(
+            GLOBAL_Q_REGISTRY
+                .read()
+                .unwrap()
+                .queues_in_db(99)
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::RELEASE_ON_DISCONNECT_CLIENTS;
+use crate::data_types::VALQ_TYPE;
+use crate::structs::q_event::QEvent;
+use crate::structs::valq_type::ValqType;
+use crate::utils::replication::{self, Effect};
+use crate::utils::{notify, q_registry};
+use std::os::raw::c_void;
+use valkey_module::logging::log_notice;
+use valkey_module::{Context, ContextFlags, Status, raw};
+
+/// Subscribes to the client change server event to release leases of disconnected clients.
+/// https://valkey.io/topics/modules-api-ref/#ValkeyModule_SubscribeToServerEvent
+pub(crate) fn subscribe(ctx: &Context) -> Status {
+    raw::subscribe_to_server_event(
+        ctx.ctx,
+        raw::RedisModuleEvent {
+            id: raw::REDISMODULE_EVENT_CLIENT_CHANGE,
+            dataver: 1,
+        },
+        Some(on_client_change),
+    )
+}
+
+extern "C" fn on_client_change(
+    ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    subevent: u64,
+    data: *mut c_void,
+) {
+    if subevent != raw::REDISMODULE_SUBEVENT_CLIENT_CHANGE_DISCONNECTED || data.is_null() {
+        return;
+    }
+    let client_id = unsafe { (*data.cast::<raw::RedisModuleClientInfo>()).id };
+    run(&Context::new(ctx), client_id);
+}
+
+/// Remembers the clients taking leases on `valq` if it releases them on disconnect,
+/// disconnects of other clients don't scan the queues.
+pub(crate) fn track(valq: &ValqType, client_ids: impl IntoIterator<Item = u64>) {
+    if !*valq.release_on_disconnect() {
+        return;
+    }
+    match RELEASE_ON_DISCONNECT_CLIENTS.write() {
+        Ok(mut clients) => clients.extend(client_ids),
+        Err(err) => log_notice(format!("release_on_disconnect track err: {}", err)),
+    }
+}
+
+/// Forgets the client, returns false if it holds no tracked leases.
+fn untrack(client_id: u64) -> bool {
+    match RELEASE_ON_DISCONNECT_CLIENTS.write() {
+        Ok(mut clients) => clients.remove(&client_id),
+        // scan the queues when in doubt
+        Err(_) => true,
+    }
+}
+
+fn run(ctx: &Context, client_id: u64) {
+    // replicas get the released leases from the primary
+    if ctx.get_flags().contains(ContextFlags::SLAVE) || !untrack(client_id) {
+        return;
+    }
+    q_registry::for_each_queue(ctx, |q_valkey_string| {
+        let q_key = ctx.open_key_writable(q_valkey_string);
+        let q_value = q_key.get_value::<ValqType>(&VALQ_TYPE).unwrap_or(None);
+        let released = handler(client_id, q_value);
+        notify::notify_events(ctx, q_valkey_string, &q_key);
+        replication::replicate_effects(ctx, q_valkey_string, &q_key);
+        if released > 0 {
+            log_notice(format!(
+                "release_on_disconnect q: {} client: {} msgs: {}",
+                q_valkey_string, client_id, released
+            ));
+        }
+    });
+}
+
+// make in-flight messages of the disconnected client visible again
+fn handler(client_id: u64, valq_type: Option<&mut ValqType>) -> usize {
+    match valq_type {
+        Some(tmp) if *tmp.release_on_disconnect() => {
+            let mut effects = Vec::new();
+            for msg in tmp
+                .msgs_mut()
+                .iter_mut()
+                .filter(|msg| msg.is_in_flight() && *msg.client_id() == Some(client_id))
+            {
+                msg.release();
+                effects.push(Effect::lease(msg));
+            }
+            let released = effects.len();
+            if released > 0 {
+                tmp.add_event(QEvent::Release);
+            }
+            tmp.effects_mut().extend(effects);
+            released
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::valq_type::tests::valq_with_msgs;
+
+    fn valq_with_leased_msgs() -> ValqType {
+        valq_with_msgs(&[
+            Some((None, Some(10), 0)),
+            Some((None, Some(20), 0)),
+            Some((None, Some(10), 0)),
+        ])
+    }
+
+    #[test]
+    fn track_and_untrack() {
+        let mut valq = valq_with_leased_msgs();
+        track(&valq, [101]);
+        assert!(!untrack(101));
+        valq.set_release_on_disconnect(true);
+        track(&valq, valq.msgs().iter().filter_map(|msg| *msg.client_id()));
+        assert!(untrack(10));
+        assert!(untrack(20));
+        assert!(!untrack(10));
+    }
+
+    #[test]
+    fn handler_nonexistent_queue() {
+        assert_eq!(handler(10, None), 0);
+    }
+
+    #[test]
+    fn handler_disabled() {
+        let mut valq = valq_with_leased_msgs();
+        assert_eq!(handler(10, Some(&mut valq)), 0);
+        assert!(valq.msgs().iter().all(|msg| msg.is_in_flight()));
+        assert!(valq.events().is_empty());
+    }
+
+    #[test]
+    fn handler_releases_msgs_of_client() {
+        let mut valq = valq_with_leased_msgs();
+        valq.set_release_on_disconnect(true);
+        assert_eq!(handler(10, Some(&mut valq)), 2);
+        assert_eq!(valq.effects()[0], Effect::lease(&valq.msgs()[0]));
+        assert_eq!(*valq.events(), [QEvent::Release]);
+        assert!(!valq.msgs()[0].is_in_flight());
+        assert_eq!(*valq.msgs()[0].delivery_attempts(), 0);
+        assert!(valq.msgs()[1].is_in_flight());
+        assert!(!valq.msgs()[2].is_in_flight());
+        // nothing left for the client
+        assert_eq!(handler(10, Some(&mut valq)), 0);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::data_types::VALQ_TYPE;
+use crate::data_types::aof_rewrite::restore_msg_args;
+use crate::structs::valq_msg::ValqMsg;
+use crate::structs::valq_type::ValqType;
+use crate::utils::claim_check::BodyOp;
+use valkey_module::key::ValkeyKeyWritable;
+use valkey_module::{Context, ValkeyError, ValkeyString};
+
+/// Change to a queue sent to replicas and the AOF as `valq apply` instead of the command.
+/// Commands that read the clock or pick messages queue these on `ValqType`, so replicas store
+/// the same timestamps and message ids as the primary, and send them with `replicate_effects`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Effect {
+    /// Append a new message to `msgs` or `dlq`, or add it to `delayed` with `score`.
+    Push {
+        list: String,
+        score: u64,
+        msg: ValqMsg,
+    },
+    /// Set the lease of the message with `id` in `msgs`, optional values are 0 or empty when not set.
+    Lease {
+        id: u64,
+        timeout_at: u64,
+        delivery_attempts: u64,
+        consumer: String,
+        leased_at: u64,
+    },
+    /// Move the delayed message with `id` to the front of `msgs`.
+    Promote(u64),
+    /// Move the message with `id` from `list` to the back of `dlq`, `timeout_at` starts its retention period.
+    Dlq {
+        list: String,
+        id: u64,
+        timeout_at: u64,
+    },
+    /// Remove the message with `id` from `list`.
+    Remove { list: String, id: u64 },
+    /// Set the id sequence and overflow count after a push.
+    Counters {
+        id_sequence: u64,
+        overflow_count: u64,
+    },
+}
+
+impl Effect {
+    /// Lease of `msg` as it is after a pop, claim, extend or release.
+    pub(crate) fn lease(msg: &ValqMsg) -> Self {
+        Self::Lease {
+            id: *msg.id(),
+            timeout_at: msg.timeout_at().unwrap_or(0),
+            delivery_attempts: *msg.delivery_attempts(),
+            consumer: msg.consumer().clone().unwrap_or_default(),
+            leased_at: msg.leased_at().unwrap_or(0),
+        }
+    }
+
+    /// Arguments of `valq apply <key>`, `Push` uses the message layout of `valq restore <key> MSG`.
+    pub(crate) fn args(&self) -> Vec<Vec<u8>> {
+        match self {
+            Self::Push { list, score, msg } => {
+                let mut args = restore_msg_args(list, *score, msg);
+                args[0] = b"PUSH".to_vec();
+                args
+            }
+            Self::Lease {
+                id,
+                timeout_at,
+                delivery_attempts,
+                consumer,
+                leased_at,
+            } => vec![
+                b"LEASE".to_vec(),
+                id.to_string().into_bytes(),
+                timeout_at.to_string().into_bytes(),
+                delivery_attempts.to_string().into_bytes(),
+                consumer.as_bytes().to_vec(),
+                leased_at.to_string().into_bytes(),
+            ],
+            Self::Promote(id) => vec![b"PROMOTE".to_vec(), id.to_string().into_bytes()],
+            Self::Dlq {
+                list,
+                id,
+                timeout_at,
+            } => vec![
+                b"DLQ".to_vec(),
+                list.as_bytes().to_vec(),
+                id.to_string().into_bytes(),
+                timeout_at.to_string().into_bytes(),
+            ],
+            Self::Remove { list, id } => vec![
+                b"REMOVE".to_vec(),
+                list.as_bytes().to_vec(),
+                id.to_string().into_bytes(),
+            ],
+            Self::Counters {
+                id_sequence,
+                overflow_count,
+            } => vec![
+                b"COUNTERS".to_vec(),
+                id_sequence.to_string().into_bytes(),
+                overflow_count.to_string().into_bytes(),
+            ],
+        }
+    }
+
+    /// Applies the change to the queue on a replica or while loading the AOF.
+    pub(crate) fn apply(self, valq: &mut ValqType) -> Result<(), ValkeyError> {
+        match self {
+            Self::Push { list, score, msg } => valq.add_msg(&list, score, msg)?,
+            Self::Lease {
+                id,
+                timeout_at,
+                delivery_attempts,
+                consumer,
+                leased_at,
+            } => {
+                let msg = valq
+                    .msgs_mut()
+                    .iter_mut()
+                    .find(|msg| *msg.id() == id)
+                    .ok_or_else(|| not_found(id))?;
+                msg.set_timeout_at(Some(timeout_at).filter(|&tmp| tmp > 0));
+                msg.set_delivery_attempts(delivery_attempts);
+                msg.set_consumer(Some(consumer).filter(|tmp| !tmp.is_empty()));
+                msg.set_leased_at(Some(leased_at).filter(|&tmp| tmp > 0));
+                // client IDs are local to the primary
+                msg.set_client_id(None);
+            }
+            Self::Promote(id) => {
+                let msg = valq
+                    .delayed_msgs_mut()
+                    .take(id)
+                    .ok_or_else(|| not_found(id))?;
+                valq.msgs_mut().push_front(msg);
+            }
+            Self::Dlq {
+                list,
+                id,
+                timeout_at,
+            } => {
+                let mut msg = valq.take_msg(&list, id)?;
+                msg.set_timeout_at(Some(timeout_at).filter(|&tmp| tmp > 0));
+                valq.dlq_msgs_mut().push_back(msg);
+            }
+            Self::Remove { list, id } => {
+                valq.take_msg(&list, id)?;
+            }
+            Self::Counters {
+                id_sequence,
+                overflow_count,
+            } => {
+                valq.set_id_sequence(id_sequence);
+                valq.set_overflow_count(overflow_count);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn not_found(id: u64) -> ValkeyError {
+    ValkeyError::String(format!("message not found with id {}", id))
+}
+
+/// Sends the effects queued on the queue stored in `key` to replicas and the AOF.
+/// Companion key changes are sent as `SET` and `DEL`, call this before `claim_check::apply_body_ops`.
+pub(crate) fn replicate_effects(ctx: &Context, key_arg: &ValkeyString, key: &ValkeyKeyWritable) {
+    let (effects, body_ops) = match key.get_value::<ValqType>(&VALQ_TYPE) {
+        Ok(Some(valq)) => (std::mem::take(valq.effects_mut()), valq.body_ops().clone()),
+        _ => return,
+    };
+    for effect in effects {
+        let mut args = vec![b"apply".to_vec(), key_arg.as_slice().to_vec()];
+        args.extend(effect.args());
+        let args: Vec<&Vec<u8>> = args.iter().collect();
+        ctx.replicate("valq", args.as_slice());
+    }
+    for body_op in body_ops {
+        match body_op {
+            BodyOp::Store { key, body } => ctx.replicate("SET", &[key.as_str(), body.as_str()]),
+            BodyOp::Delete(key) => ctx.replicate("DEL", &[key.as_str()]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::valq_type::tests::valq_with_msgs;
+
+    #[test]
+    fn test_apply_lease() {
+        let mut valq = valq_with_msgs(&[None, None, None]);
+        let mut msg = valq.msgs()[1].clone();
+        msg.lease(Some("worker1".to_string()), Some(7), 1000);
+        msg.set_delivery_attempts(1);
+        assert!(Effect::lease(&msg).apply(&mut valq).is_ok());
+        let test = &valq.msgs()[1];
+        assert_eq!(*test.timeout_at(), Some(1000));
+        assert_eq!(*test.delivery_attempts(), 1);
+        assert_eq!(*test.consumer(), Some("worker1".to_string()));
+        assert_eq!(*test.leased_at(), *msg.leased_at());
+        assert_eq!(*test.client_id(), None);
+        // release
+        msg.release();
+        assert!(Effect::lease(&msg).apply(&mut valq).is_ok());
+        assert!(!valq.msgs()[1].is_in_flight());
+        let msg = ValqMsg::new(9, "msg9".to_string(), None, 0);
+        assert!(Effect::lease(&msg).apply(&mut valq).is_err());
+    }
+
+    #[test]
+    fn test_apply_moves() {
+        let mut valq = valq_with_msgs(&[None, None, None]);
+        assert!(Effect::Promote(4).apply(&mut valq).is_ok());
+        assert_eq!(*valq.msgs()[0].id(), 4);
+        assert_eq!(valq.delayed_msgs().len(), 0);
+        assert!(Effect::Promote(4).apply(&mut valq).is_err());
+        let effect = Effect::Dlq {
+            list: "msgs".to_string(),
+            id: 2,
+            timeout_at: 100,
+        };
+        assert!(effect.apply(&mut valq).is_ok());
+        assert_eq!(*valq.dlq_msgs()[0].id(), 2);
+        assert_eq!(*valq.dlq_msgs()[0].timeout_at(), Some(100));
+        let effect = Effect::Remove {
+            list: "dlq".to_string(),
+            id: 2,
+        };
+        assert!(effect.apply(&mut valq).is_ok());
+        assert!(valq.dlq_msgs().is_empty());
+        let effect = Effect::Remove {
+            list: "invalid".to_string(),
+            id: 1,
+        };
+        assert!(effect.apply(&mut valq).is_err());
+        assert_eq!(valq.msgs().len(), 3);
+    }
+
+    #[test]
+    fn test_apply_push_and_counters() {
+        let mut valq = valq_with_msgs(&[None, None, None]);
+        let effect = Effect::Push {
+            list: "delayed".to_string(),
+            score: 200,
+            msg: ValqMsg::new(5, "msg5".to_string(), None, 0),
+        };
+        assert!(effect.apply(&mut valq).is_ok());
+        assert_eq!(valq.delayed_msgs().len(), 2);
+        let effect = Effect::Counters {
+            id_sequence: 5,
+            overflow_count: 1,
+        };
+        assert!(effect.apply(&mut valq).is_ok());
+        assert_eq!(*valq.id_sequence(), 5);
+        assert_eq!(*valq.overflow_count(), 1);
+    }
+}
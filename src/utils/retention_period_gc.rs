@@ -1,27 +1,33 @@
 use crate::data_types::VALQ_TYPE;
+use crate::structs::q_event::QEvent;
 use crate::structs::valq_type::ValqType;
-use crate::{GLOBAL_Q_LIST, utils};
-use valkey_module::ContextGuard;
+use crate::utils;
+use crate::utils::replication::{self, Effect};
+use crate::utils::{claim_check, notify, q_registry};
 use valkey_module::logging::log_notice;
+use valkey_module::{ContextFlags, ContextGuard};
 
 pub(crate) fn run(ctx: &ContextGuard) {
-    for q_string in get_all_queues() {
-        log_notice(format!("retention_period_gc q: {}", q_string).as_str());
-        let q_valkey_string = ctx.create_string(q_string);
-        let q_key = ctx.open_key_writable(&q_valkey_string);
+    // replicas get the removals from the primary
+    if ctx.get_flags().contains(ContextFlags::SLAVE) {
+        return;
+    }
+    q_registry::for_each_queue(ctx, |q_valkey_string| {
+        log_notice(format!("retention_period_gc q: {}", q_valkey_string).as_str());
+        let q_key = ctx.open_key_writable(q_valkey_string);
         let q_value = q_key.get_value::<ValqType>(&VALQ_TYPE).unwrap_or(None);
         handler(q_value);
-    }
-}
-
-fn get_all_queues() -> Vec<String> {
-    match GLOBAL_Q_LIST.read() {
-        Ok(guard) => guard.iter().cloned().collect(),
-        Err(_) => vec![],
-    }
+        notify::notify_events(ctx, q_valkey_string, &q_key);
+        replication::replicate_effects(ctx, q_valkey_string, &q_key);
+        if let Err(err) = claim_check::apply_body_ops(ctx, &q_key) {
+            log_notice(
+                format!("retention_period_gc q: {} error: {}", q_valkey_string, err).as_str(),
+            );
+        }
+    });
 }
 
-// loop through dlq_msgs and delete messages where timeout_at > now - RETENTION_PERIOD
+// loop through dlq_msgs and delete messages where timeout_at <= now - RETENTION_PERIOD
 fn handler(valq_type: Option<&mut ValqType>) {
     match valq_type {
         Some(tmp) => {
@@ -31,14 +37,25 @@ fn handler(valq_type: Option<&mut ValqType>) {
             for (index, msg) in dlq_msgs.iter().enumerate() {
                 // check if msg is too old
                 if msg.timeout_at().unwrap_or(0)
-                    > utils::now_as_seconds().saturating_sub(retention_period)
+                    <= utils::now_as_seconds().saturating_sub(retention_period)
                 {
                     msgs_to_remove.push(index);
                 }
             }
             // remove msgs in reverse order to avoid index shifting
+            let mut removed_msgs = vec![];
             for index in msgs_to_remove.iter().rev() {
-                dlq_msgs.remove(*index);
+                removed_msgs.extend(dlq_msgs.remove(*index));
+            }
+            if !removed_msgs.is_empty() {
+                tmp.add_event(QEvent::Expired);
+            }
+            for msg in removed_msgs.iter().rev() {
+                tmp.release_body(msg);
+                tmp.effects_mut().push(Effect::Remove {
+                    list: "dlq".to_string(),
+                    id: *msg.id(),
+                });
             }
         }
         None => {
@@ -53,16 +70,12 @@ mod tests {
     use crate::RETENTION_PERIOD_DEFAULT;
     use crate::structs::valq_msg::ValqMsg;
 
-    #[test]
-    fn test_get_all_queues() {
-        assert!(get_all_queues().is_empty());
-    }
-
     #[test]
     fn handler_empty_dlq() {
         let mut valq = ValqType::new("q", None, None, None).unwrap();
         handler(Some(&mut valq));
         assert!(valq.dlq_msgs().is_empty());
+        assert!(valq.events().is_empty());
     }
 
     #[test]
@@ -80,5 +93,14 @@ mod tests {
         assert_eq!(valq.dlq_msgs().len(), 2);
         handler(Some(&mut valq));
         assert_eq!(valq.dlq_msgs().len(), 1);
+        assert_eq!(*valq.dlq_msgs()[0].id(), 1);
+        assert_eq!(
+            *valq.effects(),
+            [Effect::Remove {
+                list: "dlq".to_string(),
+                id: 2,
+            }]
+        );
+        assert_eq!(*valq.events(), [QEvent::Expired]);
     }
 }
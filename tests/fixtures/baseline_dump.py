@@ -0,0 +1,97 @@
+#!/usr/bin/env python3
+"""Writes valq_v1.dump, a DUMP payload of a queue in the RDB layout of the first release
+(encoding version 1), field by field as its rdb_save wrote them.
+
+The layout is name, id sequence, visibility timeout, max delivery attempts, retention period,
+then the main queue, the DLQ and the delayed queue, each as a count followed by the messages,
+delayed messages prefixed by their score. A message is id, body, timeout at and delivery attempts.
+"""
+
+import pathlib
+import struct
+
+RDB_TYPE_MODULE_2 = 7
+RDB_VERSION = 11
+MODULE_OPCODE_EOF = 0
+MODULE_OPCODE_UINT = 2
+MODULE_OPCODE_STRING = 5
+CHARSET = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_"
+
+
+def crc64(data):
+    """CRC-64/Jones as used by Valkey, reflected with no final xor."""
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = (crc >> 1) ^ (0x95AC9329AC4BC9B5 if crc & 1 else 0)
+    return crc
+
+
+def length(value):
+    if value < 1 << 6:
+        return bytes([value])
+    if value < 1 << 14:
+        return bytes([0x40 | value >> 8, value & 0xFF])
+    if value <= 0xFFFFFFFF:
+        return b"\x80" + struct.pack(">I", value)
+    return b"\x81" + struct.pack(">Q", value)
+
+
+def module_id(name, encver):
+    value = 0
+    for char in name:
+        value = value << 6 | CHARSET.index(char)
+    return value << 10 | encver
+
+
+def unsigned(value):
+    return length(MODULE_OPCODE_UINT) + length(value)
+
+
+def string(value):
+    # not numeric, so the server would have saved it raw as well
+    assert not value.lstrip("-").isdigit()
+    data = value.encode()
+    return length(MODULE_OPCODE_STRING) + length(len(data)) + data
+
+
+def msg(msg_id, body, timeout_at, delivery_attempts):
+    return unsigned(msg_id) + string(body) + unsigned(timeout_at) + unsigned(delivery_attempts)
+
+
+def main():
+    assert crc64(b"123456789") == 0xE9C6D914C4B8D9CA
+    value = b"".join(
+        [
+            string("q-baseline"),
+            unsigned(4),
+            unsigned(30),
+            unsigned(3),
+            unsigned(86400),
+            # main queue, msg2 leased until 2100
+            unsigned(2),
+            msg(1, "msg1", 0, 0),
+            msg(2, "msg2", 4102444800, 1),
+            # DLQ
+            unsigned(1),
+            msg(3, "msg3", 4102444800, 3),
+            # delayed until 2100
+            unsigned(1),
+            unsigned(4102444800),
+            msg(4, "msg4", 0, 0),
+        ]
+    )
+    payload = (
+        bytes([RDB_TYPE_MODULE_2])
+        + length(module_id("valq-type", 1))
+        + value
+        + length(MODULE_OPCODE_EOF)
+        + struct.pack("<H", RDB_VERSION)
+    )
+    payload += struct.pack("<Q", crc64(payload))
+    pathlib.Path(__file__).with_name("valq_v1.dump").write_bytes(payload)
+
+
+if __name__ == "__main__":
+    main()
@@ -20,14 +20,15 @@ mod tests {
             .with_context(|| "failed to connect to valkey server")?;
 
         let test: Vec<String> = redis::cmd("valq").query(&mut con)?;
-        assert_eq!(test.len(), 12);
+        assert_eq!(test.len(), 18);
 
         let test: Vec<String> = redis::cmd("valq").arg(&["help"]).query(&mut con)?;
-        assert_eq!(test.len(), 12);
+        assert_eq!(test.len(), 18);
 
         // missing arguments
         for command in vec![
             "create", "delete", "update", "info", "purge", "push", "pop", "ack", "extend",
+            "pending", "claim", "pause", "resume", "restore", "apply",
         ] {
             let test: RedisResult<String> = redis::cmd("valq").arg(&[command]).query(&mut con);
             assert!(test.is_err());
@@ -97,22 +98,65 @@ mod tests {
         assert_eq!(
             test,
             [
+                "bytes",
+                "8",
+                "claim_check_threshold",
+                "0",
+                "compress_threshold",
+                "0",
+                "delayed_bytes",
+                "0",
                 "delayed_msgs",
                 "0",
+                "dlq_bytes",
+                "0",
                 "dlq_msgs",
                 "0",
+                "group_max_inflight",
+                "0",
                 "id_sequence",
                 "2",
+                "max_bytes",
+                "0",
                 "max_delivery_attempts",
                 "2",
+                "max_inflight",
+                "0",
+                "max_length",
+                "0",
+                "max_msg_size",
+                "0",
                 "msgs",
                 "2",
+                "msgs_bytes",
+                "8",
+                "overflow_count",
+                "0",
+                "overflow_policy",
+                "reject",
+                "paused",
+                "false",
+                "rate_limit",
+                "0",
+                "rate_per",
+                "0",
+                "release_on_disconnect",
+                "false",
                 "retention_period",
                 "300",
+                "retry_backoff_base",
+                "0",
+                "retry_backoff_jitter",
+                "0",
+                "retry_backoff_max",
+                "0",
                 "visibility_timeout",
                 "1"
             ]
         );
+        // memory usage includes the message bodies and the queue overhead
+        let test: u64 = redis::cmd("memory").arg(&["usage", "q1"]).query(&mut con)?;
+        assert!(test > 8);
         let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q1"]).query(&mut con)?;
         assert_eq!(test, ["body", "msg1", "id", "1"]);
         let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q1"]).query(&mut con)?;
@@ -125,18 +169,58 @@ mod tests {
         assert_eq!(
             test,
             [
+                "bytes",
+                "8",
+                "claim_check_threshold",
+                "0",
+                "compress_threshold",
+                "0",
+                "delayed_bytes",
+                "0",
                 "delayed_msgs",
                 "0",
+                "dlq_bytes",
+                "0",
                 "dlq_msgs",
                 "0",
+                "group_max_inflight",
+                "0",
                 "id_sequence",
                 "2",
+                "max_bytes",
+                "0",
                 "max_delivery_attempts",
                 "2",
+                "max_inflight",
+                "0",
+                "max_length",
+                "0",
+                "max_msg_size",
+                "0",
                 "msgs",
                 "2",
+                "msgs_bytes",
+                "8",
+                "overflow_count",
+                "0",
+                "overflow_policy",
+                "reject",
+                "paused",
+                "false",
+                "rate_limit",
+                "0",
+                "rate_per",
+                "0",
+                "release_on_disconnect",
+                "false",
                 "retention_period",
                 "300",
+                "retry_backoff_base",
+                "0",
+                "retry_backoff_jitter",
+                "0",
+                "retry_backoff_max",
+                "0",
                 "visibility_timeout",
                 "1"
             ]
@@ -181,18 +265,58 @@ mod tests {
         assert_eq!(
             test,
             [
+                "bytes",
+                "12",
+                "claim_check_threshold",
+                "0",
+                "compress_threshold",
+                "0",
+                "delayed_bytes",
+                "0",
                 "delayed_msgs",
                 "0",
+                "dlq_bytes",
+                "8",
                 "dlq_msgs",
                 "2",
+                "group_max_inflight",
+                "0",
                 "id_sequence",
                 "2",
+                "max_bytes",
+                "0",
                 "max_delivery_attempts",
                 "2",
+                "max_inflight",
+                "0",
+                "max_length",
+                "0",
+                "max_msg_size",
+                "0",
                 "msgs",
                 "1",
+                "msgs_bytes",
+                "4",
+                "overflow_count",
+                "0",
+                "overflow_policy",
+                "reject",
+                "paused",
+                "false",
+                "rate_limit",
+                "0",
+                "rate_per",
+                "0",
+                "release_on_disconnect",
+                "false",
                 "retention_period",
                 "300",
+                "retry_backoff_base",
+                "0",
+                "retry_backoff_jitter",
+                "0",
+                "retry_backoff_max",
+                "0",
                 "visibility_timeout",
                 "1"
             ]
@@ -254,18 +378,58 @@ mod tests {
         assert_eq!(
             test,
             [
+                "bytes",
+                "0",
+                "claim_check_threshold",
+                "0",
+                "compress_threshold",
+                "0",
+                "delayed_bytes",
+                "0",
                 "delayed_msgs",
                 "0",
+                "dlq_bytes",
+                "0",
                 "dlq_msgs",
                 "0",
+                "group_max_inflight",
+                "0",
                 "id_sequence",
                 "0",
+                "max_bytes",
+                "0",
                 "max_delivery_attempts",
                 "10",
+                "max_inflight",
+                "0",
+                "max_length",
+                "0",
+                "max_msg_size",
+                "0",
                 "msgs",
                 "0",
+                "msgs_bytes",
+                "0",
+                "overflow_count",
+                "0",
+                "overflow_policy",
+                "reject",
+                "paused",
+                "false",
+                "rate_limit",
+                "0",
+                "rate_per",
+                "0",
+                "release_on_disconnect",
+                "false",
                 "retention_period",
                 "100000",
+                "retry_backoff_base",
+                "0",
+                "retry_backoff_jitter",
+                "0",
+                "retry_backoff_max",
+                "0",
                 "visibility_timeout",
                 "10"
             ]
@@ -326,16 +490,50 @@ mod tests {
         assert_eq!(
             test,
             [
+                "bytes",
+                "4",
+                "claim_check_threshold",
+                "0",
+                "compress_threshold",
+                "0",
+                "delayed_bytes",
+                "0",
                 "delayed_msgs",
                 "0",
+                "dlq_bytes",
+                "0",
                 "dlq_msgs",
                 "0",
+                "group_max_inflight",
+                "0",
                 "id_sequence",
                 "4",
+                "max_bytes",
+                "0",
                 "max_delivery_attempts",
                 "2",
+                "max_inflight",
+                "0",
+                "max_length",
+                "0",
+                "max_msg_size",
+                "0",
                 "msgs",
                 "1",
+                "msgs_bytes",
+                "4",
+                "overflow_count",
+                "0",
+                "overflow_policy",
+                "reject",
+                "paused",
+                "false",
+                "rate_limit",
+                "0",
+                "rate_per",
+                "0",
+                "release_on_disconnect",
+                "false",
                 "visibility_timeout",
                 "1"
             ]
@@ -347,4 +545,1186 @@ mod tests {
         redis::cmd("save").exec(&mut con)?;
         Ok(())
     }
+
+    #[test]
+    #[serial]
+    fn test_valq_internal_commands() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // queue state can't be written by clients
+        for args in [
+            vec!["restore", "q", "QUEUE", "30", "3", "604800", "0", "0", "0"],
+            vec!["apply", "q", "PROMOTE", "1"],
+        ] {
+            let test: RedisResult<String> = redis::cmd("valq").arg(&args).query(&mut con);
+            assert!(
+                test.unwrap_err()
+                    .to_string()
+                    .contains("command is only accepted from the primary or the AOF")
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_retry_backoff() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // create queue with retry backoff, base 3 seconds, max 60 seconds, jitter 10 percent
+        let test: String = redis::cmd("valq")
+            .arg(&["create", "q3", "1", "2", "300", "BACKOFF", "3", "60", "10"])
+            .query(&mut con)?;
+        assert_eq!(test, "created q3");
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q3"]).query(&mut con)?;
+        assert_eq!(
+            test[46..52],
+            [
+                "retry_backoff_base",
+                "3",
+                "retry_backoff_jitter",
+                "10",
+                "retry_backoff_max",
+                "60"
+            ]
+        );
+        // update queue with invalid backoff, base greater than max
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&["update", "q3", "1", "2", "300", "BACKOFF", "10", "5", "0"])
+            .query(&mut con);
+        assert!(test.is_err());
+        // message is not redelivered until the backoff after its lease expiry has passed
+        redis::cmd("valq")
+            .arg(&["push", "q3", "msg1"])
+            .exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q3"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        thread::sleep(Duration::from_millis(1001));
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q3"]).query(&mut con)?;
+        assert_eq!(test, [""]);
+        thread::sleep(Duration::from_millis(3001));
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q3"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        redis::cmd("valq").arg(&["delete", "q3"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_msg_overrides() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // per-message max delivery attempts and visibility timeout override the queue defaults
+        redis::cmd("valq").arg(&["create", "q4"]).exec(&mut con)?;
+        let test: String = redis::cmd("valq")
+            .arg(&["push", "q4", "msg1", "MAXATTEMPTS", "1", "TIMEOUT", "1"])
+            .query(&mut con)?;
+        assert_eq!(test, "1");
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q4"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        // push with invalid override
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&["push", "q4", "msg2", "MAXATTEMPTS", "0"])
+            .query(&mut con);
+        assert!(test.is_err());
+        // pop with visibility timeout override for this lease
+        redis::cmd("valq")
+            .arg(&["push", "q4", "msg3"])
+            .exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq")
+            .arg(&["pop", "q4", "TIMEOUT", "1"])
+            .query(&mut con)?;
+        assert_eq!(test, ["body", "msg3", "id", "2"]);
+        thread::sleep(Duration::from_millis(1001));
+        // msg1 moved to dlq after 1 attempt, msg3 is visible again
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q4"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg3", "id", "2"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q4"]).query(&mut con)?;
+        assert_eq!(test[12..14], ["dlq_msgs", "1"]);
+        redis::cmd("valq").arg(&["delete", "q4"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_pending_and_claim() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // pop records the consumer, pending lists in-flight messages
+        redis::cmd("valq").arg(&["create", "q5"]).exec(&mut con)?;
+        for msg in ["msg1", "msg2"] {
+            redis::cmd("valq")
+                .arg(&["push", "q5", msg])
+                .exec(&mut con)?;
+        }
+        redis::cmd("valq")
+            .arg(&["pop", "q5", "CONSUMER", "worker1"])
+            .exec(&mut con)?;
+        redis::cmd("client")
+            .arg(&["setname", "worker2"])
+            .exec(&mut con)?;
+        redis::cmd("valq").arg(&["pop", "q5"]).exec(&mut con)?;
+        let test: Vec<Vec<String>> = redis::cmd("valq").arg(&["pending", "q5"]).query(&mut con)?;
+        assert_eq!(test.len(), 2);
+        assert_eq!(
+            test[0][0..4],
+            ["consumer", "worker1", "delivery_attempts", "1"]
+        );
+        assert_eq!(test[1][0..2], ["consumer", "worker2"]);
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["pending", "q5", "CONSUMER", "worker2"])
+            .query(&mut con)?;
+        assert_eq!(test.len(), 1);
+        assert_eq!(test[0][4..6], ["id", "2"]);
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["pending", "q5", "IDLE", "60"])
+            .query(&mut con)?;
+        assert!(test.is_empty());
+        // claim messages from another consumer that are idle for at least 0 seconds
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&[
+                "claim", "q5", "CONSUMER", "worker3", "MINIDLE", "0", "COUNT", "1",
+            ])
+            .query(&mut con)?;
+        assert_eq!(test, [["body", "msg1", "id", "1"]]);
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["pending", "q5", "CONSUMER", "worker3"])
+            .query(&mut con)?;
+        assert_eq!(
+            test[0][0..4],
+            ["consumer", "worker3", "delivery_attempts", "2"]
+        );
+        // nothing is idle long enough
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["claim", "q5", "CONSUMER", "worker3", "MINIDLE", "60"])
+            .query(&mut con)?;
+        assert!(test.is_empty());
+        // missing MINIDLE
+        let test: RedisResult<Vec<String>> = redis::cmd("valq")
+            .arg(&["claim", "q5", "CONSUMER", "worker3"])
+            .query(&mut con);
+        assert!(test.is_err());
+        redis::cmd("valq").arg(&["delete", "q5"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_release_on_disconnect() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // leases of a disconnected client are released without counting a delivery attempt
+        redis::cmd("valq")
+            .arg(&["create", "q6", "RELEASEONDISCONNECT", "yes"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["push", "q6", "msg1"])
+            .exec(&mut con)?;
+        let mut con2 = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q6"]).query(&mut con2)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q6"]).query(&mut con)?;
+        assert_eq!(test, [""]);
+        drop(con2);
+        thread::sleep(Duration::from_millis(100));
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q6"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let test: Vec<Vec<String>> = redis::cmd("valq").arg(&["pending", "q6"]).query(&mut con)?;
+        assert_eq!(test[0][2..4], ["delivery_attempts", "1"]);
+        redis::cmd("valq").arg(&["delete", "q6"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_max_inflight() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // max inflight
+        redis::cmd("valq")
+            .arg(&["create", "q7", "MAXINFLIGHT", "1"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["push", "q7", "msg1"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["push", "q7", "msg2"])
+            .exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q7"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q7"]).query(&mut con)?;
+        assert_eq!(test, [""]);
+        redis::cmd("valq").arg(&["ack", "q7", "1"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q7"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg2", "id", "2"]);
+        // a failing update changes nothing
+        let info: Vec<String> = redis::cmd("valq").arg(&["info", "q7"]).query(&mut con)?;
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&[
+                "update",
+                "q7",
+                "10",
+                "2",
+                "300",
+                "MAXINFLIGHT",
+                "5",
+                "COMPRESS",
+                "-1",
+            ])
+            .query(&mut con);
+        assert!(test.is_err());
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&["update", "q7", "10", "2", "300", "MAXINFLIGHT", "100001"])
+            .query(&mut con);
+        assert!(test.is_err());
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q7"]).query(&mut con)?;
+        assert_eq!(test, info);
+        redis::cmd("valq").arg(&["delete", "q7"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_group_max_inflight() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // group max inflight
+        redis::cmd("valq")
+            .arg(&["create", "q8", "GROUPMAXINFLIGHT", "1"])
+            .exec(&mut con)?;
+        for (msg, group) in [("msg1", "t1"), ("msg2", "t1"), ("msg3", "t2")] {
+            redis::cmd("valq")
+                .arg(&["push", "q8", msg, "GROUP", group])
+                .exec(&mut con)?;
+        }
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q8"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q8"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg3", "id", "3"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q8"]).query(&mut con)?;
+        assert_eq!(test, [""]);
+        redis::cmd("valq").arg(&["ack", "q8", "1"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q8"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg2", "id", "2"]);
+        redis::cmd("valq").arg(&["delete", "q8"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_rate_limit() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // rate limit
+        redis::cmd("valq")
+            .arg(&["create", "q9", "RATE", "1", "PER", "60"])
+            .exec(&mut con)?;
+        for msg in ["msg1", "msg2"] {
+            redis::cmd("valq")
+                .arg(&["push", "q9", msg])
+                .exec(&mut con)?;
+        }
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q9"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q9"]).query(&mut con)?;
+        assert_eq!(test, ["retry_after", "60"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q9"]).query(&mut con)?;
+        assert_eq!(test[38..42], ["rate_limit", "1", "rate_per", "60"]);
+        redis::cmd("valq").arg(&["delete", "q9"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_pause() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // pause and resume
+        redis::cmd("valq").arg(&["create", "q10"]).exec(&mut con)?;
+        let test: String = redis::cmd("valq").arg(&["pause", "q10"]).query(&mut con)?;
+        assert_eq!(test, "OK");
+        let test: String = redis::cmd("valq")
+            .arg(&["push", "q10", "msg1"])
+            .query(&mut con)?;
+        assert_eq!(test, "1");
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q10"]).query(&mut con)?;
+        assert_eq!(test, [""]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q10"]).query(&mut con)?;
+        assert_eq!(test[36..38], ["paused", "true"]);
+        redis::cmd("valq").arg(&["resume", "q10"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q10"]).query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        redis::cmd("valq").arg(&["delete", "q10"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_max_length() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // max length with overflow policy
+        redis::cmd("valq")
+            .arg(&["create", "q11", "MAXLENGTH", "1"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["push", "q11", "msg1"])
+            .exec(&mut con)?;
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&["push", "q11", "msg2"])
+            .query(&mut con);
+        assert!(test.is_err());
+        redis::cmd("valq")
+            .arg(&["update", "q11", "30", "5", "86400", "OVERFLOW", "dlq"])
+            .exec(&mut con)?;
+        let test: String = redis::cmd("valq")
+            .arg(&["push", "q11", "msg3"])
+            .query(&mut con)?;
+        assert_eq!(test, "2");
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q11"]).query(&mut con)?;
+        assert_eq!(test[12..14], ["dlq_msgs", "1"]);
+        assert_eq!(
+            test[32..36],
+            ["overflow_count", "2", "overflow_policy", "dlq"]
+        );
+        redis::cmd("valq").arg(&["delete", "q11"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_max_msg_size() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // max message size
+        redis::cmd("valq")
+            .arg(&["create", "q12", "MAXMSGSIZE", "4"])
+            .exec(&mut con)?;
+        let test: String = redis::cmd("valq")
+            .arg(&["push", "q12", "msg1"])
+            .query(&mut con)?;
+        assert_eq!(test, "1");
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&["push", "q12", "msg10"])
+            .query(&mut con);
+        assert!(test.unwrap_err().to_string().contains("message too large"));
+        redis::cmd("valq").arg(&["delete", "q12"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_claim_check() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // claim check
+        redis::cmd("valq")
+            .arg(&["create", "q13", "CLAIMCHECK", "4"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["push", "q13", "large msg1"])
+            .exec(&mut con)?;
+        let test: String = redis::cmd("get").arg(&["{q13}:body:1"]).query(&mut con)?;
+        assert_eq!(test, "large msg1");
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q13"]).query(&mut con)?;
+        assert_eq!(test[0..4], ["bytes", "10", "claim_check_threshold", "4"]);
+        // the copy would share the companion keys
+        let test: RedisResult<i64> = redis::cmd("copy").arg(&["q13", "q13-copy"]).query(&mut con);
+        assert!(test.is_err());
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q13"]).query(&mut con)?;
+        assert_eq!(test, ["body", "large msg1", "id", "1"]);
+        redis::cmd("valq")
+            .arg(&["ack", "q13", "1"])
+            .exec(&mut con)?;
+        let test: i64 = redis::cmd("exists")
+            .arg(&["{q13}:body:1"])
+            .query(&mut con)?;
+        assert_eq!(test, 0);
+        // a missing companion key is an error instead of an empty body
+        redis::cmd("valq")
+            .arg(&["push", "q13", "large msg3"])
+            .exec(&mut con)?;
+        redis::cmd("del").arg(&["{q13}:body:2"]).exec(&mut con)?;
+        let test: RedisResult<Vec<String>> =
+            redis::cmd("valq").arg(&["pop", "q13"]).query(&mut con);
+        assert!(
+            test.unwrap_err()
+                .to_string()
+                .contains("message 2 body key missing")
+        );
+        redis::cmd("valq")
+            .arg(&["ack", "q13", "2"])
+            .exec(&mut con)?;
+        // companion keys of other types are never overwritten
+        redis::cmd("hset")
+            .arg(&["{q13}:body:3", "field", "value"])
+            .exec(&mut con)?;
+        let test: RedisResult<String> = redis::cmd("valq")
+            .arg(&["push", "q13", "large msg2"])
+            .query(&mut con);
+        assert!(
+            test.unwrap_err()
+                .to_string()
+                .contains("companion key {q13}:body:3 holds another type")
+        );
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q13"]).query(&mut con)?;
+        assert_eq!(test[0..2], ["bytes", "0"]);
+        redis::cmd("del").arg(&["{q13}:body:3"]).exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["push", "q13", "large msg2"])
+            .exec(&mut con)?;
+        // rename keeps the companion keys, del removes them
+        redis::cmd("rename")
+            .arg(&["q13", "{q13}-renamed"])
+            .exec(&mut con)?;
+        let test: i64 = redis::cmd("exists")
+            .arg(&["{q13}:body:3"])
+            .query(&mut con)?;
+        assert_eq!(test, 1);
+        redis::cmd("del").arg(&["{q13}-renamed"]).exec(&mut con)?;
+        let test: i64 = redis::cmd("exists")
+            .arg(&["{q13}:body:3"])
+            .query(&mut con)?;
+        assert_eq!(test, 0);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_copy() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // copy keeps or resets leases
+        redis::cmd("valq").arg(&["create", "q15"]).exec(&mut con)?;
+        for msg in ["msg1", "msg2"] {
+            redis::cmd("valq")
+                .arg(&["push", "q15", msg])
+                .exec(&mut con)?;
+        }
+        redis::cmd("valq")
+            .arg(&["pop", "q15", "CONSUMER", "worker1"])
+            .exec(&mut con)?;
+        let test: i64 = redis::cmd("copy")
+            .arg(&["q15", "q15-copy"])
+            .query(&mut con)?;
+        assert_eq!(test, 1);
+        let test: Vec<String> = redis::cmd("valq")
+            .arg(&["info", "q15-copy"])
+            .query(&mut con)?;
+        let q15_info: Vec<String> = redis::cmd("valq").arg(&["info", "q15"]).query(&mut con)?;
+        assert_eq!(test, q15_info);
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["pending", "q15-copy"])
+            .query(&mut con)?;
+        assert_eq!(test.len(), 1);
+        redis::cmd("config")
+            .arg(&["set", "valq.copy-reset-leases", "yes"])
+            .exec(&mut con)?;
+        redis::cmd("copy")
+            .arg(&["q15", "q15-copy", "REPLACE"])
+            .exec(&mut con)?;
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["pending", "q15-copy"])
+            .query(&mut con)?;
+        assert_eq!(test.len(), 0);
+        let test: Vec<Vec<String>> = redis::cmd("valq")
+            .arg(&["pending", "q15"])
+            .query(&mut con)?;
+        assert_eq!(test.len(), 1);
+        redis::cmd("config")
+            .arg(&["set", "valq.copy-reset-leases", "no"])
+            .exec(&mut con)?;
+        for q in ["q15", "q15-copy"] {
+            redis::cmd("valq").arg(&["delete", q]).exec(&mut con)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_compression() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // compression
+        redis::cmd("valq")
+            .arg(&["create", "q14", "COMPRESS", "16"])
+            .exec(&mut con)?;
+        let body = "{\"status\":\"ok\"}".repeat(10);
+        redis::cmd("valq")
+            .arg(&["push", "q14", body.as_str()])
+            .exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q14"]).query(&mut con)?;
+        assert_eq!(test[4..6], ["compress_threshold", "16"]);
+        assert!(test[1].parse::<usize>()? < body.len());
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q14"]).query(&mut con)?;
+        assert_eq!(test, ["body", body.as_str(), "id", "1"]);
+        redis::cmd("valq").arg(&["delete", "q14"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_lazy_free() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // lazy free
+        redis::cmd("valq").arg(&["create", "q16"]).exec(&mut con)?;
+        for i in 0..1000 {
+            redis::cmd("valq")
+                .arg(&["push", "q16", format!("msg{}", i).as_str()])
+                .exec(&mut con)?;
+        }
+        let test: i64 = redis::cmd("unlink").arg(&["q16"]).query(&mut con)?;
+        assert_eq!(test, 1);
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert!(!test.contains(&"q16".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_registry() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // queue registry follows the keyspace
+        redis::cmd("valq").arg(&["create", "q17"]).exec(&mut con)?;
+        redis::cmd("rename").arg(&["q17", "q18"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert!(test.contains(&"q18".to_string()));
+        assert!(!test.contains(&"q17".to_string()));
+        redis::cmd("copy").arg(&["q18", "q19"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert!(test.contains(&"q19".to_string()));
+        redis::cmd("set")
+            .arg(&["q19", "not a queue"])
+            .exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert!(!test.contains(&"q19".to_string()));
+        redis::cmd("del").arg(&["q19"]).exec(&mut con)?;
+        redis::cmd("move").arg(&["q18", "1"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert!(!test.contains(&"q18".to_string()));
+        redis::cmd("select").arg(&["1"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert_eq!(test, ["q18"]);
+        redis::cmd("select").arg(&["0"]).exec(&mut con)?;
+        redis::cmd("swapdb").arg(&["0", "1"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert_eq!(test, ["q18"]);
+        redis::cmd("swapdb").arg(&["0", "1"]).exec(&mut con)?;
+        redis::cmd("select").arg(&["1"]).exec(&mut con)?;
+        redis::cmd("del").arg(&["q18"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert!(test.is_empty());
+        redis::cmd("select").arg(&["0"]).exec(&mut con)?;
+        // rebuilt by scanning the keyspace after loading
+        for q in ["q1", "q2"] {
+            redis::cmd("valq").arg(&["create", q]).exec(&mut con)?;
+        }
+        redis::cmd("debug").arg(&["reload"]).exec(&mut con)?;
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert_eq!(test, ["q1", "q2"]);
+
+        // flushall also saves the empty dataset for the next server
+        redis::cmd("flushall").exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_keyspace_events() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // keyspace notifications
+        redis::cmd("config")
+            .arg(&["set", "notify-keyspace-events", "Kd"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["create", "q20", "30", "1"])
+            .exec(&mut con)?;
+        let mut sub_con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+        let mut pubsub = sub_con.as_pubsub();
+        pubsub.set_read_timeout(Some(Duration::from_secs(1)))?;
+        pubsub.subscribe("__keyspace@0__:q20")?;
+        redis::cmd("valq")
+            .arg(&["push", "q20", "msg1"])
+            .exec(&mut con)?;
+        redis::cmd("valq").arg(&["pop", "q20"]).exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["extend", "q20", "1", "30"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["ack", "q20", "1"])
+            .exec(&mut con)?;
+        let mut test = vec![];
+        for _ in 0..4 {
+            test.push(pubsub.get_message()?.get_payload::<String>()?);
+        }
+        assert_eq!(test, ["valq.push", "valq.pop", "valq.extend", "valq.ack"]);
+        drop(pubsub);
+        redis::cmd("config")
+            .arg(&["set", "notify-keyspace-events", ""])
+            .exec(&mut con)?;
+        redis::cmd("valq").arg(&["delete", "q20"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_subcommands() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // valq.<subcommand> commands
+        redis::cmd("valq.create")
+            .arg(&["q21", "30", "1"])
+            .exec(&mut con)?;
+        let test: u64 = redis::cmd("valq.push")
+            .arg(&["q21", "msg1"])
+            .query(&mut con)?;
+        assert_eq!(test, 1);
+        let test: Vec<String> = redis::cmd("valq.pop").arg(&["q21"]).query(&mut con)?;
+        assert_eq!(test[1], "msg1");
+        let test: String = redis::cmd("valq.ack").arg(&["q21", "1"]).query(&mut con)?;
+        assert_eq!(test, "ack 1");
+        let test: RedisResult<String> = redis::cmd("valq.push").query(&mut con);
+        assert!(test.is_err());
+        let test: Vec<String> = redis::cmd("valq.help").query(&mut con)?;
+        assert_eq!(test.len(), 18);
+        // key positions for cluster routing and ACL key patterns
+        let test: Vec<String> = redis::cmd("command")
+            .arg(&["getkeys", "valq.push", "q21", "msg1"])
+            .query(&mut con)?;
+        assert_eq!(test, ["q21"]);
+        let test: Vec<String> = redis::cmd("command")
+            .arg(&["getkeys", "valq", "push", "q21", "msg1"])
+            .query(&mut con)?;
+        assert_eq!(test, ["q21"]);
+        let test: RedisResult<Vec<String>> = redis::cmd("command")
+            .arg(&["getkeys", "valq.list"])
+            .query(&mut con);
+        assert!(test.is_err());
+        let test: redis::Value = redis::cmd("command")
+            .arg(&["info", "valq.info"])
+            .query(&mut con)?;
+        assert!(format!("{:?}", test).contains("readonly"));
+        // the valq alias is a write command
+        let test: RedisResult<u64> = redis::cmd("eval_ro")
+            .arg(&[
+                "return server.call('valq', 'push', KEYS[1], 'msg2')",
+                "1",
+                "q21",
+            ])
+            .query(&mut con);
+        assert!(test.is_err());
+        let test: u64 = redis::cmd("eval_ro")
+            .arg(&["return #server.call('valq.info', KEYS[1])", "1", "q21"])
+            .query(&mut con)?;
+        assert!(test > 0);
+        redis::cmd("client")
+            .arg(&["pause", "1000", "write"])
+            .exec(&mut con)?;
+        let start = std::time::Instant::now();
+        let _: redis::Value = redis::cmd("valq.info").arg(&["q21"]).query(&mut con)?;
+        assert!(start.elapsed() < Duration::from_millis(500));
+        // blocked until the pause ends
+        let test: u64 = redis::cmd("valq")
+            .arg(&["push", "q21", "msg2"])
+            .query(&mut con)?;
+        assert_eq!(test, 2);
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        redis::cmd("valq.delete").arg(&["q21"]).exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_configs() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        let test: Vec<String> = redis::cmd("config")
+            .arg(&["get", "valq.max-msg-size"])
+            .query(&mut con)?;
+        assert_eq!(test, ["valq.max-msg-size", "536870912"]);
+        let test: Vec<String> = redis::cmd("config")
+            .arg(&["get", "valq.rdb-load-salvage"])
+            .query(&mut con)?;
+        assert_eq!(test, ["valq.rdb-load-salvage", "no"]);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_aof() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let dir = std::env::temp_dir().join("valq-aof");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let dir = dir.to_string_lossy().to_string();
+        // AOF only, without the RDB preamble so the rewrite goes through aof_rewrite
+        let args = [
+            "--dir",
+            dir.as_str(),
+            "--appendonly",
+            "yes",
+            "--aof-use-rdb-preamble",
+            "no",
+            "--save",
+            "",
+        ];
+        let guard = utils::start_server_with_module_args("valq", port, &args)
+            .with_context(|| "failed to start valkey server")?;
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        redis::cmd("valq")
+            .arg(&[
+                "create",
+                "q1",
+                "10",
+                "1",
+                "300",
+                "MAXINFLIGHT",
+                "5",
+                "COMPRESS",
+                "16",
+            ])
+            .exec(&mut con)?;
+        let body = "{\"status\":\"ok\"}".repeat(10);
+        for msg in ["msg1", body.as_str(), "msg3"] {
+            redis::cmd("valq")
+                .arg(&["push", "q1", msg])
+                .exec(&mut con)?;
+        }
+        redis::cmd("valq")
+            .arg(&["push", "q1", "msg4", "3600"])
+            .exec(&mut con)?;
+        // lease msg1
+        let test: Vec<String> = redis::cmd("valq")
+            .arg(&["pop", "q1", "CONSUMER", "worker1"])
+            .query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let before: Vec<String> = redis::cmd("valq").arg(&["info", "q1"]).query(&mut con)?;
+
+        redis::cmd("bgrewriteaof").exec(&mut con)?;
+        loop {
+            let info: String = redis::cmd("info").arg(&["persistence"]).query(&mut con)?;
+            if info.contains("aof_rewrite_in_progress:0")
+                && info.contains("aof_rewrite_scheduled:0")
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        drop(guard);
+
+        let _guard = utils::start_server_with_module_args("valq", port, &args)
+            .with_context(|| "failed to restart valkey server")?;
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+        let after: Vec<String> = redis::cmd("valq").arg(&["info", "q1"]).query(&mut con)?;
+        assert_eq!(before, after);
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert_eq!(test, ["q1"]);
+        // msg1 is still leased by worker1
+        let test: Vec<Vec<String>> = redis::cmd("valq").arg(&["pending", "q1"]).query(&mut con)?;
+        assert_eq!(test.len(), 1);
+        assert_eq!(test[0][0..2], ["consumer", "worker1"]);
+        let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q1"]).query(&mut con)?;
+        assert_eq!(test, ["body", body.as_str(), "id", "2"]);
+        // id sequence continues after the restored messages
+        let test: String = redis::cmd("valq")
+            .arg(&["push", "q1", "msg5"])
+            .query(&mut con)?;
+        assert_eq!(test, "5");
+
+        redis::cmd("flushall").exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_load_corrupt() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let dir = std::env::temp_dir().join("valq-load-corrupt");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let logfile = dir.join("valkey.log").to_string_lossy().to_string();
+        let _guard =
+            utils::start_server_with_module_args("valq", port, &["--logfile", logfile.as_str()])
+                .with_context(|| "failed to start valkey server")?;
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        redis::cmd("valq").arg(&["create", "q1"]).exec(&mut con)?;
+        for id in 1..=20 {
+            redis::cmd("valq")
+                .arg(&["push", "q1", format!("msg{}", id).as_str()])
+                .exec(&mut con)?;
+        }
+        let payload: Vec<u8> = redis::cmd("dump").arg(&["q1"]).query(&mut con)?;
+        // cut the queue data in half and keep the version and checksum trailer,
+        // checksums are skipped so the truncated data reaches rdb_load
+        let (data, trailer) = payload.split_at(payload.len() - 10);
+        let truncated = [&data[..data.len() / 2], trailer].concat();
+        redis::cmd("debug")
+            .arg(&["set-skip-checksum-validation", "1"])
+            .exec(&mut con)?;
+        let test: RedisResult<String> = redis::cmd("restore")
+            .arg("q2")
+            .arg(0)
+            .arg(truncated)
+            .query(&mut con);
+        assert!(test.is_err());
+        let test: i64 = redis::cmd("exists").arg(&["q2"]).query(&mut con)?;
+        assert_eq!(test, 0);
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert_eq!(test, ["q1"]);
+        // the failed load is logged as "rdb_load <key>: <error>"
+        let log = std::fs::read_to_string(&logfile)?;
+        assert!(log.contains("rdb_load "));
+
+        redis::cmd("flushall").exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_load_v1_dump() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // queue in the encoding version 1 layout of the first release, see fixtures/baseline_dump.py
+        let payload = include_bytes!("fixtures/valq_v1.dump").to_vec();
+        redis::cmd("restore")
+            .arg("q-baseline")
+            .arg(0)
+            .arg(payload)
+            .exec(&mut con)?;
+        let info: Vec<String> = redis::cmd("valq")
+            .arg(&["info", "q-baseline"])
+            .query(&mut con)?;
+        let field = |name: &str| {
+            info.chunks(2)
+                .find(|pair| pair[0] == name)
+                .map(|pair| pair[1].clone())
+        };
+        for (name, value) in [
+            ("id_sequence", "4"),
+            ("visibility_timeout", "30"),
+            ("max_delivery_attempts", "3"),
+            ("retention_period", "86400"),
+            ("msgs", "2"),
+            ("dlq_msgs", "1"),
+            ("delayed_msgs", "1"),
+            ("max_inflight", "0"),
+            ("overflow_policy", "reject"),
+        ] {
+            assert_eq!(field(name).as_deref(), Some(value));
+        }
+        let test: Vec<String> = redis::cmd("valq").arg(&["list"]).query(&mut con)?;
+        assert_eq!(test, ["q-baseline"]);
+        // msg2 is leased until 2100 and msg4 delayed until then
+        let test: Vec<String> = redis::cmd("valq")
+            .arg(&["pop", "q-baseline"])
+            .query(&mut con)?;
+        assert_eq!(test, ["body", "msg1", "id", "1"]);
+        let test: Vec<String> = redis::cmd("valq")
+            .arg(&["pop", "q-baseline"])
+            .query(&mut con)?;
+        assert_eq!(test, [""]);
+        let test: String = redis::cmd("valq")
+            .arg(&["push", "q-baseline", "msg5"])
+            .query(&mut con)?;
+        assert_eq!(test, "5");
+
+        redis::cmd("flushall").exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_digest() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let replica_port: u16 = 6480;
+        let primary_port = port.to_string();
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+            utils::start_server_with_module_args(
+                "valq",
+                replica_port,
+                &["--replicaof", "127.0.0.1", primary_port.as_str()],
+            )
+            .with_context(|| "failed to start valkey replica")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+        let mut replica_con = utils::get_server_connection(replica_port)
+            .with_context(|| "failed to connect to valkey replica")?;
+        loop {
+            let info: String = redis::cmd("info")
+                .arg(&["replication"])
+                .query(&mut replica_con)?;
+            if info.contains("master_link_status:up") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        // workload touching the main queue, DLQ, delayed queue and leases
+        redis::cmd("valq")
+            .arg(&["create", "q1", "1", "1", "60", "COMPRESS", "16"])
+            .exec(&mut con)?;
+        let body = "{\"status\":\"ok\"}".repeat(10);
+        for msg in ["msg1", "msg2", body.as_str()] {
+            redis::cmd("valq")
+                .arg(&["push", "q1", msg])
+                .exec(&mut con)?;
+        }
+        redis::cmd("valq")
+            .arg(&["push", "q1", "msg4", "3600"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["pop", "q1", "CONSUMER", "worker1"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["pop", "q1", "CONSUMER", "worker1"])
+            .exec(&mut con)?;
+        redis::cmd("valq").arg(&["ack", "q1", "2"]).exec(&mut con)?;
+        // msg1 moves to the DLQ after its only delivery attempt times out
+        thread::sleep(Duration::from_millis(1001));
+        // an explicit consumer, the default is the name or id of the client which differs on the replica
+        redis::cmd("valq")
+            .arg(&["pop", "q1", "CONSUMER", "worker2"])
+            .exec(&mut con)?;
+        redis::cmd("valq")
+            .arg(&["extend", "q1", "3", "30"])
+            .exec(&mut con)?;
+        let test: i64 = redis::cmd("wait").arg(&["1", "1000"]).query(&mut con)?;
+        assert_eq!(test, 1);
+
+        let test: Vec<String> = redis::cmd("debug")
+            .arg(&["digest-value", "q1"])
+            .query(&mut con)?;
+        let replica_test: Vec<String> = redis::cmd("debug")
+            .arg(&["digest-value", "q1"])
+            .query(&mut replica_con)?;
+        assert_eq!(test, replica_test);
+        assert_ne!(test[0], "0".repeat(40));
+        let test: String = redis::cmd("debug").arg(&["digest"]).query(&mut con)?;
+        let replica_test: String = redis::cmd("debug")
+            .arg(&["digest"])
+            .query(&mut replica_con)?;
+        assert_eq!(test, replica_test);
+        // leases carry the timestamps of the primary, the valq alias is a write command on replicas
+        let test: Vec<Vec<String>> = redis::cmd("valq.pending").arg(&["q1"]).query(&mut con)?;
+        let replica_test: Vec<Vec<String>> = redis::cmd("valq.pending")
+            .arg(&["q1"])
+            .query(&mut replica_con)?;
+        assert_eq!(test.len(), 1);
+        // idle is relative to the clock of each node
+        assert_eq!(test[0][0..8], replica_test[0][0..8]);
+
+        redis::cmd("flushall").exec(&mut con)?;
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_valq_defrag() -> anyhow::Result<()> {
+        let port: u16 = 6479;
+        let _guards = vec![
+            utils::start_server_with_module("valq", port)
+                .with_context(|| "failed to start valkey server")?,
+        ];
+        let mut con = utils::get_server_connection(port)
+            .with_context(|| "failed to connect to valkey server")?;
+
+        // main, DLQ and delayed messages, the overflow moves the oldest ones to the DLQ
+        redis::cmd("valq")
+            .arg(&[
+                "create",
+                "q1",
+                "MAXLENGTH",
+                "4",
+                "OVERFLOW",
+                "dlq",
+                "COMPRESS",
+                "16",
+            ])
+            .exec(&mut con)?;
+        let body = "{\"status\":\"ok\"}".repeat(10);
+        for msg in ["msg1", "msg2", body.as_str(), "msg4", "msg5", "msg6"] {
+            redis::cmd("valq")
+                .arg(&["push", "q1", msg])
+                .exec(&mut con)?;
+        }
+        for msg in ["msg7", "msg8"] {
+            redis::cmd("valq")
+                .arg(&["push", "q1", msg, "3600"])
+                .exec(&mut con)?;
+        }
+        let digest: Vec<String> = redis::cmd("debug")
+            .arg(&["digest-value", "q1"])
+            .query(&mut con)?;
+        let info: Vec<String> = redis::cmd("valq").arg(&["info", "q1"]).query(&mut con)?;
+        assert_eq!(info[12..14], ["dlq_msgs", "4"]);
+
+        // skipped when the server is not built with jemalloc
+        for (name, value) in [
+            ("active-defrag-ignore-bytes", "1"),
+            ("active-defrag-threshold-lower", "0"),
+            ("active-defrag-cycle-max", "99"),
+            ("active-defrag-cycle-min", "99"),
+        ] {
+            redis::cmd("config")
+                .arg(&["set", name, value])
+                .exec(&mut con)?;
+        }
+        let test: RedisResult<()> = redis::cmd("config")
+            .arg(&["set", "activedefrag", "yes"])
+            .query(&mut con);
+        if test.is_err() {
+            redis::cmd("flushall").exec(&mut con)?;
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(2));
+        redis::cmd("config")
+            .arg(&["set", "activedefrag", "no"])
+            .exec(&mut con)?;
+
+        let test: Vec<String> = redis::cmd("debug")
+            .arg(&["digest-value", "q1"])
+            .query(&mut con)?;
+        assert_eq!(test, digest);
+        let test: Vec<String> = redis::cmd("valq").arg(&["info", "q1"]).query(&mut con)?;
+        assert_eq!(test, info);
+        for (id, msg) in [("5", "msg5"), ("6", "msg6")] {
+            let test: Vec<String> = redis::cmd("valq").arg(&["pop", "q1"]).query(&mut con)?;
+            assert_eq!(test, ["body", msg, "id", id]);
+        }
+        redis::cmd("flushall").exec(&mut con)?;
+        Ok(())
+    }
 }
@@ -23,6 +23,14 @@ impl Drop for ChildGuard {
 }
 
 pub(crate) fn start_server_with_module(module_name: &str, port: u16) -> anyhow::Result<ChildGuard> {
+    start_server_with_module_args(module_name, port, &[])
+}
+
+pub(crate) fn start_server_with_module_args(
+    module_name: &str,
+    port: u16,
+    extra_args: &[&str],
+) -> anyhow::Result<ChildGuard> {
     let module_path = get_module_path(module_name)?;
 
     let args = &[
@@ -38,6 +46,7 @@ pub(crate) fn start_server_with_module(module_name: &str, port: u16) -> anyhow::
 
     let server = Command::new("valkey-server")
         .args(args)
+        .args(extra_args)
         .spawn()
         .map(|c| ChildGuard {
             name: "server",